@@ -0,0 +1,207 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::data::data_saving::{Record, read_from_file};
+use super::company::{self, Company, DEFAULT_SECTOR, DEFAULT_SHARES_OUTSTANDING};
+use super::price_model::{self, PriceModel};
+use super::company_manager::name_key;
+
+/// How many ticks of history companies get before a new game starts, unless the catalogue says otherwise
+const DEFAULT_WARMUP_TICKS : usize = 50;
+
+/// The catalogue file, as it is written
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogueFile {
+    #[serde(default = "default_warmup_ticks")]
+    warmup_ticks : usize,
+    companies : Vec<CatalogueEntry>,
+}
+
+fn default_warmup_ticks() -> usize {
+    DEFAULT_WARMUP_TICKS
+}
+
+fn default_sector() -> String {
+    String::from(DEFAULT_SECTOR)
+}
+
+fn default_shares() -> usize {
+    DEFAULT_SHARES_OUTSTANDING
+}
+
+/// A company of the catalogue
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogueEntry {
+    name : String,
+    ticker : String,
+    #[serde(default = "default_sector")]
+    sector : String,
+    /// The price the company starts every season at
+    price : f32,
+    /// The float, how many shares the company has
+    #[serde(default = "default_shares")]
+    shares : usize,
+    /// Left out to use the liquidity in the server settings
+    liquidity : Option<usize>,
+    /// Left out to use the default price model
+    model : Option<ModelEntry>,
+}
+
+/// The price model of a company, its name and parameters
+#[derive(Deserialize)]
+struct ModelEntry {
+    name : String,
+    #[serde(flatten)]
+    parameters : BTreeMap<String, f32>,
+}
+
+impl CatalogueEntry {
+    /// Gets the name of the company
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Gets the ticker of the company
+    pub fn ticker(&self) -> &String {
+        &self.ticker
+    }
+
+    /// Gets the price the company starts at
+    pub fn price(&self) -> f32 {
+        self.price
+    }
+
+    /// Makes the price model of the company
+    fn price_model(&self) -> Result<Box<dyn PriceModel>, String> {
+        let model = match &self.model {
+            Some(model) => model,
+            None => return Ok(price_model::default_price_model()),
+        };
+
+        let mut record = Record::new("model");
+        for (name, value) in model.parameters.iter() {
+            record = record.with(name, value);
+        }
+        let price_model = match price_model::load_price_model(&model.name, &record) {
+            Ok(price_model) => price_model,
+            Err(error) => return Err(error),
+        };
+
+        //A misspelled parameter would otherwise quietly get its default
+        let known : Vec<&'static str> = price_model.parameters().iter().map(|(name, _value)| *name).collect();
+        match model.parameters.keys().find(|name| !known.contains(&name.as_str())) {
+            Some(unknown) => Err(format!("The {} model has no parameter '{}', it has {}", model.name, unknown, known.join(", "))),
+            None => Ok(price_model),
+        }
+    }
+
+    /// Checks the entry makes a working company, giving every problem found
+    fn validate(&self) -> Vec<String> {
+        let mut problems : Vec<String> = Vec::new();
+
+        //Names are written in the comma separated stock data
+        if self.name.trim().is_empty() || self.name.contains(',') || self.name.contains('\n') {
+            problems.push(String::from("the name can't be empty or have commas or new lines"));
+        }
+        if let Err(error) = company::check_ticker(&self.ticker) {
+            problems.push(error);
+        }
+        if self.sector.trim().is_empty() {
+            problems.push(String::from("the sector can't be empty"));
+        }
+        if !self.price.is_finite() || self.price <= 0.0 {
+            problems.push(format!("the price should be above 0, found {}", self.price));
+        }
+        if self.shares == 0 {
+            problems.push(String::from("the company should have at least 1 share"));
+        }
+        if let Err(error) = self.price_model() {
+            problems.push(error);
+        }
+
+        problems
+    }
+
+    /// Sets up a new or reset company as the entry describes, all but its ticker
+    /// Liquidity is used when the entry doesn't have its own
+    pub fn configure(&self, company : &mut Company, liquidity : usize) -> Result<(), String> {
+        let price_model = match self.price_model() {
+            Ok(price_model) => price_model,
+            Err(error) => return Err(error),
+        };
+
+        company.set_sector(self.sector.clone());
+        company.set_shares_outstanding(self.shares);
+        company.set_liquidity(self.liquidity.unwrap_or(liquidity));
+        company.set_price_model(price_model);
+        Ok(())
+    }
+}
+
+/// The companies a game is played with, read from a config file
+pub struct Catalogue {
+    warmup_ticks : usize,
+    companies : Vec<CatalogueEntry>,
+}
+
+impl Catalogue {
+    /// Reads and checks the catalogue file
+    pub fn load(path : &str) -> Result<Catalogue, String> {
+        match read_from_file(path) {
+            //Each problem is on its own line, and each says where it is
+            Ok(text) => Catalogue::parse(&text).map_err(|error| {
+                error.lines()
+                    .map(|problem| format!("{}: {}", path, problem))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }),
+            Err(error) => Err(format!("Could not read {}: {}", path, error)),
+        }
+    }
+
+    /// Reads and checks a catalogue written as JSON
+    /// Every problem with every company is reported, one per line
+    pub fn parse(text : &str) -> Result<Catalogue, String> {
+        let file : CatalogueFile = match serde_json::from_str(text) {
+            Ok(file) => file,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        if file.companies.is_empty() {
+            return Err(String::from("The catalogue has no companies"));
+        }
+
+        let mut problems : Vec<String> = Vec::new();
+        for (index, entry) in file.companies.iter().enumerate() {
+            for problem in entry.validate() {
+                problems.push(format!("company {} ({}): {}", index + 1, entry.name, problem));
+            }
+
+            //Names and tickers pick out a single company
+            let earlier = &file.companies[..index];
+            if earlier.iter().any(|other| name_key(&other.name) == name_key(&entry.name)) {
+                problems.push(format!("company {} ({}): another company has the same name", index + 1, entry.name));
+            }
+            if earlier.iter().any(|other| other.ticker == entry.ticker) {
+                problems.push(format!("company {} ({}): another company has the ticker {}", index + 1, entry.name, entry.ticker));
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(Catalogue { warmup_ticks : file.warmup_ticks, companies : file.companies }),
+            false => Err(problems.join("\n")),
+        }
+    }
+
+    /// Gets how many ticks of history companies get before a new game starts
+    pub fn warmup_ticks(&self) -> usize {
+        self.warmup_ticks
+    }
+
+    /// Gets the companies, in the order they are listed
+    pub fn companies(&self) -> &Vec<CatalogueEntry> {
+        &self.companies
+    }
+}
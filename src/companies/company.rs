@@ -1,37 +1,94 @@
 
 
 
-use crate::{Stock, data::data_saving::SaveData};
+use crate::{Stock, data::data_saving::{SaveData, LoadData, Record}};
 use crate::User;
 use crate::ID;
+use super::order_book::{OrderBook, TradeSide};
+use super::price_model::{self, PriceModel};
 
+/// How many shares a company has unless it is given another amount
+pub const DEFAULT_SHARES_OUTSTANDING : usize = 1000;
+/// How many shares it takes to move the price by its whole value, unless the company is given another amount
+pub const DEFAULT_LIQUIDITY : usize = 500;
+/// The sector companies are in unless they are put in another one
+pub const DEFAULT_SECTOR : &str = "General";
 
 /// A Company is similar to a real life company
 /// 
-/// They have shares that can be bought and sold at the price
-/// They are responsible for tracking their previous stock price
+/// They have a fixed amount of shares that can be bought and sold at the price
+/// They are responsible for tracking their previous stock price,
+/// how many of their shares players hold and the limit orders waiting on it
 #[derive(Debug)]
 pub struct Company {
     id : ID,
     name : String,
+    /// The short symbol players trade the company by
+    ticker : String,
+    /// Companies in the same sector move together
+    sector : String,
     stock_price : f32,
     stock_price_history : Vec<f32>,
+    /// The price the market maker quotes around in exchange mode, the random walk moves it each tick
+    fair_price : f32,
+    shares_outstanding : usize,
+    /// Shares held by players, the house holds the rest
+    /// Not saved, it is counted from the users wallets when a game loads
+    shares_held : usize,
+    /// How many shares traded with the house move the price by its whole value, 0 means trades never move it
+    liquidity : usize,
+    /// Moves the price each tick
+    price_model : Box<dyn PriceModel>,
+    order_book : OrderBook,
 }
 
 
+/// The most letters a ticker can have
+pub const MAX_TICKER_LENGTH : usize = 8;
+
+/// Makes a ticker from the first letters and numbers of a name, "Apple" gets "APPL"
+pub fn default_ticker(name : &str) -> String {
+    let ticker : String = name.chars()
+        .filter(|character| character.is_ascii_alphanumeric())
+        .take(4)
+        .collect();
+
+    match ticker.is_empty() {
+        true => String::from("CO"),
+        false => ticker.to_ascii_uppercase(),
+    }
+}
+
+/// Checks a ticker is 1 to 8 capital letters, numbers or dots
+pub fn check_ticker(ticker : &str) -> Result<(), String> {
+    if ticker.is_empty() || ticker.len() > MAX_TICKER_LENGTH || !ticker.chars().all(|character| character.is_ascii_uppercase() || character.is_ascii_digit() || character == '.') {
+        return Err(format!("The ticker '{}' should be 1 to {} capital letters, numbers or dots", ticker, MAX_TICKER_LENGTH));
+    }
+    Ok(())
+}
+
 /// Default COmpany functions
 impl Company {
     /// Builds a new company from the given parameters
     pub fn new(name : String, stock_price : f32) -> Company {
         Company {
+            ticker : default_ticker(&name),
             name,
             id : ID::new(),
+            sector : String::from(DEFAULT_SECTOR),
             stock_price,
             stock_price_history : vec!(stock_price), // (Starts the pricing history at the current price)
+            fair_price : stock_price,
+            shares_outstanding : DEFAULT_SHARES_OUTSTANDING,
+            shares_held : 0,
+            liquidity : DEFAULT_LIQUIDITY,
+            price_model : price_model::default_price_model(),
+            order_book : OrderBook::new(),
         }
     }
 
-    /// Getters
+
+    // Getters
 
     /// Get the name of the company
     pub fn name(&self) -> &String {
@@ -43,16 +100,130 @@ impl Company {
         self.id
     }
 
+    /// Gets the short symbol players trade the company by
+    pub fn ticker(&self) -> &String {
+        &self.ticker
+    }
+
+    /// Sets the short symbol players trade the company by
+    /// Only the company manager sets it, as it keeps tickers unique
+    pub(super) fn set_ticker(&mut self, ticker : String) {
+        self.ticker = ticker;
+    }
+
+    /// Gets the sector the company is in
+    pub fn sector(&self) -> &String {
+        &self.sector
+    }
+
+    /// Puts the company in a sector
+    pub fn set_sector(&mut self, sector : String) {
+        self.sector = sector;
+    }
+
     /// Get the current price of the stock
     pub fn stock_price(&self) -> f32 {
         self.stock_price
     }
 
+    /// Gets the price the market maker quotes around in exchange mode
+    pub fn fair_price(&self) -> f32 {
+        self.fair_price
+    }
+
     /// Gets the stock price history of the company
     pub fn stock_price_history(&self) -> &Vec<f32> {
         &self.stock_price_history
     }
 
+    /// Gets how many shares the company has in total
+    pub fn shares_outstanding(&self) -> usize {
+        self.shares_outstanding
+    }
+
+    /// Gets how many shares players hold
+    pub fn shares_held(&self) -> usize {
+        self.shares_held
+    }
+
+    /// Gets how many shares the house still has to sell
+    pub fn house_shares(&self) -> usize {
+        self.shares_outstanding.saturating_sub(self.shares_held)
+    }
+
+    /// Gets the value of every share at the current price
+    pub fn market_cap(&self) -> f32 {
+        self.stock_price * self.shares_outstanding as f32
+    }
+
+    /// Gets the percent of the shares players hold
+    pub fn held_percent(&self) -> f32 {
+        match self.shares_outstanding {
+            0 => 0.0,
+            shares => self.shares_held as f32 * 100.0 / shares as f32,
+        }
+    }
+
+    /// Sets how many shares the company has in total
+    /// Only done when the company is made or reset, while players hold none of them
+    pub fn set_shares_outstanding(&mut self, shares_outstanding : usize) {
+        self.shares_outstanding = shares_outstanding;
+    }
+
+    /// Gets how many shares it takes to move the price by its whole value
+    pub fn liquidity(&self) -> usize {
+        self.liquidity
+    }
+
+    /// Sets how many shares it takes to move the price by its whole value, 0 turns price impact off
+    pub fn set_liquidity(&mut self, liquidity : usize) {
+        self.liquidity = liquidity;
+    }
+
+    /// Gets the model that moves the price each tick
+    pub fn price_model(&self) -> &dyn PriceModel {
+        self.price_model.as_ref()
+    }
+
+    /// Sets the model that moves the price each tick
+    pub fn set_price_model(&mut self, price_model : Box<dyn PriceModel>) {
+        self.price_model = price_model;
+    }
+
+    /// Gets the price after trading the shares with the house straight away
+    /// Buys push it up by their share of the liquidity, sells push it down the same way so a buy and sell of the same size cancel out
+    pub fn impacted_price(&self, side : TradeSide, amount : usize) -> f32 {
+        if self.liquidity == 0 {
+            return self.stock_price;
+        }
+
+        let impact = amount as f32 / self.liquidity as f32;
+        match side {
+            TradeSide::Buy => self.stock_price * (1.0 + impact),
+            TradeSide::Sell => self.stock_price / (1.0 + impact),
+        }
+    }
+
+    /// Gets the average price each share trades at with the house, halfway between the current and the impacted price
+    pub fn execution_price(&self, side : TradeSide, amount : usize) -> f32 {
+        (self.stock_price + self.impacted_price(side, amount)) / 2.0
+    }
+
+    /// Sets how many shares players hold, after counting them from their wallets
+    pub fn set_shares_held(&mut self, shares_held : usize) {
+        self.shares_held = shares_held;
+    }
+
+    /// Gets the open limit orders of the company
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
+    /// Gets the open limit orders of the company mutably
+    pub fn order_book_mut(&mut self) -> &mut OrderBook {
+        &mut self.order_book
+    }
+
     /// Sets a new price for the Company
     pub fn set_stock_price(&mut self, new_price : f32) -> Result<(), String>{
         //Ensures the new price is valid
@@ -68,25 +239,72 @@ impl Company {
         Ok(())
     }
 
+    /// Moves the current price without adding to the history
+    /// Used by trades, so the history only holds one price per tick
+    pub fn move_stock_price(&mut self, new_price : f32) -> Result<(), String> {
+        if new_price < 0.0 { return Err(String::from("Price cannot be set to a negative value!")); }
+
+        self.stock_price = new_price;
+        Ok(())
+    }
+
+    /// Sets the price the market maker quotes around in exchange mode
+    pub fn set_fair_price(&mut self, new_price : f32) -> Result<(), String> {
+        if new_price <= 0.0 { return Err(String::from("The fair price must be above 0!")); }
+
+        self.fair_price = new_price;
+        Ok(())
+    }
+
+    /// Adds the current price to the history
+    /// Used on ticks in exchange mode, where trades set the price instead of the tick
+    pub fn record_stock_price(&mut self) {
+        self.stock_price_history.push(self.stock_price);
+    }
+
     /// Resets the companies stock history
     pub fn reset_company(&mut self, new_price : f32) -> Result<(), String> {
         //Ensures the new price is valid
         if new_price < 0.0 { return Err(String::from("Price cannot be set to a negative value!")); }
-        //Clears the stock history
+        //Clears the stock history, the users reset with it so their orders and shares go too
         self.stock_price_history.clear();
-        //Sets the new price
+        self.order_book.clear();
+        self.shares_held = 0;
+        //Sets the new price, the market maker starts quoting around it too
+        self.fair_price = new_price;
         self.set_stock_price(new_price)
     }
 
-    /// Purchases a stock from the company
-    pub fn purchase_stock(&self, user : &mut User, buy_amount : usize) -> Result<(), String> {
+    /// Purchases a stock from the company at a traded price
+    /// Only the shares the house still holds can be bought
+    pub fn purchase_stock_at(&mut self, user : &mut User, buy_amount : usize, price : f32) -> Result<(), String> {
+        if buy_amount > self.house_shares() {
+            return Err(format!("Only {} shares of {} are left to buy!", self.house_shares(), self.name()));
+        }
+
+        match self.transfer_stock_at(user, buy_amount, price) {
+            Ok(_) => {
+                self.shares_held += buy_amount;
+                Ok(())
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Gives a user shares another player sold them, players hold as many shares as before
+    pub fn transfer_stock_at(&self, user : &mut User, buy_amount : usize, price : f32) -> Result<(), String> {
         //Creates the bought stock
-        let stock = Stock::new(self.id(), self.name.clone(), self.stock_price());
+        let stock = Stock::new(self.id(), self.name.clone(), price);
 
         //Returns the result of the users buy
         user.buy_stock(stock, buy_amount)
     }
 
+    /// Takes back shares a player sold to the house
+    pub fn return_stock(&mut self, sell_amount : usize) {
+        self.shares_held = self.shares_held.saturating_sub(sell_amount);
+    }
+
 }
 
 impl SaveData for Company {
@@ -112,6 +330,116 @@ impl SaveData for Company {
         //Return the data
         data
     }
+
+    /// Saves the company with its full price history
+    fn save_data(&self) -> String {
+        let history : Vec<String> = self.stock_price_history()
+            .iter()
+            .map(|price| price.to_string())
+            .collect();
+
+        let mut record = Record::new("company")
+            .with("id", self.id())
+            .with("name", self.name())
+            .with("ticker", self.ticker())
+            .with("sector", self.sector())
+            .with("price", self.stock_price())
+            .with("fair_price", self.fair_price())
+            .with("shares", self.shares_outstanding())
+            .with("liquidity", self.liquidity())
+            .with("model", self.price_model().name());
+        for (name, value) in self.price_model().parameters() {
+            record = record.with(name, value);
+        }
+
+        record
+            .with("history", history.join(","))
+            .get_data()
+    }
+}
+
+/// Loads a company from its saved data
+impl LoadData for Company {
+    fn load_data(data : &str) -> Result<Company, String> {
+        let record = match Record::load_kind(data, "company") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let id : usize = match record.get("id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let name : String = match record.get("name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        //Companies saved before tickers get the one made from their name
+        let ticker : String = match record.get_or("ticker", default_ticker(&name)) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        if let Err(error) = check_ticker(&ticker) {
+            return Err(error);
+        }
+        let sector : String = match record.get_or("sector", String::from(DEFAULT_SECTOR)) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let stock_price : f32 = match record.get("price") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        //Companies saved before fair prices quote around their last price
+        let fair_price : f32 = match record.get_or("fair_price", stock_price) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let shares_outstanding : usize = match record.get_or("shares", DEFAULT_SHARES_OUTSTANDING) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let liquidity : usize = match record.get_or("liquidity", DEFAULT_LIQUIDITY) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        //Companies saved before price models walk the default way
+        let price_model = match record.get::<String>("model") {
+            Ok(model_name) => match price_model::load_price_model(&model_name, &record) {
+                Ok(price_model) => price_model,
+                Err(error) => return Err(error),
+            },
+            Err(_error) => price_model::default_price_model(),
+        };
+        let history_text : String = match record.get_or("history", String::new()) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        //Loads the price history
+        let mut stock_price_history : Vec<f32> = Vec::new();
+        for price_text in history_text.split(',').filter(|text| !text.is_empty()) {
+            match price_text.parse::<f32>() {
+                Ok(price) => stock_price_history.push(price),
+                Err(_error) => return Err(format!("Could not parse price history from '{}' in company", price_text)),
+            }
+        }
+
+        Ok(Company {
+            id : ID::load(id),
+            name,
+            ticker,
+            sector,
+            stock_price,
+            stock_price_history,
+            fair_price,
+            shares_outstanding,
+            shares_held : 0,
+            liquidity,
+            price_model,
+            order_book : OrderBook::new(),
+        })
+    }
 }
 
 
@@ -1,17 +1,52 @@
-
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
 
 use crate::Company;
-use crate::SaveData;
+use crate::data::data_saving::{SaveData, LoadData};
+use crate::users::user::User;
+use crate::users::user_manager::UserManager;
 use crate::ID;
-use rand::Rng;
+use super::company;
+use crate::users::trade_history::{Fill, TradeRecord, TradeKind};
+use super::order_book::{Match, Order, Quote, TradeSide};
+use super::market_factors::MarketFactors;
+use super::news::{self, NewsEvent, MAX_NEWS};
+use super::price_model::MIN_PRICE;
+
 
+/// A resting order taken out of its book because it couldn't trade when it crossed
+#[derive(Debug)]
+pub struct CancelledOrder {
+    pub order : Order,
+    pub company_name : String,
+    /// Why it couldn't trade
+    pub reason : String,
+}
 
 /// The Company manager holds all other companies
-/// This is so you can search for specific companies by their IDs, etc
+/// This is so you can search for specific companies by their IDs, tickers and names
+/// Companies are never removed, so the indexes hold where each one is in the list
 #[derive(Debug)]
 pub struct CompanyManager<> {
     companies : Vec<Company>,
+    /// Finds a company by the value of its ID
+    by_id : HashMap<usize, usize>,
+    /// Finds a company by its ticker, in capitals
+    by_ticker : HashMap<String, usize>,
+    /// Finds a company by its name, in lower case
+    by_name : HashMap<String, usize>,
     stored_save : String,
+    /// Users trade with each other and a market maker instead of the house
+    exchange_mode : bool,
+    /// The seed every tick of the market is drawn from
+    seed : u64,
+    /// How many ticks have been drawn since the seed was set
+    tick : u64,
+    /// The latest news, oldest first
+    news : Vec<NewsEvent>,
+    /// Orders that were cancelled because they couldn't trade, until their users are told
+    cancelled_orders : Vec<CancelledOrder>,
 }
 
 
@@ -24,102 +59,778 @@ impl CompanyManager {
     pub fn new() -> CompanyManager {
         CompanyManager { 
             companies : Vec::new(),
+            by_id : HashMap::new(),
+            by_ticker : HashMap::new(),
+            by_name : HashMap::new(),
             stored_save : String::new(),
+            exchange_mode : false,
+            seed : rand::thread_rng().gen(),
+            tick : 0,
+            news : Vec::new(),
+            cancelled_orders : Vec::new(),
         }
     }
 
     /// Creates a new company in the manager
-    pub fn new_company(&mut self, name : String, stock_price : f32) -> ID {
+    /// It gets a ticker made from its name, with a number added if another company has it
+    /// Names have to be unique whatever their case, so each company can be found by its name
+    pub fn new_company(&mut self, name : String, stock_price : f32) -> Result<ID, String> {
+        if let Err(error) = self.check_name(&name) {
+            return Err(error);
+        }
+
         //Create the new company
         let new_company = Company::new(name, stock_price);
         //Copy the ID
         let company_id = new_company.id();
-        self.companies.push(new_company);
+        self.add_company(new_company);
+
+        Ok(company_id)
+    }
+
+    /// Checks no company has the name yet, whatever its case
+    fn check_name(&self, name : &str) -> Result<(), String> {
+        match self.by_name.get(&name_key(name)) {
+            Some(pos) => Err(format!("{} is already the name of a company", self.companies[*pos].name())),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a company that was loaded from a save
+    /// Replaces the company with the same ID if there already is one
+    pub fn restore_company(&mut self, company : Company) -> ID {
+        let company_id = company.id();
+
+        //The saved company keeps its ticker, a company that has it now moves off it
+        //Journal entries record every company a catalogue reset changed, so that company gets its own ticker back after
+        match self.by_ticker.get(&ticker_key(company.ticker())).map(|pos| self.companies[*pos].id()) {
+            Some(holder) if !holder.equals(company_id) => {
+                let reserved = vec![company.ticker().clone()];
+                if let Err(error) = self.release_ticker(holder, &reserved) {
+                    println!("Could not move {} off its ticker: {}", holder, error);
+                }
+            },
+            _ => (),
+        }
+
+        match self.position(company_id) {
+            Some(pos) => {
+                self.unindex(pos);
+                self.companies[pos] = company;
+                self.index(pos);
+            },
+            None => self.add_company(company),
+        }
+
+        //The stored save no longer matches the companies
+        self.stored_save.clear();
 
         company_id
     }
 
+    /// Adds a company to the end of the list and the indexes
+    fn add_company(&mut self, company : Company) {
+        self.companies.push(company);
+        self.index(self.companies.len() - 1);
+    }
+
+    /// Adds the company at the position to the indexes
+    /// A ticker another company has gets a number added, names are checked to be unique before a company is added
+    fn index(&mut self, pos : usize) {
+        let ticker = self.unique_ticker(self.companies[pos].ticker(), pos, &[]);
+
+        let company = &mut self.companies[pos];
+        company.set_ticker(ticker);
+        self.by_id.insert(company.id().value(), pos);
+        self.by_ticker.insert(ticker_key(company.ticker()), pos);
+        self.by_name.insert(name_key(company.name()), pos);
+    }
+
+    /// Makes a ticker from the base that no other company has and isn't reserved
+    /// The base is cut short so the number added still fits in a ticker
+    fn unique_ticker(&self, base : &str, pos : usize, reserved : &[String]) -> String {
+        let taken = |ticker : &str| {
+            matches!(self.by_ticker.get(&ticker_key(ticker)), Some(other) if *other != pos)
+                || reserved.iter().any(|other| ticker_key(other) == ticker_key(ticker))
+        };
+
+        let mut ticker = base.to_string();
+        let mut number = 2;
+        while taken(&ticker) {
+            let suffix = number.to_string();
+            let length = base.len().min(company::MAX_TICKER_LENGTH - suffix.len());
+            ticker = format!("{}{}", &base[..length], suffix);
+            number += 1;
+        }
+
+        ticker
+    }
+
+    /// Removes the company at the position from the indexes
+    fn unindex(&mut self, pos : usize) {
+        let company = &self.companies[pos];
+        self.by_id.remove(&company.id().value());
+        if self.by_ticker.get(&ticker_key(company.ticker())) == Some(&pos) {
+            self.by_ticker.remove(&ticker_key(company.ticker()));
+        }
+        if self.by_name.get(&name_key(company.name())) == Some(&pos) {
+            self.by_name.remove(&name_key(company.name()));
+        }
+    }
+
+    /// Gets where the company with the ID is in the list
+    fn position(&self, id : ID) -> Option<usize> {
+        self.by_id.get(&id.value()).copied()
+    }
+
     /// Gets the Company list
     pub fn companies(&self) -> &Vec<Company> {
         &self.companies
     }
 
     /// Gets the company list mutably
-    pub fn companies_mut(&mut self) -> &mut Vec<Company> {
+    /// Companies can't be added or moved through it, so the indexes stay right
+    pub fn companies_mut(&mut self) -> &mut [Company] {
         &mut self.companies
     }
 
     /// Gets a company by it's ID
     pub fn get_company_by_id(&self, id : ID) -> Result<&Company, String> {
-        //Checks every companies name
-        let filtered : Vec<&Company> = self.companies()
-            .iter()
-            .filter(|company| company.id().equals(id))
-            .collect();
-
-        //Checks that there are results
-        if filtered.len() == 0 { return Err(format!("No company with ID {} was found!", id)); }
-        //Ensures there is only 1 result
-        if filtered.len() != 1 { return Err(String::from("Multiple companies with the same name exist!"))}
+        match self.position(id) {
+            Some(pos) => Ok(&self.companies[pos]),
+            None => Err(format!("No company with ID {} was found!", id)),
+        }
+    }
 
-        //Return the only company
-        Ok(&filtered[0])
+    /// Gets a company by it's ID mutably
+    pub fn get_company_by_id_mut(&mut self, id : ID) -> Result<&mut Company, String> {
+        match self.position(id) {
+            Some(pos) => Ok(&mut self.companies[pos]),
+            None => Err(format!("No company with ID {} was found!", id)),
+        }
     }
 
-    /// Gets a company by it's name
+    /// Gets a company by it's name, whatever the case
     pub fn get_company_by_name(&self, name : &String) -> Result<&Company, String> {
-        //Checks every companies name
-        let filtered : Vec<&Company> = self.companies()
-            .iter()
-            .filter(|company| company.name().eq(name))
-            .collect();
+        match self.by_name.get(&name_key(name)) {
+            Some(pos) => Ok(&self.companies[*pos]),
+            None => Err(format!("No company named {} was found!", name)),
+        }
+    }
 
-        //Checks that there are results
-        if filtered.len() == 0 { return Err(String::from("No company was found!")); }
-        //Ensures there is only 1 result
-        if filtered.len() != 1 { return Err(String::from("Multiple companies with the same name exist!"))}
+    /// Gets a company by it's name mutably, whatever the case
+    pub fn get_company_by_name_mut(&mut self, name : &String) -> Result<&mut Company, String> {
+        match self.by_name.get(&name_key(name)) {
+            Some(pos) => Ok(&mut self.companies[*pos]),
+            None => Err(format!("No company named {} was found!", name)),
+        }
+    }
 
-        //Return the only company
-        Ok(&filtered[0])
+    /// Gets a company by its ticker, whatever the case
+    pub fn get_company_by_ticker(&self, ticker : &str) -> Result<&Company, String> {
+        match self.by_ticker.get(&ticker_key(ticker)) {
+            Some(pos) => Ok(&self.companies[*pos]),
+            None => Err(format!("No company has the ticker {}!", ticker)),
+        }
     }
 
-    /// Gets a company by it's name
-    pub fn get_company_by_name_mut(&mut self, name : &String) -> Result<&mut Company, String> {
-        for company in self.companies_mut() {
-            if company.name().eq(name) {
-                return Ok(company);
+    /// Finds a company by its ticker, name or ID, whatever the case
+    /// Tickers are tried first, so "AAPL", "aapl" and "Apple" all find Apple
+    pub fn find_company(&self, key : &str) -> Result<&Company, String> {
+        let key = key.trim();
+        if let Ok(company) = self.get_company_by_ticker(key) {
+            return Ok(company);
+        }
+        if let Ok(company) = self.get_company_by_name(&key.to_string()) {
+            return Ok(company);
+        }
+
+        match key.parse::<usize>().ok().and_then(|id| self.by_id.get(&id)) {
+            Some(pos) => Ok(&self.companies[*pos]),
+            None => Err(format!("No company has the ticker, name or ID {}!", key)),
+        }
+    }
+
+    /// Gives a company a new ticker, no other company can have it
+    pub fn set_ticker(&mut self, company_id : ID, ticker : String) -> Result<(), String> {
+        if let Err(error) = company::check_ticker(&ticker) {
+            return Err(error);
+        }
+        let pos = match self.position(company_id) {
+            Some(pos) => pos,
+            None => return Err(format!("No company with ID {} was found!", company_id)),
+        };
+        match self.by_ticker.get(&ticker_key(&ticker)) {
+            Some(other) if *other != pos => return Err(format!("{} is already the ticker of {}", ticker, self.companies[*other].name())),
+            _ => (),
+        }
+
+        self.by_ticker.remove(&ticker_key(self.companies[pos].ticker()));
+        self.by_ticker.insert(ticker_key(&ticker), pos);
+        self.companies[pos].set_ticker(ticker);
+        Ok(())
+    }
+
+    /// Moves a company off its ticker, onto one made from its name that no other company has and isn't reserved
+    pub fn release_ticker(&mut self, company_id : ID, reserved : &[String]) -> Result<(), String> {
+        let pos = match self.position(company_id) {
+            Some(pos) => pos,
+            None => return Err(format!("No company with ID {} was found!", company_id)),
+        };
+        let ticker = self.unique_ticker(&company::default_ticker(self.companies[pos].name()), pos, reserved);
+
+        self.by_ticker.remove(&ticker_key(self.companies[pos].ticker()));
+        self.by_ticker.insert(ticker_key(&ticker), pos);
+        self.companies[pos].set_ticker(ticker);
+        Ok(())
+    }
+
+    /// Checks if users trade with each other and the market maker, instead of the house at the set price
+    pub fn exchange_mode(&self) -> bool {
+        self.exchange_mode
+    }
+
+    /// Turns exchange mode on or off, the market maker leaves the books when it is off
+    pub fn set_exchange_mode(&mut self, exchange_mode : bool) {
+        self.exchange_mode = exchange_mode;
+        if !exchange_mode {
+            for company in self.companies_mut() {
+                company.order_book_mut().clear_quotes();
             }
         }
-        Err(format!("No Company with name {} found", name))
     }
 
-    /// Updates the prices of the companies
-    pub fn update(&mut self) {
-        const STOCK_RANGE : f32 = 20.0;
+    /// Gets the seed the market is drawn from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Gets how many ticks have been drawn since the seed was set
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Sets the seed, and how many ticks have already been drawn from it
+    pub fn set_seed(&mut self, seed : u64, tick : u64) {
+        self.seed = seed;
+        self.tick = tick;
+    }
+
+    /// Gets the random numbers for the next tick
+    /// Each tick has its own generator, so the seed and tick count are all there is to save
+    fn next_rng(&mut self) -> StdRng {
+        let rng = StdRng::seed_from_u64(self.seed ^ self.tick.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        self.tick += 1;
+        rng
+    }
+
+    /// Updates the prices of the companies, each moved by the market, its sector, its own price model and the news
+    /// In exchange mode the random walk moves the fair price the market maker quotes around, trades set the prices
+    /// Returns the new price of every company that changed
+    pub fn update(&mut self) -> Vec<(ID, f32)> {
+        let mut new_prices : Vec<(ID, f32)> = Vec::new();
+        let mut rng = self.next_rng();
+
+        //Sometimes news breaks, jumping the prices of the companies it is about
+        let tick = self.tick;
+        if let Some(event) = news::draw_news(&self.companies, tick, &mut rng) {
+            self.add_news(event);
+        }
+
+        //Companies move with the market and their sector, then by their own model
+        let factors = MarketFactors::draw(&self.companies, &mut rng);
+
         //Loops through each company
-        for company in self.companies_mut() {
-            let current_stock_price = company.stock_price();
+        for company in self.companies() {
+            //In exchange mode the walk carries on from the last fair price, not the last trade
+            let price = match self.exchange_mode {
+                true => company.fair_price(),
+                false => company.stock_price(),
+            };
+            let mut next_price = company.price_model().next_price(price, &mut rng) * factors.shock(company.sector());
+
+            //The news about the company pushes its price along while it lasts
+            for event in self.news.iter().filter(|event| event.active(tick) && event.affects(company.id())) {
+                next_price *= event.drift().exp();
+                if event.tick() == tick {
+                    next_price *= 1.0 + event.jump();
+                }
+            }
+
+            //Prices never fall to 0, however far the model moves them
+            let next_price = next_price.max(MIN_PRICE);
+            if next_price.is_finite() {
+                new_prices.push((company.id(), next_price));
+            }
+        }
+
+        //Only the prices that could be set are kept, so replaying them does the same
+        new_prices.retain(|(company_id, price)| self.set_price(*company_id, *price).is_ok());
+
+        //Updates the stored save data
+        self.stored_save.clear();
+        self.stored_save = self.get_data();
+
+        new_prices
+    }
+
+    /// Gets the latest news, oldest first
+    pub fn news(&self) -> &Vec<NewsEvent> {
+        &self.news
+    }
+
+    /// Gets the news that broke on the last tick
+    pub fn breaking_news(&self) -> Vec<&NewsEvent> {
+        self.news.iter().filter(|event| event.tick() == self.tick).collect()
+    }
+
+    /// Adds news, forgetting the oldest once there is too much
+    /// Used when news breaks and when replaying the journal
+    pub fn add_news(&mut self, event : NewsEvent) {
+        if self.news.len() >= MAX_NEWS {
+            self.news.remove(0);
+        }
+        self.news.push(event);
+    }
+
+    /// Sets the price of a company, or the fair price its market maker quotes around in exchange mode
+    fn set_price(&mut self, company_id : ID, price : f32) -> Result<(), String> {
+        let exchange_mode = self.exchange_mode;
+        let company = match self.get_company_by_id_mut(company_id) {
+            Ok(company) => company,
+            Err(error) => return Err(error),
+        };
+
+        if !exchange_mode {
+            //The fair price follows along, so exchange mode starts from the last tick
+            if let Err(error) = company.set_stock_price(price) {
+                return Err(error);
+            }
+            return company.set_fair_price(price);
+        }
+        if let Err(error) = company.set_fair_price(price) {
+            return Err(error);
+        }
+
+        //The market maker only sells the shares the house holds
+        let house_shares = company.house_shares();
+        company.order_book_mut().requote(price, house_shares);
+        //The history still gets a point each tick, at the price trades left it at
+        company.record_stock_price();
+        Ok(())
+    }
+
+    /// Buys or sells stock for a user straight away, adding it to their history
+    /// Trades at the set price, or against the book in exchange mode
+    /// In exchange mode the book has to hold enough to fill it in full, but it stops early if a match can't go through
+    /// Returns the fill of the user, holding the amount that traded, then those of anyone they traded with
+    pub fn trade(&mut self, user_manager : &mut UserManager, user_id : ID, side : TradeSide, company_id : ID, amount : usize, kind : TradeKind) -> Result<Vec<Fill>, String> {
+        let pos = match self.position(company_id) {
+            Some(pos) => pos,
+            None => return Err(format!("No company with ID {} was found!", company_id)),
+        };
+        let user = match user_manager.get_user_by_id_mut(user_id) {
+            Ok(user) => user,
+            Err(error) => return Err(error),
+        };
+
+        if self.exchange_mode {
+            //Trades at any price, as long as the user can pay for all of it
+            let limit_price = match side {
+                TradeSide::Buy => f32::MAX,
+                TradeSide::Sell => 0.0,
+            };
+            let mut order = Order::new(company_id, user_id, side, amount, limit_price);
+
+            match self.companies[pos].order_book().cost_to_fill(&order) {
+                Some(cost) if side == TradeSide::Buy && cost > user.available_money() => return Err(format!("{} does not have enough money to buy {} shares for {}$", user.display_name(), amount, cost)),
+                Some(_cost) if side == TradeSide::Sell && amount > user.wallet().available_amount(company_id) => return Err(String::from("Selling more stock than is available to sell!")),
+                Some(_cost) => (),
+                None => return Err(format!("There aren't enough orders to {} {} shares of {}", side.as_str(), amount, self.companies[pos].name())),
+            }
 
-            //Generates a random price change
-            let mut rng = rand::thread_rng();
-            let price_change : f32 = rng.gen_range(-STOCK_RANGE..STOCK_RANGE);
+            return self.match_order(pos, user_manager, &mut order, false, kind);
+        }
 
-            let price_change_result = company.set_stock_price(current_stock_price + price_change);
+        //Bigger trades get a worse price, and move it for everyone after
+        let company = &mut self.companies[pos];
+        let price = company.execution_price(side, amount);
+        let impacted_price = company.impacted_price(side, amount);
+        match Self::trade_at(company, user, side, amount, price, true) {
+            Ok(_) => {
+                let trade = TradeRecord::new(kind, side, company_id, company.name().clone(), amount, price);
+                user.record_trade(trade.clone());
+                Self::move_price(company, impacted_price);
+                self.stored_save.clear();
+                Ok(vec![Fill { user_id, trade }])
+            },
+            Err(error) => Err(error),
+        }
+    }
 
-            match price_change_result {
-                Err(_error) => (),
-                _ => (),
+    /// Trades an incoming order against the book until it is filled or nothing crosses it
+    /// A reserved order lets go of what it held for each share that trades
+    /// The last trade sets the companies price
+    /// Stops at the first match the incoming user can't pay for or deliver, it goes back in the book
+    /// Only gives an error if nothing traded, trades that went through stand
+    fn match_order(&mut self, pos : usize, user_manager : &mut UserManager, order : &mut Order, reserved : bool, kind : TradeKind) -> Result<Vec<Fill>, String> {
+        let mut fills : Vec<Fill> = Vec::new();
+        let mut traded_amount : usize = 0;
+        let mut traded_money : f32 = 0.0;
+        let mut last_price : Option<f32> = None;
+        let mut failure : Option<String> = None;
+
+        while order.amount() > 0 {
+            let matched = match self.companies[pos].order_book_mut().take_best(order) {
+                Some(matched) => matched,
+                None => break,
+            };
+            let with_house = matched.order.is_none();
+
+            //Checks the incoming side before the resting side trades, so a trade never goes through on one side only
+            let taken = order.clone().split_off(matched.amount);
+            let checked = match user_manager.get_user_by_id(order.user_id()) {
+                Ok(user) => Self::can_trade(&self.companies[pos], user, &taken, matched.price, reserved, with_house),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = checked {
+                self.companies[pos].order_book_mut().put_back(matched, order.side());
+                failure = Some(format!("Could not fill order {}: {}", order.id(), error));
+                break;
+            }
+
+            //The resting order trades at its own price, it is cancelled if it can't
+            if let Some(resting) = matched.order {
+                match self.settle(pos, user_manager, &resting, matched.price, TradeKind::Limit, false) {
+                    Ok(fill) => fills.push(fill),
+                    Err(error) => {
+                        self.cancel_unfilled(pos, user_manager, resting, error);
+                        continue;
+                    },
+                }
+            }
+            let taken = order.split_off(matched.amount);
+
+            //The incoming order trades at the same price
+            let user = match user_manager.get_user_by_id_mut(order.user_id()) {
+                Ok(user) => user,
+                Err(error) => {
+                    failure = Some(error);
+                    break;
+                },
+            };
+            if reserved {
+                user.release_order(&taken);
+            }
+            match Self::trade_at(&mut self.companies[pos], user, order.side(), matched.amount, matched.price, with_house) {
+                Ok(_) => {
+                    traded_amount += matched.amount;
+                    traded_money += matched.price * matched.amount as f32;
+                    last_price = Some(matched.price);
+                },
+                Err(error) => {
+                    failure = Some(format!("Could not fill order {}: {}", order.id(), error));
+                    break;
+                },
+            }
+        }
+
+        if let Some(price) = last_price {
+            let company = &mut self.companies[pos];
+            let trade = TradeRecord::new(kind, order.side(), company.id(), company.name().clone(), traded_amount, traded_money / traded_amount as f32);
+            if let Ok(user) = user_manager.get_user_by_id_mut(order.user_id()) {
+                user.record_trade(trade.clone());
+            }
+            fills.insert(0, Fill { user_id : order.user_id(), trade });
+
+            Self::move_price(company, price);
+            self.stored_save.clear();
+        }
+
+        match (failure, fills.is_empty()) {
+            (Some(error), true) => Err(error),
+            _ => Ok(fills),
+        }
+    }
+
+    /// Checks a user has the money or shares for their side of a trade
+    /// A reserved order can use what it held back
+    fn can_trade(company : &Company, user : &User, order : &Order, price : f32, reserved : bool, with_house : bool) -> Result<(), String> {
+        match order.side() {
+            TradeSide::Buy => {
+                let cost = price * order.amount() as f32;
+                let held = if reserved { order.reserved_money() } else { 0.0 };
+                if with_house && order.amount() > company.house_shares() {
+                    return Err(format!("Only {} shares of {} are left to buy!", company.house_shares(), company.name()));
+                }
+                if user.available_money() + held < cost {
+                    return Err(format!("{} does not have enough money to buy {} shares for {}$", user.display_name(), order.amount(), cost));
+                }
+            },
+            TradeSide::Sell => {
+                let held = if reserved { order.amount() } else { 0 };
+                if user.wallet().available_amount(company.id()) + held < order.amount() {
+                    return Err(String::from("Selling more stock than is available to sell!"));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Trades the shares of a resting order at a price, after letting go of what they held
+    /// Gives an error if the user is gone or can't trade, the order still holds what it did then
+    fn settle(&mut self, pos : usize, user_manager : &mut UserManager, order : &Order, price : f32, kind : TradeKind, with_house : bool) -> Result<Fill, String> {
+        let company = &mut self.companies[pos];
+        let user = match user_manager.get_user_by_id_mut(order.user_id()) {
+            Ok(user) => user,
+            Err(error) => return Err(error),
+        };
+        if let Err(error) = Self::can_trade(company, user, order, price, true, with_house) {
+            return Err(error);
+        }
+
+        user.release_order(order);
+        match Self::trade_at(company, user, order.side(), order.amount(), price, with_house) {
+            Ok(_) => {
+                let trade = TradeRecord::new(kind, order.side(), company.id(), company.name().clone(), order.amount(), price);
+                user.record_trade(trade.clone());
+                Ok(Fill { user_id : order.user_id(), trade })
+            },
+            Err(error) => {
+                //Nothing traded, so the order holds back what it did before
+                if let Err(reserve_error) = user.reserve_for_order(order) {
+                    println!("Could not hold back order {} again: {}", order.id(), reserve_error);
+                }
+                Err(error)
+            },
+        }
+    }
+
+    /// Cancels a resting order that was taken out of its book but couldn't trade
+    /// It gives back what it held like any cancelled order, and waits to be sent to its user
+    fn cancel_unfilled(&mut self, pos : usize, user_manager : &mut UserManager, order : Order, reason : String) {
+        if let Ok(user) = user_manager.get_user_by_id_mut(order.user_id()) {
+            user.release_order(&order);
+        }
+        let company_name = self.companies[pos].name().clone();
+        self.cancelled_orders.push(CancelledOrder { order, company_name, reason });
+    }
+
+    /// Takes the orders that were cancelled because they couldn't trade, so their users can be told
+    pub fn take_cancelled_orders(&mut self) -> Vec<CancelledOrder> {
+        std::mem::take(&mut self.cancelled_orders)
+    }
+
+    /// Buys or sells shares for a user at a price
+    /// Shares only come from or go back to the house when it is the other side, players trading keep the count the same
+    fn trade_at(company : &mut Company, user : &mut User, side : TradeSide, amount : usize, price : f32, with_house : bool) -> Result<(), String> {
+        match (side, with_house) {
+            (TradeSide::Buy, true) => company.purchase_stock_at(user, amount, price),
+            (TradeSide::Buy, false) => company.transfer_stock_at(user, amount, price),
+            (TradeSide::Sell, with_house) => match user.sell_stock_at(company.id(), amount, price) {
+                Ok(_) => {
+                    if with_house {
+                        company.return_stock(amount);
+                    }
+                    Ok(())
+                },
+                Err(error) => Err(error),
+            },
+        }
+    }
+
+    /// Sets the price a trade left a company at, the next tick records it in the history in both modes
+    fn move_price(company : &mut Company, price : f32) {
+        if let Err(error) = company.move_stock_price(price) {
+            println!("Could not set the price of {}: {}", company.name(), error);
+        }
+    }
+
+    /// Counts the shares players hold of each company from their wallets
+    /// Runs once a saved game loads, trades keep the count after that
+    pub fn count_shares_held(&mut self, user_manager : &UserManager) {
+        for company in self.companies.iter_mut() {
+            let shares_held = user_manager.users()
+                .iter()
+                .flat_map(|user| user.wallet().holders().iter())
+                .filter(|holder| holder.company_id().equals(company.id()))
+                .map(|holder| holder.stock_amount())
+                .sum();
+            company.set_shares_held(shares_held);
+        }
+    }
+
+    /// Gets every open order of a user, oldest first in each company
+    pub fn orders_of_user(&self, user_id : ID) -> Vec<&Order> {
+        self.companies()
+            .iter()
+            .flat_map(|company| company.order_book().orders().iter())
+            .filter(|order| order.user_id().equals(user_id))
+            .collect()
+    }
+
+    /// Holds back what the order needs from the user, then adds it to its companies order book
+    /// In exchange mode it first trades against the book, only what is left rests
+    /// Returns the fills of the user and anyone they traded with
+    pub fn place_order(&mut self, user_manager : &mut UserManager, mut order : Order) -> Result<Vec<Fill>, String> {
+        let pos = match self.position(order.company_id()) {
+            Some(pos) => pos,
+            None => return Err(format!("No company with ID {} was found!", order.company_id())),
+        };
+
+        //Only other players could sell more than the house has left
+        let house_shares = self.companies[pos].house_shares();
+        if !self.exchange_mode && order.side() == TradeSide::Buy && order.amount() > house_shares {
+            return Err(format!("Only {} shares of {} are left to buy!", house_shares, self.companies[pos].name()));
+        }
+
+        match user_manager.get_user_by_id_mut(order.user_id()) {
+            Ok(user) => match user.reserve_for_order(&order) {
+                Ok(_) => (),
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        }
+
+        //An order that can't trade yet still rests in the book
+        let fills = match self.exchange_mode {
+            true => self.match_order(pos, user_manager, &mut order, true, TradeKind::Limit).unwrap_or_default(),
+            false => Vec::new(),
+        };
+
+        if order.amount() > 0 {
+            self.companies[pos].order_book_mut().add(order);
+        }
+        Ok(fills)
+    }
+
+    /// Takes an order out of its book and gives its user back what it held
+    pub fn cancel_order(&mut self, user_manager : &mut UserManager, order_id : ID) -> Result<Order, String> {
+        let order = match self.companies_mut().iter_mut().find_map(|company| company.order_book_mut().remove(order_id)) {
+            Some(order) => order,
+            None => return Err(format!("No open order with ID {} was found!", order_id)),
+        };
+
+        match user_manager.get_user_by_id_mut(order.user_id()) {
+            Ok(user) => user.release_order(&order),
+            Err(error) => return Err(error),
+        }
+
+        Ok(order)
+    }
+
+    /// Trades every order the current prices crossed, oldest first
+    /// They move the price like a trade with the house, but never fill past their limit
+    /// In exchange mode they are the orders the market makers new quotes cross, trading at the quotes price
+    /// Runs after each update, and again when the update is replayed, so it has to only depend on the prices
+    pub fn fill_orders(&mut self, user_manager : &mut UserManager) -> Vec<Fill> {
+        let mut fills : Vec<Fill> = Vec::new();
+
+        for pos in 0..self.companies.len() {
+            //Each fill moves the price, so every order is checked against the price the fills before it left
+            loop {
+                let company = &mut self.companies[pos];
+                let price = company.stock_price();
+                let (order, price, impacted_price) = match self.exchange_mode {
+                    true => match company.order_book_mut().take_quoted() {
+                        Some(Match { order : Some(order), price, .. }) => (order, price, price),
+                        Some(_matched) => continue,
+                        None => break,
+                    },
+                    false => {
+                        let order = match company.order_book_mut().take_crossed(price) {
+                            Some(order) => order,
+                            None => break,
+                        };
+                        let execution_price = company.execution_price(order.side(), order.amount());
+                        let execution_price = match order.side() {
+                            TradeSide::Buy => execution_price.min(order.limit_price()),
+                            TradeSide::Sell => execution_price.max(order.limit_price()),
+                        };
+                        let impacted_price = company.impacted_price(order.side(), order.amount());
+                        (order, execution_price, impacted_price)
+                    },
+                };
+
+                match self.settle(pos, user_manager, &order, price, TradeKind::Limit, true) {
+                    Ok(fill) => fills.push(fill),
+                    Err(error) => {
+                        self.cancel_unfilled(pos, user_manager, order, error);
+                        continue;
+                    },
+                }
+
+                //Each trade with the house moves the price
+                Self::move_price(&mut self.companies[pos], impacted_price);
+                self.stored_save.clear();
+            }
+        }
+
+        fills
+    }
+
+    /// Sells for every stop-loss, trailing-stop and take-profit the current prices triggered
+    /// They sell through the same path as any other sell, shares held back for limit orders aren't sold
+    /// Runs after each update, and again when the update is replayed, so it has to only depend on the prices
+    pub fn trigger_protections(&mut self, user_manager : &mut UserManager) -> Vec<Fill> {
+        let mut triggered = Vec::new();
+        for user in user_manager.users_mut() {
+            triggered.extend(user.take_triggered_protections(self));
+        }
+
+        let mut fills : Vec<Fill> = Vec::new();
+        for protection in triggered {
+            //A protection with nothing left to sell is dropped
+            let available = match user_manager.get_user_by_id(protection.user_id()) {
+                Ok(user) => user.wallet().available_amount(protection.company_id()),
+                Err(_error) => continue,
+            };
+            let amount = protection.amount().min(available);
+            if amount == 0 {
+                continue;
+            }
+
+            match self.trade(user_manager, protection.user_id(), TradeSide::Sell, protection.company_id(), amount, TradeKind::from(protection.kind())) {
+                Ok(sold) => fills.extend(sold),
+                Err(error) => println!("Could not sell for protection {}: {}", protection.id(), error),
+            }
+        }
+
+        fills
+    }
+
+    /// Sets the prices from a previous update
+    /// Used when replaying the journal, it counts as a tick so later updates draw what they did live
+    pub fn apply_prices(&mut self, new_prices : &[(ID, f32)]) -> Result<(), String> {
+        self.tick += 1;
+        for (company_id, price) in new_prices {
+            if let Err(error) = self.set_price(*company_id, *price) {
+                return Err(error);
             }
         }
 
         //Updates the stored save data
         self.stored_save.clear();
         self.stored_save = self.get_data();
+
+        Ok(())
     }
 }
 
 
 
+/// Makes the key a ticker is indexed by
+fn ticker_key(ticker : &str) -> String {
+    ticker.trim().to_ascii_uppercase()
+}
+
+/// Makes the key a name is indexed by, names with the same key are the same company
+pub(super) fn name_key(name : &str) -> String {
+    name.trim().to_lowercase()
+}
+
+
+
 impl SaveData for CompanyManager {
     /// Gets the Data of the Company manager in String form
     fn get_data(&self) -> String {
@@ -137,12 +848,88 @@ impl SaveData for CompanyManager {
             data.push('\n');
         }
         //removes the last '\n'
-        if data.len() > 0 {
+        if !data.is_empty() {
             data.pop();
         }
         //Return the data
         data
     }
+
+    /// Saves every company on its own line, followed by the lines of its open orders and quotes
+    /// The news is saved last, one line each
+    fn save_data(&self) -> String {
+        let mut lines : Vec<String> = Vec::new();
+        for company in self.companies() {
+            lines.push(company.save_data());
+
+            let orders = company.order_book().save_data();
+            if !orders.is_empty() {
+                lines.push(orders);
+            }
+        }
+        for event in self.news() {
+            lines.push(event.save_data());
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Loads the Company Manager from the saved companies
+impl LoadData for CompanyManager {
+    fn load_data(data : &str) -> Result<CompanyManager, String> {
+        let mut company_manager = CompanyManager::new();
+
+        for line in data.lines().filter(|line| !line.is_empty()) {
+            //Orders belong to the company above them
+            if line.starts_with("order\t") {
+                let order = match Order::load_data(line) {
+                    Ok(order) => order,
+                    Err(error) => return Err(error),
+                };
+
+                match company_manager.companies.last_mut() {
+                    Some(company) if company.id().equals(order.company_id()) => company.order_book_mut().add(order),
+                    _ => return Err(format!("Order {} is not saved under its company", order.id())),
+                }
+                continue;
+            }
+
+            //So do the market makers quotes
+            if line.starts_with("quote\t") {
+                let quote = match Quote::load_data(line) {
+                    Ok(quote) => quote,
+                    Err(error) => return Err(error),
+                };
+
+                match company_manager.companies.last_mut() {
+                    Some(company) => company.order_book_mut().add_quote(quote),
+                    None => return Err(String::from("A quote is saved before any company")),
+                }
+                continue;
+            }
+
+            //News isn't about a single company
+            if line.starts_with("news\t") {
+                match NewsEvent::load_data(line) {
+                    Ok(event) => company_manager.add_news(event),
+                    Err(error) => return Err(error),
+                }
+                continue;
+            }
+
+            let company = match Company::load_data(line) {
+                Ok(company) => company,
+                Err(error) => return Err(error),
+            };
+            if let Err(error) = company_manager.check_name(company.name()) {
+                return Err(error);
+            }
+            company_manager.add_company(company);
+        }
+
+        Ok(company_manager)
+    }
 }
 
 
@@ -0,0 +1,53 @@
+use rand::RngCore;
+
+use super::company::Company;
+use super::price_model::standard_normal;
+
+/// How far the whole market moves every price each tick, as a fraction of the price
+pub const MARKET_VOLATILITY : f32 = 0.015;
+/// How far a sector moves the prices of its companies each tick, as a fraction of the price
+pub const SECTOR_VOLATILITY : f32 = 0.02;
+
+/// The random moves every company of a tick shares
+/// Each price moves by the market factor, the factor of its sector and the noise of its own model
+pub struct MarketFactors {
+    market : f32,
+    /// The factor of each sector, in the order the sectors first appear
+    sectors : Vec<(String, f32)>,
+}
+
+impl MarketFactors {
+    /// Draws the market factor and one factor for each sector of the companies
+    pub fn draw(companies : &[Company], rng : &mut dyn RngCore) -> MarketFactors {
+        let market = standard_normal(rng);
+
+        let mut sectors : Vec<(String, f32)> = Vec::new();
+        for company in companies {
+            if !sectors.iter().any(|(sector, _factor)| sector == company.sector()) {
+                sectors.push((company.sector().clone(), standard_normal(rng)));
+            }
+        }
+
+        MarketFactors { market, sectors }
+    }
+
+    /// Gets the market factor, from the standard normal distribution
+    pub fn market(&self) -> f32 {
+        self.market
+    }
+
+    /// Gets the factor of a sector, 0 for a sector no company is in
+    pub fn sector(&self, sector : &str) -> f32 {
+        match self.sectors.iter().find(|(name, _factor)| name == sector) {
+            Some((_name, factor)) => *factor,
+            None => 0.0,
+        }
+    }
+
+    /// Gets what the factors multiply the price of a company in the sector by
+    /// Corrected for volatility, so on average they leave the price where it was
+    pub fn shock(&self, sector : &str) -> f32 {
+        let variance = MARKET_VOLATILITY * MARKET_VOLATILITY + SECTOR_VOLATILITY * SECTOR_VOLATILITY;
+        (MARKET_VOLATILITY * self.market() + SECTOR_VOLATILITY * self.sector(sector) - variance / 2.0).exp()
+    }
+}
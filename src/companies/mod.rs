@@ -1,3 +1,9 @@
 pub mod company_manager;
 pub mod company;
-pub mod stock;
\ No newline at end of file
+pub mod stock;
+pub mod order_book;
+pub mod protection;
+pub mod price_model;
+pub mod news;
+pub mod market_factors;
+pub mod catalogue;
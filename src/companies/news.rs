@@ -0,0 +1,220 @@
+use rand::{Rng, RngCore};
+use serde::Serialize;
+
+use crate::data::data_saving::{SaveData, LoadData, Record};
+use crate::id::ID;
+use super::company::Company;
+
+/// The chance each tick that news breaks
+pub const NEWS_CHANCE : f32 = 0.1;
+/// The most news kept, older stories are forgotten once they stop moving prices
+pub const MAX_NEWS : usize = 20;
+
+/// What the news is about
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewsKind {
+    /// A company made more money than expected
+    EarningsBeat,
+    /// A company made less money than expected
+    EarningsMiss,
+    /// A company is caught doing something it shouldn't
+    Scandal,
+    /// A company launches a new product
+    ProductLaunch,
+    /// Every company in a sector rises together
+    SectorRally,
+}
+
+impl NewsKind {
+    /// Gets the kind as it is saved
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NewsKind::EarningsBeat => "earnings_beat",
+            NewsKind::EarningsMiss => "earnings_miss",
+            NewsKind::Scandal => "scandal",
+            NewsKind::ProductLaunch => "product_launch",
+            NewsKind::SectorRally => "sector_rally",
+        }
+    }
+
+    /// Reads a saved kind
+    pub fn parse(text : &str) -> Result<NewsKind, String> {
+        match text {
+            "earnings_beat" => Ok(NewsKind::EarningsBeat),
+            "earnings_miss" => Ok(NewsKind::EarningsMiss),
+            "scandal" => Ok(NewsKind::Scandal),
+            "product_launch" => Ok(NewsKind::ProductLaunch),
+            "sector_rally" => Ok(NewsKind::SectorRally),
+            other => Err(format!("Unknown news kind '{}'", other)),
+        }
+    }
+}
+
+/// A story that moves the prices of the companies it is about
+/// It jumps their prices on the tick it breaks, then adds to their drift while it lasts
+#[derive(Clone, Debug)]
+pub struct NewsEvent {
+    kind : NewsKind,
+    company_ids : Vec<ID>,
+    headline : String,
+    /// The tick the news broke on
+    tick : u64,
+    /// The fraction the price jumps by when it breaks
+    jump : f32,
+    /// Added to the drift of the price each tick it lasts
+    drift : f32,
+    /// The ticks it lasts, counting the one it broke on
+    duration : u64,
+}
+
+impl NewsEvent {
+    /// Makes news about the companies
+    pub fn new(kind : NewsKind, company_ids : Vec<ID>, headline : String, tick : u64, jump : f32, drift : f32, duration : u64) -> NewsEvent {
+        NewsEvent { kind, company_ids, headline, tick, jump, drift, duration }
+    }
+
+    /// Gets what the news is about
+    pub fn kind(&self) -> NewsKind {
+        self.kind
+    }
+
+    /// Gets the IDs of the companies it moves
+    pub fn company_ids(&self) -> &Vec<ID> {
+        &self.company_ids
+    }
+
+    /// Gets the headline players read
+    pub fn headline(&self) -> &String {
+        &self.headline
+    }
+
+    /// Gets the tick the news broke on
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Gets the fraction the price jumped by when it broke
+    pub fn jump(&self) -> f32 {
+        self.jump
+    }
+
+    /// Gets what it adds to the drift each tick it lasts
+    pub fn drift(&self) -> f32 {
+        self.drift
+    }
+
+    /// Gets the ticks it lasts
+    pub fn duration(&self) -> u64 {
+        self.duration
+    }
+
+    /// Checks if the news moves the company
+    pub fn affects(&self, company_id : ID) -> bool {
+        self.company_ids.iter().any(|id| id.equals(company_id))
+    }
+
+    /// Checks if the news still moves prices on the tick
+    pub fn active(&self, tick : u64) -> bool {
+        tick >= self.tick && tick < self.tick + self.duration
+    }
+}
+
+/// Saves the news as a single record, the companies IDs split by ','
+impl SaveData for NewsEvent {
+    fn get_data(&self) -> String {
+        let company_ids : Vec<String> = self.company_ids
+            .iter()
+            .map(|company_id| company_id.to_string())
+            .collect();
+
+        Record::new("news")
+            .with("kind", self.kind().as_str())
+            .with("company_ids", company_ids.join(","))
+            .with("headline", self.headline())
+            .with("tick", self.tick())
+            .with("jump", self.jump())
+            .with("drift", self.drift())
+            .with("duration", self.duration())
+            .get_data()
+    }
+}
+
+/// Loads news from its record
+impl LoadData for NewsEvent {
+    fn load_data(data : &str) -> Result<NewsEvent, String> {
+        let record = match Record::load_kind(data, "news") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let kind = match record.get::<String>("kind") {
+            Ok(kind_text) => match NewsKind::parse(&kind_text) {
+                Ok(kind) => kind,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let company_ids_text : String = match record.get("company_ids") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let mut company_ids : Vec<ID> = Vec::new();
+        for company_id in company_ids_text.split(',').filter(|company_id| !company_id.is_empty()) {
+            match company_id.parse::<usize>() {
+                Ok(company_id) => company_ids.push(ID::load(company_id)),
+                Err(_error) => return Err(format!("Could not parse company ID '{}' in news", company_id)),
+            }
+        }
+        let headline : String = match record.get("headline") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let tick : u64 = match record.get("tick") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let jump : f32 = match record.get("jump") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let drift : f32 = match record.get("drift") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let duration : u64 = match record.get("duration") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        Ok(NewsEvent { kind, company_ids, headline, tick, jump, drift, duration })
+    }
+}
+
+/// Maybe breaks news about the companies on the tick
+/// Returns None on the ticks nothing happens
+pub fn draw_news(companies : &[Company], tick : u64, rng : &mut dyn RngCore) -> Option<NewsEvent> {
+    if companies.is_empty() || rng.gen::<f32>() >= NEWS_CHANCE {
+        return None;
+    }
+
+    //Every kind but a rally is about a single company, a rally is about its sector
+    let company = &companies[rng.gen_range(0..companies.len())];
+    let name = company.name();
+    let event = match rng.gen_range(0..5) {
+        0 => NewsEvent::new(NewsKind::EarningsBeat, vec![company.id()], format!("{} beats earnings expectations", name), tick, rng.gen_range(0.03..0.08), 0.005, 5),
+        1 => NewsEvent::new(NewsKind::EarningsMiss, vec![company.id()], format!("{} misses earnings expectations", name), tick, -rng.gen_range(0.03..0.08), -0.005, 5),
+        2 => NewsEvent::new(NewsKind::Scandal, vec![company.id()], format!("Scandal rocks {}", name), tick, -rng.gen_range(0.08..0.2), -0.01, 10),
+        3 => NewsEvent::new(NewsKind::ProductLaunch, vec![company.id()], format!("{} launches a new product", name), tick, rng.gen_range(0.0..0.04), 0.01, 10),
+        _ => {
+            let company_ids = companies
+                .iter()
+                .filter(|other| other.sector() == company.sector())
+                .map(|other| other.id())
+                .collect();
+            NewsEvent::new(NewsKind::SectorRally, company_ids, format!("{} stocks rally", company.sector()), tick, rng.gen_range(0.02..0.05), 0.003, 5)
+        },
+    };
+
+    Some(event)
+}
@@ -0,0 +1,460 @@
+use serde::{Serialize, Deserialize};
+
+use crate::data::data_saving::{SaveData, LoadData, Record};
+use crate::id::ID;
+
+/// How far from the fair price the market maker buys and sells, as a fraction of it
+const MARKET_MAKER_SPREAD : f32 = 0.01;
+/// How many shares the market maker offers on each side every tick
+const MARKET_MAKER_DEPTH : usize = 50;
+
+/// Which way a trade goes
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+impl TradeSide {
+    /// Gets the side as it is saved
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+        }
+    }
+
+    /// Reads a saved side
+    pub fn parse(text : &str) -> Result<TradeSide, String> {
+        match text {
+            "buy" => Ok(TradeSide::Buy),
+            "sell" => Ok(TradeSide::Sell),
+            other => Err(format!("Unknown trade side '{}'", other)),
+        }
+    }
+}
+
+/// An order that waits until the price crosses its limit
+/// Buys fill once the price is at or below the limit, sells once it is at or above it
+#[derive(Clone, Debug)]
+pub struct Order {
+    id : ID,
+    company_id : ID,
+    user_id : ID,
+    side : TradeSide,
+    amount : usize,
+    limit_price : f32,
+}
+
+impl Order {
+    /// Makes a new order
+    pub fn new(company_id : ID, user_id : ID, side : TradeSide, amount : usize, limit_price : f32) -> Order {
+        Order { id : ID::new(), company_id, user_id, side, amount, limit_price }
+    }
+
+    /// Gets the ID of the order
+    pub fn id(&self) -> ID {
+        self.id
+    }
+
+    /// Gets the ID of the company the order trades
+    pub fn company_id(&self) -> ID {
+        self.company_id
+    }
+
+    /// Gets the ID of the user that placed the order
+    pub fn user_id(&self) -> ID {
+        self.user_id
+    }
+
+    /// Gets if the order buys or sells
+    pub fn side(&self) -> TradeSide {
+        self.side
+    }
+
+    /// Gets the amount of shares
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Gets the worst price the order will trade at
+    pub fn limit_price(&self) -> f32 {
+        self.limit_price
+    }
+
+    /// Gets the money a buy order holds back, the most it could cost
+    pub fn reserved_money(&self) -> f32 {
+        match self.side {
+            TradeSide::Buy => self.limit_price * self.amount as f32,
+            TradeSide::Sell => 0.0,
+        }
+    }
+
+    /// Checks if the order would trade at the price
+    pub fn crosses(&self, price : f32) -> bool {
+        match self.side {
+            TradeSide::Buy => price <= self.limit_price,
+            TradeSide::Sell => price >= self.limit_price,
+        }
+    }
+
+    /// Takes shares off the order for a partial fill, they keep the orders ID and limit
+    pub fn split_off(&mut self, amount : usize) -> Order {
+        self.amount -= amount;
+        Order { amount, ..self.clone() }
+    }
+}
+
+/// Gets the other side of a trade
+fn opposite(side : TradeSide) -> TradeSide {
+    match side {
+        TradeSide::Buy => TradeSide::Sell,
+        TradeSide::Sell => TradeSide::Buy,
+    }
+}
+
+/// Checks if a price is better than another for whoever trades on the side
+fn is_better(side : TradeSide, price : f32, other : f32) -> bool {
+    match side {
+        TradeSide::Buy => price < other,
+        TradeSide::Sell => price > other,
+    }
+}
+
+/// An offer from the market maker, it always has the money and shares to trade
+#[derive(Clone, Debug)]
+pub struct Quote {
+    side : TradeSide,
+    amount : usize,
+    price : f32,
+}
+
+impl Quote {
+    /// Gets if the market maker buys or sells
+    pub fn side(&self) -> TradeSide {
+        self.side
+    }
+
+    /// Gets the shares left in the quote
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Gets the price the market maker trades at
+    pub fn price(&self) -> f32 {
+        self.price
+    }
+}
+
+/// Saves the quote as a single record
+impl SaveData for Quote {
+    fn get_data(&self) -> String {
+        Record::new("quote")
+            .with("side", self.side().as_str())
+            .with("amount", self.amount())
+            .with("price", self.price())
+            .get_data()
+    }
+}
+
+/// Loads a quote from its record
+impl LoadData for Quote {
+    fn load_data(data : &str) -> Result<Quote, String> {
+        let record = match Record::load_kind(data, "quote") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let side = match record.get::<String>("side") {
+            Ok(side_text) => match TradeSide::parse(&side_text) {
+                Ok(side) => side,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let amount : usize = match record.get("amount") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let price : f32 = match record.get("price") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        Ok(Quote { side, amount, price })
+    }
+}
+
+/// Shares that traded against the book
+pub struct Match {
+    /// The price of the resting order or quote
+    pub price : f32,
+    pub amount : usize,
+    /// The shares taken from a users order, none when the market maker traded
+    pub order : Option<Order>,
+}
+
+/// Saves the order as a single record
+impl SaveData for Order {
+    fn get_data(&self) -> String {
+        Record::new("order")
+            .with("id", self.id())
+            .with("company_id", self.company_id())
+            .with("user_id", self.user_id())
+            .with("side", self.side().as_str())
+            .with("amount", self.amount())
+            .with("limit", self.limit_price())
+            .get_data()
+    }
+}
+
+/// Loads an order from its record
+impl LoadData for Order {
+    fn load_data(data : &str) -> Result<Order, String> {
+        let record = match Record::load_kind(data, "order") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let id : usize = match record.get("id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let company_id : usize = match record.get("company_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let user_id : usize = match record.get("user_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let side = match record.get::<String>("side") {
+            Ok(side_text) => match TradeSide::parse(&side_text) {
+                Ok(side) => side,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let amount : usize = match record.get("amount") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let limit_price : f32 = match record.get("limit") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        Ok(Order {
+            id : ID::load(id),
+            company_id : ID::load(company_id),
+            user_id : ID::load(user_id),
+            side,
+            amount,
+            limit_price,
+        })
+    }
+}
+
+/// The open orders of one company, oldest first, and the market makers quotes in exchange mode
+#[derive(Clone, Debug)]
+pub struct OrderBook {
+    orders : Vec<Order>,
+    quotes : Vec<Quote>,
+}
+
+impl OrderBook {
+    /// Makes an empty order book
+    pub fn new() -> OrderBook {
+        OrderBook { orders : Vec::new(), quotes : Vec::new() }
+    }
+
+    /// Gets the market makers quotes
+    pub fn quotes(&self) -> &Vec<Quote> {
+        &self.quotes
+    }
+
+    /// Adds a saved quote of the market maker
+    pub fn add_quote(&mut self, quote : Quote) {
+        self.quotes.push(quote);
+    }
+
+    /// Replaces the market makers quotes with a bid and an ask around the fair price
+    /// It never offers more shares than the house holds
+    pub fn requote(&mut self, fair_price : f32, house_shares : usize) {
+        self.quotes = vec![
+            Quote { side : TradeSide::Buy, amount : MARKET_MAKER_DEPTH, price : fair_price * (1.0 - MARKET_MAKER_SPREAD) },
+            Quote { side : TradeSide::Sell, amount : MARKET_MAKER_DEPTH.min(house_shares), price : fair_price * (1.0 + MARKET_MAKER_SPREAD) },
+        ];
+    }
+
+    /// Removes the market makers quotes
+    pub fn clear_quotes(&mut self) {
+        self.quotes.clear();
+    }
+
+    /// Trades an incoming order against the best price in the book, the oldest order first at equal prices
+    /// The market maker steps back at equal prices, so users trade with each other first
+    /// Orders of the same user are skipped, the order is only read, the caller takes the shares off it
+    pub fn take_best(&mut self, incoming : &Order) -> Option<Match> {
+        let side = opposite(incoming.side());
+
+        //The oldest order at the best price, orders are already oldest first
+        let mut best_order : Option<usize> = None;
+        for (pos, order) in self.orders.iter().enumerate() {
+            if order.side() != side || order.user_id().equals(incoming.user_id()) || !incoming.crosses(order.limit_price()) {
+                continue;
+            }
+            match best_order {
+                Some(best) if !is_better(incoming.side(), order.limit_price(), self.orders[best].limit_price()) => (),
+                _ => best_order = Some(pos),
+            }
+        }
+
+        let best_quote = self.quotes
+            .iter()
+            .position(|quote| quote.side() == side && quote.amount() > 0 && incoming.crosses(quote.price()));
+
+        //Takes the users order unless the quote is strictly better
+        let take_quote = match (best_order, best_quote) {
+            (Some(order), Some(quote)) => is_better(incoming.side(), self.quotes[quote].price(), self.orders[order].limit_price()),
+            (None, Some(_quote)) => true,
+            _ => false,
+        };
+
+        if take_quote {
+            let pos = match best_quote {
+                Some(pos) => pos,
+                None => return None,
+            };
+            let quote = &mut self.quotes[pos];
+            let amount = incoming.amount().min(quote.amount());
+            quote.amount -= amount;
+            return Some(Match { price : quote.price(), amount, order : None });
+        }
+
+        let pos = match best_order {
+            Some(pos) => pos,
+            None => return None,
+        };
+        let amount = incoming.amount().min(self.orders[pos].amount());
+        let taken = self.orders[pos].split_off(amount);
+        if self.orders[pos].amount() == 0 {
+            self.orders.remove(pos);
+        }
+        Some(Match { price : taken.limit_price(), amount, order : Some(taken) })
+    }
+
+    /// Trades the oldest resting order the market makers quotes cross, at the quotes price
+    pub fn take_quoted(&mut self) -> Option<Match> {
+        for pos in 0..self.orders.len() {
+            let side = opposite(self.orders[pos].side());
+            let order = &self.orders[pos];
+            let quote = match self.quotes.iter_mut().find(|quote| quote.side() == side && quote.amount() > 0 && order.crosses(quote.price())) {
+                Some(quote) => quote,
+                None => continue,
+            };
+
+            let amount = order.amount().min(quote.amount());
+            quote.amount -= amount;
+            let price = quote.price();
+
+            let taken = self.orders[pos].split_off(amount);
+            if self.orders[pos].amount() == 0 {
+                self.orders.remove(pos);
+            }
+            return Some(Match { price, amount, order : Some(taken) });
+        }
+
+        None
+    }
+
+    /// Puts back shares taken from the book that couldn't trade, against an incoming order on the side
+    /// An order goes back to its place by age, joining what is left of it if it was split
+    pub fn put_back(&mut self, matched : Match, incoming_side : TradeSide) {
+        let order = match matched.order {
+            Some(order) => order,
+            None => {
+                let side = opposite(incoming_side);
+                if let Some(quote) = self.quotes.iter_mut().find(|quote| quote.side() == side && quote.price() == matched.price) {
+                    quote.amount += matched.amount;
+                }
+                return;
+            },
+        };
+
+        match self.orders.iter_mut().find(|other| other.id().equals(order.id())) {
+            Some(other) => other.amount += order.amount(),
+            None => {
+                //IDs are handed out in order, so older orders have smaller ones
+                let pos = self.orders.iter().position(|other| other.id().value() > order.id().value()).unwrap_or(self.orders.len());
+                self.orders.insert(pos, order);
+            },
+        }
+    }
+
+    /// Gets what an order would cost, or make, if it traded straight away in full
+    /// None when the book can't fill all of it
+    pub fn cost_to_fill(&self, incoming : &Order) -> Option<f32> {
+        let mut book = self.clone();
+        let mut remaining = incoming.clone();
+        let mut total = 0.0;
+
+        while remaining.amount() > 0 {
+            match book.take_best(&remaining) {
+                Some(matched) => {
+                    total += matched.price * matched.amount as f32;
+                    remaining.split_off(matched.amount);
+                },
+                None => return None,
+            }
+        }
+
+        Some(total)
+    }
+
+    /// Gets every open order
+    pub fn orders(&self) -> &Vec<Order> {
+        &self.orders
+    }
+
+    /// Adds an order to the back of the book
+    pub fn add(&mut self, order : Order) {
+        self.orders.push(order);
+    }
+
+    /// Takes an order out of the book
+    pub fn remove(&mut self, order_id : ID) -> Option<Order> {
+        match self.orders.iter().position(|order| order.id().equals(order_id)) {
+            Some(pos) => Some(self.orders.remove(pos)),
+            None => None,
+        }
+    }
+
+    /// Takes out the oldest order that trades at the price
+    pub fn take_crossed(&mut self, price : f32) -> Option<Order> {
+        match self.orders.iter().position(|order| order.crosses(price)) {
+            Some(pos) => Some(self.orders.remove(pos)),
+            None => None,
+        }
+    }
+
+    /// Removes every order and quote
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.quotes.clear();
+    }
+}
+
+/// Saves every order on its own line, then every quote
+impl SaveData for OrderBook {
+    fn get_data(&self) -> String {
+        let lines : Vec<String> = self.orders
+            .iter()
+            .map(|order| order.get_data())
+            .chain(self.quotes.iter().map(|quote| quote.get_data()))
+            .collect();
+
+        lines.join("\n")
+    }
+}
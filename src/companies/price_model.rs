@@ -0,0 +1,232 @@
+use rand::{Rng, RngCore};
+
+use crate::data::data_saving::Record;
+
+/// The lowest price a model moves a company to, so it never reaches 0
+pub const MIN_PRICE : f32 = 0.01;
+
+/// Decides how the price of a company moves each tick
+/// Drift and volatility are per tick, as fractions of the price for every model but the uniform walk
+pub trait PriceModel : std::fmt::Debug + Send + Sync {
+    /// Gets the name the model is saved and picked by
+    fn name(&self) -> &'static str;
+
+    /// Gets the parameters of the model with their names, to save and show them
+    fn parameters(&self) -> Vec<(&'static str, f32)>;
+
+    /// Gets the price one tick after the current one
+    fn next_price(&self, price : f32, rng : &mut dyn RngCore) -> f32;
+}
+
+/// Gets a random number from the standard normal distribution (Box-Muller)
+pub fn standard_normal(rng : &mut dyn RngCore) -> f32 {
+    let u1 : f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2 : f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+/// Adds the same random amount of money in either direction whatever the price, the original walk
+#[derive(Debug)]
+pub struct UniformWalk {
+    /// Dollars added every tick
+    drift : f32,
+    /// The most dollars the price moves either way in a tick
+    volatility : f32,
+}
+
+impl UniformWalk {
+    /// Makes a walk that drifts and swings by dollars
+    pub fn new(drift : f32, volatility : f32) -> UniformWalk {
+        UniformWalk { drift, volatility }
+    }
+}
+
+impl PriceModel for UniformWalk {
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f32)> {
+        vec![("drift", self.drift), ("volatility", self.volatility)]
+    }
+
+    fn next_price(&self, price : f32, rng : &mut dyn RngCore) -> f32 {
+        let change = match self.volatility > 0.0 {
+            true => rng.gen_range(-self.volatility..self.volatility),
+            false => 0.0,
+        };
+        price + self.drift + change
+    }
+}
+
+/// Moves the price by a random percentage, so cheap and expensive stocks swing alike and never go below 0
+#[derive(Debug)]
+pub struct GeometricBrownian {
+    drift : f32,
+    volatility : f32,
+}
+
+impl GeometricBrownian {
+    /// Makes a walk that drifts and swings by fractions of the price
+    pub fn new(drift : f32, volatility : f32) -> GeometricBrownian {
+        GeometricBrownian { drift, volatility }
+    }
+}
+
+impl PriceModel for GeometricBrownian {
+    fn name(&self) -> &'static str {
+        "geometric_brownian"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f32)> {
+        vec![("drift", self.drift), ("volatility", self.volatility)]
+    }
+
+    fn next_price(&self, price : f32, rng : &mut dyn RngCore) -> f32 {
+        let shock = self.volatility * standard_normal(rng);
+        price * (self.drift - self.volatility * self.volatility / 2.0 + shock).exp()
+    }
+}
+
+/// Pulls the price back towards a mean, with random swings on the way (Ornstein-Uhlenbeck on the log of the price)
+#[derive(Debug)]
+pub struct MeanReversion {
+    /// The price it is pulled towards
+    mean : f32,
+    /// How much of the way back to the mean it goes each tick, from 0 to 1
+    speed : f32,
+    volatility : f32,
+}
+
+impl MeanReversion {
+    /// Makes a walk pulled towards the mean price
+    pub fn new(mean : f32, speed : f32, volatility : f32) -> MeanReversion {
+        MeanReversion { mean, speed, volatility }
+    }
+}
+
+impl PriceModel for MeanReversion {
+    fn name(&self) -> &'static str {
+        "mean_reversion"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f32)> {
+        vec![("mean", self.mean), ("speed", self.speed), ("volatility", self.volatility)]
+    }
+
+    fn next_price(&self, price : f32, rng : &mut dyn RngCore) -> f32 {
+        let log_price = price.max(MIN_PRICE).ln();
+        let pull = self.speed * (self.mean.ln() - log_price);
+        (log_price + pull + self.volatility * standard_normal(rng)).exp()
+    }
+}
+
+/// Geometric Brownian motion that now and then jumps, like a surprise earnings report
+#[derive(Debug)]
+pub struct JumpDiffusion {
+    drift : f32,
+    volatility : f32,
+    /// The chance of a jump each tick, from 0 to 1
+    jump_chance : f32,
+    /// How big jumps are, as the volatility of the jump
+    jump_size : f32,
+}
+
+impl JumpDiffusion {
+    /// Makes a walk that jumps by the size at the chance each tick
+    pub fn new(drift : f32, volatility : f32, jump_chance : f32, jump_size : f32) -> JumpDiffusion {
+        JumpDiffusion { drift, volatility, jump_chance, jump_size }
+    }
+}
+
+impl PriceModel for JumpDiffusion {
+    fn name(&self) -> &'static str {
+        "jump_diffusion"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f32)> {
+        vec![("drift", self.drift), ("volatility", self.volatility), ("jump_chance", self.jump_chance), ("jump_size", self.jump_size)]
+    }
+
+    fn next_price(&self, price : f32, rng : &mut dyn RngCore) -> f32 {
+        let diffused = GeometricBrownian::new(self.drift, self.volatility).next_price(price, rng);
+        match rng.gen::<f32>() < self.jump_chance {
+            true => diffused * (self.jump_size * standard_normal(rng)).exp(),
+            false => diffused,
+        }
+    }
+}
+
+/// Gets the model companies use unless they are given another one
+pub fn default_price_model() -> Box<dyn PriceModel> {
+    Box::new(GeometricBrownian::new(0.0, 0.05))
+}
+
+/// Makes a model from its name and a record holding its parameters
+/// Parameters that aren't in the record get their defaults
+pub fn load_price_model(name : &str, record : &Record) -> Result<Box<dyn PriceModel>, String> {
+    let parameter = |parameter_name : &str, default : f32| -> Result<f32, String> {
+        match record.get_or(parameter_name, default) {
+            Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+            Ok(value) => Err(format!("The {} of a price model can't be {}", parameter_name, value)),
+            Err(error) => Err(error),
+        }
+    };
+
+    //Drift can be negative, so it is read on its own
+    let drift : f32 = match record.get_or("drift", 0.0) {
+        Ok(value) => value,
+        Err(error) => return Err(error),
+    };
+
+    match name {
+        "uniform" => {
+            let volatility = match parameter("volatility", 20.0) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            Ok(Box::new(UniformWalk::new(drift, volatility)))
+        },
+        "geometric_brownian" => {
+            let volatility = match parameter("volatility", 0.05) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            Ok(Box::new(GeometricBrownian::new(drift, volatility)))
+        },
+        "mean_reversion" => {
+            let mean = match parameter("mean", 100.0) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            if mean == 0.0 {
+                return Err(String::from("The mean of a price model must be above 0"));
+            }
+            let speed = match parameter("speed", 0.1) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            let volatility = match parameter("volatility", 0.05) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            Ok(Box::new(MeanReversion::new(mean, speed.min(1.0), volatility)))
+        },
+        "jump_diffusion" => {
+            let volatility = match parameter("volatility", 0.05) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            let jump_chance = match parameter("jump_chance", 0.02) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            let jump_size = match parameter("jump_size", 0.2) {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            };
+            Ok(Box::new(JumpDiffusion::new(drift, volatility, jump_chance.min(1.0), jump_size)))
+        },
+        other => Err(format!("Unknown price model '{}'", other)),
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Serialize, Deserialize};
+
+use crate::data::data_saving::{SaveData, LoadData, Record};
+use crate::id::ID;
+
+/// How a protective order decides to sell
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtectionKind {
+    /// Sells once the price falls to the trigger
+    StopLoss,
+    /// Sells once the price falls a distance below the highest price since it was placed
+    TrailingStop,
+    /// Sells once the price rises to the trigger
+    TakeProfit,
+}
+
+impl ProtectionKind {
+    /// Gets the kind as it is saved
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtectionKind::StopLoss => "stop_loss",
+            ProtectionKind::TrailingStop => "trailing_stop",
+            ProtectionKind::TakeProfit => "take_profit",
+        }
+    }
+
+    /// Reads a saved kind
+    pub fn parse(text : &str) -> Result<ProtectionKind, String> {
+        match text {
+            "stop_loss" => Ok(ProtectionKind::StopLoss),
+            "trailing_stop" => Ok(ProtectionKind::TrailingStop),
+            "take_profit" => Ok(ProtectionKind::TakeProfit),
+            other => Err(format!("Unknown protection kind '{}'", other)),
+        }
+    }
+}
+
+/// A protective sell order attached to the shares a user holds
+/// It holds nothing back, when it triggers it sells what is left to sell, up to its amount
+#[derive(Clone, Debug)]
+pub struct Protection {
+    id : ID,
+    company_id : ID,
+    user_id : ID,
+    kind : ProtectionKind,
+    amount : usize,
+    trigger_price : f32,
+    /// How far below the highest price a trailing stop follows, 0 for the other kinds
+    distance : f32,
+}
+
+impl Protection {
+    /// Makes a new protection at the current price of the company
+    /// Level is the trigger price, or the distance for a trailing stop
+    pub fn new(company_id : ID, user_id : ID, kind : ProtectionKind, amount : usize, level : f32, current_price : f32) -> Result<Protection, String> {
+        if !level.is_finite() || level <= 0.0 {
+            return Err(String::from("The trigger of a protection must be above 0"));
+        }
+
+        //A protection that would trigger straight away is a plain sell
+        let (trigger_price, distance) = match kind {
+            ProtectionKind::StopLoss if level >= current_price => return Err(format!("A stop-loss must be below the current price of {}$", current_price)),
+            ProtectionKind::TakeProfit if level <= current_price => return Err(format!("A take-profit must be above the current price of {}$", current_price)),
+            ProtectionKind::TrailingStop if level >= current_price => return Err(format!("A trailing stop must be closer than the current price of {}$", current_price)),
+            ProtectionKind::TrailingStop => (current_price - level, level),
+            _ => (level, 0.0),
+        };
+
+        Ok(Protection { id : ID::new(), company_id, user_id, kind, amount, trigger_price, distance })
+    }
+
+    /// Gets the ID of the protection
+    pub fn id(&self) -> ID {
+        self.id
+    }
+
+    /// Gets the ID of the company whose shares it sells
+    pub fn company_id(&self) -> ID {
+        self.company_id
+    }
+
+    /// Gets the ID of the user it protects
+    pub fn user_id(&self) -> ID {
+        self.user_id
+    }
+
+    /// Gets how the protection decides to sell
+    pub fn kind(&self) -> ProtectionKind {
+        self.kind
+    }
+
+    /// Gets the most shares it sells
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Gets the price it sells at
+    pub fn trigger_price(&self) -> f32 {
+        self.trigger_price
+    }
+
+    /// Gets how far a trailing stop stays below the highest price
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Moves a trailing stop up behind a new high, it never moves down
+    pub fn follow(&mut self, price : f32) {
+        if self.kind == ProtectionKind::TrailingStop && price - self.distance > self.trigger_price {
+            self.trigger_price = price - self.distance;
+        }
+    }
+
+    /// Checks if the protection sells at the price
+    pub fn triggered(&self, price : f32) -> bool {
+        match self.kind {
+            ProtectionKind::StopLoss | ProtectionKind::TrailingStop => price <= self.trigger_price,
+            ProtectionKind::TakeProfit => price >= self.trigger_price,
+        }
+    }
+}
+
+/// Saves the protection as a single record
+impl SaveData for Protection {
+    fn get_data(&self) -> String {
+        Record::new("protection")
+            .with("id", self.id())
+            .with("company_id", self.company_id())
+            .with("user_id", self.user_id())
+            .with("kind", self.kind().as_str())
+            .with("amount", self.amount())
+            .with("trigger", self.trigger_price())
+            .with("distance", self.distance())
+            .get_data()
+    }
+}
+
+/// Loads a protection from its record
+impl LoadData for Protection {
+    fn load_data(data : &str) -> Result<Protection, String> {
+        let record = match Record::load_kind(data, "protection") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let id : usize = match record.get("id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let company_id : usize = match record.get("company_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let user_id : usize = match record.get("user_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let kind = match record.get::<String>("kind") {
+            Ok(kind_text) => match ProtectionKind::parse(&kind_text) {
+                Ok(kind) => kind,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let amount : usize = match record.get("amount") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let trigger_price : f32 = match record.get("trigger") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let distance : f32 = match record.get("distance") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        Ok(Protection {
+            id : ID::load(id),
+            company_id : ID::load(company_id),
+            user_id : ID::load(user_id),
+            kind,
+            amount,
+            trigger_price,
+            distance,
+        })
+    }
+}
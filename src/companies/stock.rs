@@ -1,7 +1,8 @@
 
 
-use crate::data::data_saving::SaveData;
+use crate::data::data_saving::{SaveData, LoadData, Record};
 use super::company_manager::CompanyManager;
+use super::protection::Protection;
 use super::super::id::ID;
 
 
@@ -24,6 +25,11 @@ impl StockWallet {
         self.holders.clear();
     }
 
+    /// Gets the holders of each company the wallet has stock in
+    pub fn holders(&self) -> &Vec<StockHolder> {
+        &self.holders
+    }
+
     /// Gets the amount of stock in the wallet
     pub fn stock_amount(&self) -> usize {
         let mut stock_amount = 0;
@@ -70,22 +76,94 @@ impl StockWallet {
         }
     }
 
-    /// Sells a certain amount of stock from a company
-    /// Returns the amount of money made from selling
-    pub fn sell_stock(&mut self, company_manager : &CompanyManager, company_id : ID, sell_amount : usize) -> Result<f32, String> {
-        // Gets the holder of the stock
-        let holder_result = self.get_stock_holder_by_id_mut(company_id);
+    /// Holds back shares for a sell order, so they can't be sold twice
+    pub fn reserve_stock(&mut self, company_id : ID, amount : usize) -> Result<(), String> {
+        match self.get_stock_holder_by_id_mut(company_id) {
+            Ok(holder) => holder.reserve(amount),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Lets go of shares held back for a sell order
+    pub fn release_stock(&mut self, company_id : ID, amount : usize) {
+        if let Ok(holder) = self.get_stock_holder_by_id_mut(company_id) {
+            holder.reserved_amount = holder.reserved_amount.saturating_sub(amount);
+        }
+    }
+
+    /// Gets the amount of shares of a company that can be sold right now
+    pub fn available_amount(&self, company_id : ID) -> usize {
+        self.holders
+            .iter()
+            .find(|holder| holder.company_id().equals(company_id))
+            .map(|holder| holder.available_amount())
+            .unwrap_or(0)
+    }
 
-        match holder_result {
+    /// Attaches a protection to the holder of its company
+    pub fn protect(&mut self, protection : Protection) -> Result<(), String> {
+        let holder = match self.get_stock_holder_by_id_mut(protection.company_id()) {
+            Ok(holder) => holder,
             Err(error) => return Err(error),
-            _ => (),
         };
 
-        // Sells the stock
-        holder_result.unwrap().sell_stock(company_manager, sell_amount)
+        if protection.amount() > holder.stock_amount() {
+            return Err(format!("Protecting {} shares of {} but only {} are owned", protection.amount(), holder.company_name(), holder.stock_amount()));
+        }
+
+        holder.protections.push(protection);
+        Ok(())
     }
 
-    /// Gets a stock holder by the companies ID
+    /// Removes a protection from whichever holder has it
+    pub fn cancel_protection(&mut self, protection_id : ID) -> Result<Protection, String> {
+        for holder in self.holders.iter_mut() {
+            if let Some(pos) = holder.protections.iter().position(|protection| protection.id().equals(protection_id)) {
+                return Ok(holder.protections.remove(pos));
+            }
+        }
+        Err(format!("No protection with ID {} was found!", protection_id))
+    }
+
+    /// Gets every protection on the wallet
+    pub fn protections(&self) -> Vec<&Protection> {
+        self.holders
+            .iter()
+            .flat_map(|holder| holder.protections.iter())
+            .collect()
+    }
+
+    /// Moves the trailing stops with the current prices, then takes out every protection they trigger
+    pub fn trigger_protections(&mut self, company_manager : &CompanyManager) -> Vec<Protection> {
+        let mut triggered : Vec<Protection> = Vec::new();
+
+        for holder in self.holders.iter_mut() {
+            let price = match company_manager.get_company_by_id(holder.company_id()) {
+                Ok(company) => company.stock_price(),
+                Err(_error) => continue,
+            };
+
+            for protection in holder.protections.iter_mut() {
+                protection.follow(price);
+            }
+            let (fired, waiting) : (Vec<Protection>, Vec<Protection>) = holder.protections.drain(..).partition(|protection| protection.triggered(price));
+            holder.protections = waiting;
+            triggered.extend(fired);
+        }
+
+        triggered
+    }
+
+    /// Sells a certain amount of stock from a company at a traded price
+    /// Returns the amount of money made from selling
+    pub fn sell_stock_at(&mut self, company_id : ID, sell_amount : usize, price : f32) -> Result<f32, String> {
+        match self.get_stock_holder_by_id_mut(company_id) {
+            Ok(holder) => holder.sell_stock_at(sell_amount, price),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Gets a stock holder by the companies ID
     // fn get_stock_holder_by_id(&self, company_id : ID) -> Result<&StockHolder, String> {
     //     //Filters for all holders with the same ID
     //     let filtered : Vec<&StockHolder> = self.holders
@@ -129,11 +207,54 @@ impl SaveData for StockWallet {
         }
 
         //Removes the extra ','
-        if data.len() > 0 { data.pop(); }
+        if !data.is_empty() { data.pop(); }
 
         //Return the data
         data
     }
+
+    /// Saves every holder on its own line, followed by the lines of its protections
+    fn save_data(&self) -> String {
+        let mut lines : Vec<String> = Vec::new();
+        for holder in &self.holders {
+            lines.push(holder.save_data());
+            for protection in &holder.protections {
+                lines.push(protection.save_data());
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Loads the stock wallet from its saved holders
+impl LoadData for StockWallet {
+    fn load_data(data : &str) -> Result<StockWallet, String> {
+        let mut wallet = StockWallet::new();
+
+        for line in data.lines().filter(|line| !line.is_empty()) {
+            //Protections belong to the holder above them
+            if line.starts_with("protection\t") {
+                let protection = match Protection::load_data(line) {
+                    Ok(protection) => protection,
+                    Err(error) => return Err(error),
+                };
+
+                match wallet.holders.last_mut() {
+                    Some(holder) if holder.company_id().equals(protection.company_id()) => holder.protections.push(protection),
+                    _ => return Err(format!("Protection {} is not saved under its holder", protection.id())),
+                }
+                continue;
+            }
+
+            match StockHolder::load_data(line) {
+                Ok(holder) => wallet.holders.push(holder),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(wallet)
+    }
 }
 
 /// Prints the stock to the screen
@@ -150,18 +271,22 @@ impl std::fmt::Display for StockWallet {
         }
 
         //Removes the extra ','
-        if text.len() > 0 { text.pop(); }
+        if !text.is_empty() { text.pop(); }
 
         write!(f, "Company Holder:\n{}", text)
     }
 }
 
 ///Holds all a users shares of one stock
-struct StockHolder {
+pub struct StockHolder {
     company_name : String,
     company_id : ID,
     average_purchase_price : f32,
     stock_amount : usize,
+    /// Shares held back for open sell orders
+    reserved_amount : usize,
+    /// Stop-loss, trailing-stop and take-profit orders on the shares
+    protections : Vec<Protection>,
 }
 
 // Holds a stock
@@ -173,6 +298,8 @@ impl StockHolder {
             company_id, 
             average_purchase_price: 0.0, 
             stock_amount: 0,
+            reserved_amount: 0,
+            protections: Vec::new(),
         }
     }
 
@@ -204,6 +331,26 @@ impl StockHolder {
         self.stock_amount
     }
 
+    /// Gets the amount of shares held back for sell orders
+    pub fn reserved_amount(&self) -> usize {
+        self.reserved_amount
+    }
+
+    /// Gets the amount of shares that can be sold right now
+    pub fn available_amount(&self) -> usize {
+        self.stock_amount - self.reserved_amount
+    }
+
+    /// Holds back shares for a sell order
+    fn reserve(&mut self, amount : usize) -> Result<(), String> {
+        if self.available_amount() < amount {
+            return Err(String::from("Reserving more stock than is available to sell!"));
+        }
+
+        self.reserved_amount += amount;
+        Ok(())
+    }
+
     /// Adds a stock to the holder
     pub fn add_stock(&mut self, stock : Stock, buy_amount : usize) -> Result<(), String> {
         //Checks that the company ID's match
@@ -224,20 +371,15 @@ impl StockHolder {
         Ok(())
     }
 
-    /// Sells the amount of stock from the handler
+    /// Sells the amount of stock from the handler at a price
     /// Returns the amount of money made from selling
-    pub fn sell_stock(&mut self, company_manager : &CompanyManager, sell_amount : usize) -> Result<f32, String> {
-        // Ensures there is enough stock to sell
+    pub fn sell_stock_at(&mut self, sell_amount : usize, stock_price : f32) -> Result<f32, String> {
+        // Ensures there is enough stock to sell, shares held for orders don't count
         if self.stock_amount() < sell_amount {
             return Err(String::from("Selling more stock than currently owned!"));
         }
-        
-        //Gets the company (So it know it's stock price!)
-        let stock_price;
-
-        match company_manager.get_company_by_id(self.company_id()) {
-            Ok(company) => stock_price = company.stock_price(),
-            Err(error) => return Err(error),
+        if self.available_amount() < sell_amount {
+            return Err(String::from("Selling stock that is reserved for open orders!"));
         }
 
         //Removes (x) number of stocks
@@ -274,6 +416,61 @@ impl SaveData for StockHolder {
         //Return the data
         data
     }
+
+    /// Saves the holder with the company ID so it can be re-linked on load
+    fn save_data(&self) -> String {
+        Record::new("holder")
+            .with("company_id", self.company_id())
+            .with("amount", self.stock_amount())
+            .with("average_price", self.avg_purchase_price())
+            .with("company_name", self.company_name())
+            .with("reserved", self.reserved_amount())
+            .get_data()
+    }
+}
+
+/// Loads the holder from its saved data
+impl LoadData for StockHolder {
+    fn load_data(data : &str) -> Result<StockHolder, String> {
+        let record = match Record::load_kind(data, "holder") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let company_id : usize = match record.get("company_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let stock_amount : usize = match record.get("amount") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let average_purchase_price : f32 = match record.get("average_price") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let company_name : String = match record.get("company_name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        //Saves from before limit orders have nothing reserved
+        let reserved_amount : usize = match record.get_or("reserved", 0) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        if reserved_amount > stock_amount {
+            return Err(format!("Holder of {} has {} shares reserved but only {} owned", company_name, reserved_amount, stock_amount));
+        }
+
+        Ok(StockHolder {
+            company_name,
+            company_id : ID::load(company_id),
+            average_purchase_price,
+            stock_amount,
+            reserved_amount,
+            protections : Vec::new(),
+        })
+    }
 }
 
 /// Prints the stock to the screen
@@ -309,7 +506,7 @@ impl Stock {
         }
     }
 
-    /// Getters
+    // Getters
     
     /// Get the ID of the stock
     pub fn id(&self) -> ID {
@@ -6,7 +6,20 @@ use std::fs::File;
 
 
 pub trait SaveData {
+    /// Gets the data sent to the clients
     fn get_data(&self) -> String;
+
+    /// Gets the data written to disk
+    /// Defaults to the client data, override it when the client data is missing fields
+    fn save_data(&self) -> String {
+        self.get_data()
+    }
+}
+
+/// The counterpart to SaveData
+/// Rebuilds the type from the text made by SaveData::save_data
+pub trait LoadData : Sized {
+    fn load_data(data : &str) -> Result<Self, String>;
 }
 
 /// Reads the file into a string
@@ -19,30 +32,165 @@ pub fn read_from_file(filename : &str) -> Result<String, String> {
     //Reads the contents
     let mut contents : String = String::new();
     match file.read_to_string(&mut contents) {
-        Err(error) => return Err(error.to_string()),
+        Err(error) => Err(error.to_string()),
         _ => Ok(contents),
     }
 }
 
-// Saves data to a file
-// pub fn save_to_file(filename : &str, data : &String) -> Result<(), String> {
-//     //Creates the file
-//     let file_result = File::create(filename);
-    
-//     //Opens the file
-//     let mut file;
-//     match file_result {
-//         Ok(new_file) => file = new_file,
-//         Err(error) => return Err(error.to_string()),
-//     }
-//     //Writes all the data to the file
-//     let write_result = file.write(data.as_bytes());
-
-//     //Checks that the write result is fully valid
-//     match write_result {
-//         Ok(value) => (if value == 0 {return Err(String::from("Unknown error writing to file"))}),
-//         Err(error) => return Err(error.to_string()),
-//     }
-
-//     Ok(())
-// }
\ No newline at end of file
+/// Reads the file into bytes
+pub fn read_bytes_from_file(filename : &str) -> Result<Vec<u8>, String> {
+    match std::fs::read(filename) {
+        Ok(bytes) => Ok(bytes),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Saves data to a file
+/// The data is written to a temporary file first so a crash never leaves a half written save
+pub fn save_to_file(filename : &str, data : &str) -> Result<(), String> {
+    let temp_filename = format!("{}.tmp", filename);
+
+    //Creates the temporary file
+    let mut file = match File::create(&temp_filename) {
+        Ok(file) => file,
+        Err(error) => return Err(error.to_string()),
+    };
+
+    //Writes all the data to the file
+    if let Err(error) = file.write_all(data.as_bytes()) {
+        return Err(error.to_string());
+    }
+
+    //Makes sure the data is on the disk before replacing the old save
+    if let Err(error) = file.sync_all() {
+        return Err(error.to_string());
+    }
+
+    //Replaces the old save
+    match std::fs::rename(&temp_filename, filename) {
+        Err(error) => Err(error.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// A single line of saved data
+/// Every value is saved with its name, so fields can be added without breaking older saves
+/// Written as "kind\tname=value\tname=value"
+pub struct Record {
+    kind : String,
+    fields : Vec<(String, String)>,
+}
+
+impl Record {
+    /// Makes an empty record
+    pub fn new(kind : &str) -> Record {
+        Record { kind : kind.to_string(), fields : Vec::new() }
+    }
+
+    /// Adds a field to the record
+    pub fn with<T : ToString>(mut self, name : &str, value : T) -> Record {
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Gets the kind of the record
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Checks if the record has a field
+    pub fn has(&self, name : &str) -> bool {
+        self.fields.iter().any(|(field_name, _value)| field_name == name)
+    }
+
+    /// Gets a field from the record
+    pub fn get<T : std::str::FromStr>(&self, name : &str) -> Result<T, String> {
+        let text = match self.fields.iter().find(|(field_name, _value)| field_name == name) {
+            Some((_name, value)) => value,
+            None => return Err(format!("Missing field {} in {}", name, self.kind)),
+        };
+
+        match text.parse::<T>() {
+            Ok(value) => Ok(value),
+            Err(_error) => Err(format!("Could not parse {} from '{}' in {}", name, text, self.kind)),
+        }
+    }
+
+    /// Gets a field from the record, or the default if it was saved before the field existed
+    pub fn get_or<T : std::str::FromStr>(&self, name : &str, default : T) -> Result<T, String> {
+        if !self.has(name) {
+            return Ok(default);
+        }
+        self.get(name)
+    }
+
+    /// Loads a record, making sure it is the expected kind
+    pub fn load_kind(data : &str, kind : &str) -> Result<Record, String> {
+        match Record::load_data(data) {
+            Ok(record) if record.kind == kind => Ok(record),
+            Ok(record) => Err(format!("Expected a {}, found a {}", kind, record.kind)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl SaveData for Record {
+    fn get_data(&self) -> String {
+        let mut data = self.kind.clone();
+
+        for (name, value) in &self.fields {
+            data.push('\t');
+            data.push_str(name);
+            data.push('=');
+            //Escapes the value so it can never break the line
+            for c in value.chars() {
+                match c {
+                    '\\' => data.push_str("\\\\"),
+                    '\t' => data.push_str("\\t"),
+                    '\n' => data.push_str("\\n"),
+                    _ => data.push(c),
+                }
+            }
+        }
+
+        data
+    }
+}
+
+impl LoadData for Record {
+    fn load_data(data : &str) -> Result<Record, String> {
+        let mut parts = data.split('\t');
+
+        let mut record = match parts.next() {
+            Some(kind) if !kind.is_empty() => Record::new(kind),
+            _ => return Err(format!("Record has no kind: '{}'", data)),
+        };
+
+        for part in parts {
+            let (name, escaped) = match part.split_once('=') {
+                Some(split) => split,
+                None => return Err(format!("Field '{}' has no value in {}", part, record.kind)),
+            };
+
+            //Un-escapes the value
+            let mut value = String::new();
+            let mut chars = escaped.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    value.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('t') => value.push('\t'),
+                    Some('n') => value.push('\n'),
+                    Some(other) => value.push(other),
+                    None => return Err(format!("Field {} ends with an escape in {}", name, record.kind)),
+                }
+            }
+
+            record.fields.push((name.to_string(), value));
+        }
+
+        Ok(record)
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::path::Path;
+
+use super::data_saving::read_bytes_from_file;
+use super::migration;
+use super::snapshot::SAVE_DIRECTORY;
+use super::storage::Storage;
+
+const DATABASE_FILE : &str = "saves/game.db";
+
+/// Marks the start of the database file
+const MAGIC : &[u8; 4] = b"FSDB";
+/// Marks the start of every batch
+const BATCH_MARKER : u8 = b'B';
+/// The database is compacted once the file is this many times bigger than the live tables
+const COMPACT_RATIO : u64 = 4;
+/// Small databases are never compacted
+const COMPACT_MIN_SIZE : u64 = 1024 * 1024;
+
+/// The newest data of every table with the save version it was written in
+type Tables = HashMap<String, (u32, String)>;
+
+/// A batch of tables that were written together
+struct Batch {
+    version : u32,
+    tables : Vec<(String, String)>,
+    /// The size of the batch in the file
+    size : usize,
+}
+
+/// An embedded database kept in a single file
+/// Writes are appended as checksummed batches, so only the tables that changed are written
+/// A batch that was cut off by a crash fails its checksum and is dropped when the file is opened
+pub struct DatabaseStorage {
+    filename : String,
+    file : File,
+    tables : Tables,
+    /// The size of the file
+    file_size : u64,
+}
+
+impl DatabaseStorage {
+    /// Opens the default database file
+    pub fn open() -> Result<DatabaseStorage, String> {
+        //Makes sure the save folder exists
+        if let Err(error) = std::fs::create_dir_all(SAVE_DIRECTORY) {
+            return Err(error.to_string());
+        }
+
+        Self::open_file(DATABASE_FILE)
+    }
+
+    /// Opens a database file, creating it if needed
+    pub fn open_file(filename : &str) -> Result<DatabaseStorage, String> {
+        //A missing file is a new database
+        let bytes = if Path::new(filename).exists() {
+            match read_bytes_from_file(filename) {
+                Ok(bytes) => bytes,
+                Err(error) => return Err(error),
+            }
+        } else {
+            Vec::new()
+        };
+
+        //Reads every complete batch
+        let (tables, valid_size) = match read_batches(&bytes) {
+            Ok(result) => result,
+            Err(error) => return Err(format!("{}: {}", filename, error)),
+        };
+
+        let mut file = match OpenOptions::new().create(true).read(true).write(true).truncate(false).open(filename) {
+            Ok(file) => file,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        //Drops the batch a crash cut off, or writes the header to a new database
+        if valid_size == 0 {
+            if let Err(error) = file.set_len(0).and_then(|_| file.write_all(MAGIC)).and_then(|_| file.sync_data()) {
+                return Err(error.to_string());
+            }
+        } else if valid_size < bytes.len() as u64 {
+            println!("Dropping {} bytes of an unfinished batch from {}", bytes.len() as u64 - valid_size, filename);
+            if let Err(error) = file.set_len(valid_size) {
+                return Err(error.to_string());
+            }
+        }
+
+        let file_size = match file.seek(std::io::SeekFrom::End(0)) {
+            Ok(file_size) => file_size,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        Ok(DatabaseStorage { filename : filename.to_string(), file, tables, file_size })
+    }
+
+    /// Gets the size of the newest version of every table
+    fn live_size(&self) -> u64 {
+        self.tables
+            .iter()
+            .map(|(name, (_version, data))| (name.len() + data.len()) as u64)
+            .sum()
+    }
+
+    /// Re-writes the database with only the newest version of every table
+    fn compact(&mut self) -> Result<(), String> {
+        let temp_filename = format!("{}.tmp", self.filename);
+
+        let tables : Vec<(&str, u32, &str)> = self.tables
+            .iter()
+            .map(|(name, (version, data))| (name.as_str(), *version, data.as_str()))
+            .collect();
+
+        let mut bytes : Vec<u8> = MAGIC.to_vec();
+        for (name, version, data) in tables {
+            bytes.extend(encode_batch(version, &[(name, data)]));
+        }
+
+        let mut temp_file = match File::create(&temp_filename) {
+            Ok(file) => file,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        if let Err(error) = temp_file.write_all(&bytes).and_then(|_| temp_file.sync_all()) {
+            return Err(error.to_string());
+        }
+
+        if let Err(error) = std::fs::rename(&temp_filename, &self.filename) {
+            return Err(error.to_string());
+        }
+
+        //Re-opens the compacted file
+        match Self::open_file(&self.filename.clone()) {
+            Ok(database) => { *self = database; Ok(()) },
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl Storage for DatabaseStorage {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    fn read(&mut self, table : &str) -> Result<Option<String>, String> {
+        match self.tables.get(table) {
+            //Upgrades tables written by an older build
+            Some((version, data)) => match migration::migrate(*version, data) {
+                Ok(data) => Ok(Some(data)),
+                Err(error) => Err(format!("Table {}: {}", table, error)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn write(&mut self, tables : &[(&str, String)]) -> Result<(), String> {
+        let batch_tables : Vec<(&str, &str)> = tables
+            .iter()
+            .map(|(name, data)| (*name, data.as_str()))
+            .collect();
+        let batch = encode_batch(migration::SAVE_VERSION, &batch_tables);
+
+        //The batch only counts once it is fully on the disk
+        if let Err(error) = self.file.write_all(&batch).and_then(|_| self.file.sync_data()) {
+            return Err(format!("Could not write to the database: {}", error));
+        }
+        self.file_size += batch.len() as u64;
+
+        for (name, data) in tables {
+            self.tables.insert(name.to_string(), (migration::SAVE_VERSION, data.clone()));
+        }
+
+        //Keeps the file from growing forever
+        if self.file_size > COMPACT_MIN_SIZE && self.file_size > self.live_size() * COMPACT_RATIO {
+            return self.compact();
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns a batch of tables into bytes
+/// marker, save version, table count, (name length, name, data length, data)..., checksum
+fn encode_batch(version : u32, tables : &[(&str, &str)]) -> Vec<u8> {
+    let mut bytes : Vec<u8> = vec![BATCH_MARKER];
+    bytes.extend(version.to_le_bytes());
+    bytes.extend((tables.len() as u32).to_le_bytes());
+
+    for (name, data) in tables {
+        bytes.extend((name.len() as u32).to_le_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data.as_bytes());
+    }
+
+    let checksum = checksum(&bytes);
+    bytes.extend(checksum.to_le_bytes());
+    bytes
+}
+
+/// Reads every complete batch in the file
+/// Returns the newest version of every table and the size of the file that holds complete batches
+fn read_batches(bytes : &[u8]) -> Result<(Tables, u64), String> {
+    let mut tables : Tables = HashMap::new();
+
+    //A new database
+    if bytes.is_empty() {
+        return Ok((tables, 0));
+    }
+
+    if !bytes.starts_with(MAGIC) {
+        return Err(String::from("Not a fake_stocks database"));
+    }
+
+    let mut position = MAGIC.len();
+    while position < bytes.len() {
+        match decode_batch(&bytes[position..]) {
+            Some(batch) => {
+                for (name, data) in batch.tables {
+                    tables.insert(name, (batch.version, data));
+                }
+                position += batch.size;
+            },
+            //The rest of the file was never finished
+            None => break,
+        }
+    }
+
+    Ok((tables, position as u64))
+}
+
+/// Reads a single batch
+/// Returns None if the batch is cut off or fails its checksum
+fn decode_batch(bytes : &[u8]) -> Option<Batch> {
+    let mut reader = ByteReader { bytes, position : 0 };
+
+    match reader.take(1) {
+        Some(marker) if marker == [BATCH_MARKER] => (),
+        _ => return None,
+    }
+
+    let version = match reader.read_u32() {
+        Some(version) => version,
+        None => return None,
+    };
+    let count = match reader.read_u32() {
+        Some(count) => count,
+        None => return None,
+    };
+
+    let mut tables : Vec<(String, String)> = Vec::new();
+    for _ in 0..count {
+        let name = match reader.read_text() {
+            Some(name) => name,
+            None => return None,
+        };
+        let data = match reader.read_text() {
+            Some(data) => data,
+            None => return None,
+        };
+        tables.push((name, data));
+    }
+
+    //Checks that the batch wasn't damaged
+    let batch_end = reader.position;
+    let saved_checksum = match reader.take(8).map(|bytes| bytes.try_into()) {
+        Some(Ok(bytes)) => u64::from_le_bytes(bytes),
+        _ => return None,
+    };
+    if saved_checksum != checksum(&bytes[..batch_end]) {
+        return None;
+    }
+
+    Some(Batch { version, tables, size : reader.position })
+}
+
+/// Reads values from a batch
+struct ByteReader<'a> {
+    bytes : &'a [u8],
+    position : usize,
+}
+
+impl<'a> ByteReader<'a> {
+    /// Takes the next bytes, None if there aren't enough left
+    fn take(&mut self, length : usize) -> Option<&'a [u8]> {
+        let end = match self.position.checked_add(length) {
+            Some(end) => end,
+            None => return None,
+        };
+        let taken = match self.bytes.get(self.position..end) {
+            Some(taken) => taken,
+            None => return None,
+        };
+        self.position = end;
+        Some(taken)
+    }
+
+    /// Reads a little endian u32
+    fn read_u32(&mut self) -> Option<u32> {
+        match self.take(4).map(|bytes| bytes.try_into()) {
+            Some(Ok(bytes)) => Some(u32::from_le_bytes(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Reads text written after its length
+    fn read_text(&mut self) -> Option<String> {
+        let length = match self.read_u32() {
+            Some(length) => length as usize,
+            None => return None,
+        };
+        match self.take(length) {
+            Some(bytes) => String::from_utf8(bytes.to_vec()).ok(),
+            None => None,
+        }
+    }
+}
+
+/// FNV-1a hash of the bytes, catches batches that were only partly written
+fn checksum(bytes : &[u8]) -> u64 {
+    let mut hash : u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
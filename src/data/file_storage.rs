@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use super::data_saving::{read_from_file, save_to_file};
+use super::migration;
+use super::snapshot::SAVE_DIRECTORY;
+use super::storage::Storage;
+
+const SNAPSHOT_FILE : &str = "saves/snapshot.txt";
+
+/// Keeps every table in one text file
+/// The whole file is re-written on each save, best for small games
+pub struct FileStorage {
+    filename : String,
+    /// The tables read from the file, loaded on the first read
+    tables : Option<Vec<(String, String)>>,
+}
+
+impl FileStorage {
+    /// Makes the storage for the default snapshot file
+    pub fn new() -> FileStorage {
+        Self::with_file(SNAPSHOT_FILE)
+    }
+
+    /// Makes the storage for a specific file
+    pub fn with_file(filename : &str) -> FileStorage {
+        FileStorage { filename : filename.to_string(), tables : None }
+    }
+
+    /// Reads every table from the file
+    fn load_tables(&self) -> Result<Vec<(String, String)>, String> {
+        //Nothing has been saved yet
+        if !Path::new(&self.filename).exists() {
+            return Ok(Vec::new());
+        }
+
+        //Reads the file, upgrading it if an older build saved it
+        let data = match read_from_file(&self.filename) {
+            Ok(data) => match migration::upgrade(&data, "snapshot") {
+                Ok(data) => data,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+
+        //Every table starts with its section line
+        let mut tables : Vec<(String, String)> = Vec::new();
+        for line in data.lines() {
+            if let Some(name) = line.strip_prefix("section\tname=") {
+                tables.push((name.to_string(), String::new()));
+                continue;
+            }
+
+            match tables.last_mut() {
+                Some((_name, table)) => {
+                    if !table.is_empty() { table.push('\n'); }
+                    table.push_str(line);
+                },
+                None if line.is_empty() => (),
+                None => return Err(format!("Found '{}' before any section in {}", line, self.filename)),
+            }
+        }
+
+        Ok(tables)
+    }
+}
+
+impl Storage for FileStorage {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn read(&mut self, table : &str) -> Result<Option<String>, String> {
+        if self.tables.is_none() {
+            match self.load_tables() {
+                Ok(tables) => self.tables = Some(tables),
+                Err(error) => return Err(error),
+            }
+        }
+
+        let tables = self.tables.as_ref().unwrap();
+        Ok(tables.iter().find(|(name, _data)| name == table).map(|(_name, data)| data.clone()))
+    }
+
+    fn write(&mut self, tables : &[(&str, String)]) -> Result<(), String> {
+        //Makes sure the save folder exists
+        if let Err(error) = std::fs::create_dir_all(SAVE_DIRECTORY) {
+            return Err(error.to_string());
+        }
+
+        //Tables that aren't being written are kept
+        let mut all_tables = match self.tables.take() {
+            Some(all_tables) => all_tables,
+            None => match self.load_tables() {
+                Ok(all_tables) => all_tables,
+                Err(error) => return Err(error),
+            },
+        };
+
+        for (name, table) in tables {
+            match all_tables.iter_mut().find(|(existing, _data)| existing == name) {
+                Some((_name, data)) => *data = table.clone(),
+                None => all_tables.push((name.to_string(), table.clone())),
+            }
+        }
+
+        let mut data = migration::header("snapshot");
+        for (name, table) in &all_tables {
+            data.push_str(&format!("\nsection\tname={}\n{}", name, table));
+        }
+        data.push('\n');
+
+        //The file is replaced in one go, so every table is saved together
+        //If it fails the tables are read back from the file next time
+        match save_to_file(&self.filename, &data) {
+            Ok(_) => { self.tables = Some(all_tables); Ok(()) },
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::companies::company::Company;
+use crate::companies::news::NewsEvent;
+use crate::companies::order_book::{Order, TradeSide};
+use crate::companies::protection::Protection;
+use crate::id::ID;
+use crate::users::ranking::Ranker;
+use crate::users::user::User;
+use crate::users::trade_history::TradeKind;
+use super::data_saving::{SaveData, LoadData, Record, read_from_file};
+use super::migration;
+use super::snapshot::{Snapshot, SAVE_DIRECTORY};
+
+const JOURNAL_FILE : &str = "saves/journal.txt";
+
+/// A change to the game that happened after the last snapshot
+pub enum JournalEntry {
+    /// A new account, holds the users save data
+    NewUser(String),
+    /// A user bought stock (user ID, company ID, amount)
+    Buy(ID, ID, usize),
+    /// A user sold stock (user ID, company ID, amount)
+    Sell(ID, ID, usize),
+    /// The prices set by CompanyManager::update
+    Tick(Vec<(ID, f32)>),
+    /// A company was added or reset, holds the companies save data
+    CompanyState(String),
+    /// News broke, holds its save data
+    News(String),
+    /// A limit order was placed, holds the orders save data
+    PlaceOrder(String),
+    /// A limit order was cancelled (order ID)
+    CancelOrder(ID),
+    /// A stop-loss, trailing-stop or take-profit was attached to a holding, holds its save data
+    Protect(String),
+    /// A protection was removed (user ID, protection ID)
+    CancelProtection(ID, ID),
+    /// The server started with exchange mode on or off, written first in every journal
+    ExchangeMode(bool),
+    /// The market was seeded, or drawn without a tick entry (seed, ticks drawn)
+    Seed(u64, u64),
+    /// The season ended, the rankings are archived and the users reset
+    SeasonReset,
+}
+
+impl JournalEntry {
+    /// Makes the journal entry for a new user
+    pub fn new_user(user : &User) -> JournalEntry {
+        JournalEntry::NewUser(user.save_data())
+    }
+
+    /// Makes the journal entry for a company that was added or reset
+    pub fn company_state(company : &Company) -> JournalEntry {
+        JournalEntry::CompanyState(company.save_data())
+    }
+
+    /// Makes the journal entry for news that broke
+    pub fn news(event : &NewsEvent) -> JournalEntry {
+        JournalEntry::News(event.save_data())
+    }
+
+    /// Makes the journal entry for a placed order
+    pub fn place_order(order : &Order) -> JournalEntry {
+        JournalEntry::PlaceOrder(order.save_data())
+    }
+
+    /// Makes the journal entry for an attached protection
+    pub fn protect(protection : &Protection) -> JournalEntry {
+        JournalEntry::Protect(protection.save_data())
+    }
+
+    /// Checks if the entry is a players request, they are written before they are applied
+    /// So a request the game refused is in the journal too, and is refused again when replayed
+    pub fn is_request(&self) -> bool {
+        matches!(self,
+            JournalEntry::Buy(..) | JournalEntry::Sell(..)
+            | JournalEntry::PlaceOrder(_) | JournalEntry::CancelOrder(_)
+            | JournalEntry::Protect(_) | JournalEntry::CancelProtection(..))
+    }
+
+    /// Applies the entry to a loaded game
+    pub fn apply(&self, game : &mut Snapshot) -> Result<(), String> {
+        match self {
+            JournalEntry::NewUser(data) => {
+                match User::load_data(data) {
+                    Ok(user) => game.user_manager.restore_user(user).map(|_id| ()),
+                    Err(error) => Err(error),
+                }
+            },
+            JournalEntry::Buy(user_id, company_id, amount) => game.company_manager
+                .trade(&mut game.user_manager, *user_id, TradeSide::Buy, *company_id, *amount, TradeKind::Market)
+                .map(|_fills| ()),
+            JournalEntry::Sell(user_id, company_id, amount) => game.company_manager
+                .trade(&mut game.user_manager, *user_id, TradeSide::Sell, *company_id, *amount, TradeKind::Market)
+                .map(|_fills| ()),
+            //The orders and protections the prices crossed fill the same way they did live
+            JournalEntry::Tick(new_prices) => {
+                if let Err(error) = game.company_manager.apply_prices(new_prices) {
+                    return Err(error);
+                }
+                game.company_manager.fill_orders(&mut game.user_manager);
+                game.company_manager.trigger_protections(&mut game.user_manager);
+                Ok(())
+            },
+            JournalEntry::PlaceOrder(data) => {
+                let order = match Order::load_data(data) {
+                    Ok(order) => order,
+                    Err(error) => return Err(error),
+                };
+
+                game.company_manager.place_order(&mut game.user_manager, order).map(|_fills| ())
+            },
+            JournalEntry::CancelOrder(order_id) => game.company_manager.cancel_order(&mut game.user_manager, *order_id).map(|_order| ()),
+            JournalEntry::Protect(data) => {
+                let protection = match Protection::load_data(data) {
+                    Ok(protection) => protection,
+                    Err(error) => return Err(error),
+                };
+
+                match game.user_manager.get_user_by_id_mut(protection.user_id()) {
+                    Ok(user) => user.protect(protection),
+                    Err(error) => Err(error),
+                }
+            },
+            JournalEntry::CancelProtection(user_id, protection_id) => {
+                match game.user_manager.get_user_by_id_mut(*user_id) {
+                    Ok(user) => user.cancel_protection(*protection_id).map(|_protection| ()),
+                    Err(error) => Err(error),
+                }
+            },
+            JournalEntry::ExchangeMode(exchange_mode) => {
+                game.company_manager.set_exchange_mode(*exchange_mode);
+                Ok(())
+            },
+            JournalEntry::Seed(seed, tick) => {
+                game.company_manager.set_seed(*seed, *tick);
+                Ok(())
+            },
+            JournalEntry::News(data) => {
+                match NewsEvent::load_data(data) {
+                    Ok(event) => { game.company_manager.add_news(event); Ok(()) },
+                    Err(error) => Err(error),
+                }
+            },
+            JournalEntry::CompanyState(data) => {
+                match Company::load_data(data) {
+                    Ok(company) => { game.company_manager.restore_company(company); Ok(()) },
+                    Err(error) => Err(error),
+                }
+            },
+            JournalEntry::SeasonReset => {
+                //Ranks the users one last time before archiving
+                let mut ranker = Ranker::new();
+                if let Err(error) = ranker.rank_users(&game.user_manager, &game.company_manager) {
+                    return Err(error);
+                }
+
+                game.ranker_history.add(ranker);
+                game.user_manager.reset_users();
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Writes the entry as a single record
+impl SaveData for JournalEntry {
+    fn get_data(&self) -> String {
+        match self {
+            JournalEntry::NewUser(data) => data.clone(),
+            JournalEntry::Buy(user_id, company_id, amount) => Record::new("buy")
+                .with("user_id", user_id)
+                .with("company_id", company_id)
+                .with("amount", amount)
+                .get_data(),
+            JournalEntry::Sell(user_id, company_id, amount) => Record::new("sell")
+                .with("user_id", user_id)
+                .with("company_id", company_id)
+                .with("amount", amount)
+                .get_data(),
+            JournalEntry::Tick(new_prices) => {
+                let prices : Vec<String> = new_prices
+                    .iter()
+                    .map(|(company_id, price)| format!("{}:{}", company_id, price))
+                    .collect();
+
+                Record::new("tick").with("prices", prices.join(",")).get_data()
+            },
+            JournalEntry::CompanyState(data) => data.clone(),
+            JournalEntry::News(data) => data.clone(),
+            JournalEntry::PlaceOrder(data) => data.clone(),
+            JournalEntry::CancelOrder(order_id) => Record::new("cancel_order").with("order_id", order_id).get_data(),
+            JournalEntry::Protect(data) => data.clone(),
+            JournalEntry::CancelProtection(user_id, protection_id) => Record::new("cancel_protection")
+                .with("user_id", user_id)
+                .with("protection_id", protection_id)
+                .get_data(),
+            JournalEntry::ExchangeMode(exchange_mode) => Record::new("exchange_mode").with("enabled", exchange_mode).get_data(),
+            JournalEntry::Seed(seed, tick) => Record::new("seed").with("seed", seed).with("tick", tick).get_data(),
+            JournalEntry::SeasonReset => Record::new("season").get_data(),
+        }
+    }
+}
+
+/// Reads the entry back from its record
+impl LoadData for JournalEntry {
+    fn load_data(data : &str) -> Result<JournalEntry, String> {
+        let record = match Record::load_data(data) {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        match record.kind() {
+            "user" => Ok(JournalEntry::NewUser(data.to_string())),
+            "company" => Ok(JournalEntry::CompanyState(data.to_string())),
+            "news" => Ok(JournalEntry::News(data.to_string())),
+            "order" => Ok(JournalEntry::PlaceOrder(data.to_string())),
+            "cancel_order" => match record.get::<usize>("order_id") {
+                Ok(order_id) => Ok(JournalEntry::CancelOrder(ID::load(order_id))),
+                Err(error) => Err(error),
+            },
+            "protection" => Ok(JournalEntry::Protect(data.to_string())),
+            "cancel_protection" => {
+                let user_id : usize = match record.get("user_id") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+                let protection_id : usize = match record.get("protection_id") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+
+                Ok(JournalEntry::CancelProtection(ID::load(user_id), ID::load(protection_id)))
+            },
+            "exchange_mode" => match record.get::<bool>("enabled") {
+                Ok(exchange_mode) => Ok(JournalEntry::ExchangeMode(exchange_mode)),
+                Err(error) => Err(error),
+            },
+            "seed" => {
+                let seed : u64 = match record.get("seed") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+                let tick : u64 = match record.get("tick") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+
+                Ok(JournalEntry::Seed(seed, tick))
+            },
+            "season" => Ok(JournalEntry::SeasonReset),
+            "buy" | "sell" => {
+                let user_id : usize = match record.get("user_id") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+                let company_id : usize = match record.get("company_id") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+                let amount : usize = match record.get("amount") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+
+                if record.kind() == "buy" {
+                    Ok(JournalEntry::Buy(ID::load(user_id), ID::load(company_id), amount))
+                } else {
+                    Ok(JournalEntry::Sell(ID::load(user_id), ID::load(company_id), amount))
+                }
+            },
+            "tick" => {
+                let prices_text : String = match record.get("prices") {
+                    Ok(value) => value,
+                    Err(error) => return Err(error),
+                };
+
+                //Reads the (company ID, price) pairs
+                let mut new_prices : Vec<(ID, f32)> = Vec::new();
+                for pair in prices_text.split(',').filter(|pair| !pair.is_empty()) {
+                    let parsed = match pair.split_once(':') {
+                        Some((company_id, price)) => company_id.parse::<usize>().ok().zip(price.parse::<f32>().ok()),
+                        None => None,
+                    };
+
+                    match parsed {
+                        Some((company_id, price)) => new_prices.push((ID::load(company_id), price)),
+                        None => return Err(format!("Could not parse price '{}' in tick", pair)),
+                    }
+                }
+
+                Ok(JournalEntry::Tick(new_prices))
+            },
+            other => Err(format!("Unknown journal entry '{}'", other)),
+        }
+    }
+}
+
+/// The append-only journal of everything that happened since the last snapshot
+pub struct Journal {
+    file : File,
+    position : u64,
+}
+
+impl Journal {
+    /// Starts a new empty journal, new entries continue on from the position
+    /// Only call this once the snapshot holds every entry of the old journal
+    pub fn create(position : u64) -> Result<Journal, String> {
+        //Makes sure the save folder exists
+        if let Err(error) = std::fs::create_dir_all(SAVE_DIRECTORY) {
+            return Err(error.to_string());
+        }
+
+        Self::create_at(Path::new(JOURNAL_FILE), position)
+    }
+
+    /// Starts a new empty journal in another file
+    pub fn create_at(path : &Path, position : u64) -> Result<Journal, String> {
+        let file = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => file,
+            Err(error) => return Err(error.to_string()),
+        };
+
+        let mut journal = Journal { file, position };
+        match journal.clear() {
+            Err(error) => Err(error),
+            _ => Ok(journal),
+        }
+    }
+
+    /// Gets the position of the last entry written
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Writes the entry to the journal
+    /// Only returns once the entry is on the disk
+    pub fn record(&mut self, entry : &JournalEntry) -> Result<(), String> {
+        //The position is added as the last field of the entries record
+        let line = format!("{}\tposition={}\n", entry.get_data(), self.position + 1);
+
+        if let Err(error) = self.file.write_all(line.as_bytes()) {
+            return Err(format!("Could not write to the journal: {}", error));
+        }
+
+        if let Err(error) = self.file.sync_data() {
+            return Err(format!("Could not write to the journal: {}", error));
+        }
+
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Empties the journal once a snapshot holds all of its entries
+    pub fn clear(&mut self) -> Result<(), String> {
+        if let Err(error) = self.file.set_len(0) {
+            return Err(error.to_string());
+        }
+
+        //Every journal starts with the header
+        if let Err(error) = self.file.write_all(format!("{}\n", migration::header("journal")).as_bytes()) {
+            return Err(error.to_string());
+        }
+
+        match self.file.sync_data() {
+            Err(error) => Err(error.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replays the journal on top of the loaded game
+/// Returns the position of the last entry in the journal
+pub fn replay_journal(game : &mut Snapshot) -> Result<u64, String> {
+    //There is nothing to replay
+    if !Path::new(JOURNAL_FILE).exists() {
+        return Ok(game.journal_position);
+    }
+
+    match read_from_file(JOURNAL_FILE) {
+        Ok(data) => replay_data(game, data),
+        Err(error) => Err(error),
+    }
+}
+
+/// Replays the written journal on top of the loaded game
+/// Returns the position of the last entry in it
+pub fn replay_data(game : &mut Snapshot, mut data : String) -> Result<u64, String> {
+    let mut position = game.journal_position;
+
+    //A crash can leave the last line half written, it was never acknowledged so it is dropped
+    if !data.ends_with('\n') {
+        data.truncate(data.rfind('\n').map_or(0, |end| end + 1));
+    }
+
+    //Upgrades the journal if an older build wrote it
+    let data = match migration::upgrade(&data, "journal") {
+        Ok(data) => data,
+        Err(error) => return Err(error),
+    };
+
+    for line in data.lines().filter(|line| !line.is_empty()) {
+        let entry_position : u64 = match Record::load_data(line) {
+            Ok(record) => match record.get("position") {
+                Ok(value) => value,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+
+        //The snapshot already holds this entry
+        if entry_position <= game.journal_position {
+            continue;
+        }
+
+        let entry = match JournalEntry::load_data(line) {
+            Ok(entry) => entry,
+            Err(error) => return Err(format!("Journal entry {}: {}", entry_position, error)),
+        };
+
+        match entry.apply(game) {
+            Err(error) if entry.is_request() => println!("Skipping journal entry {}, the request was refused: {}", entry_position, error),
+            Err(error) => return Err(format!("Replaying journal entry {}: {}", entry_position, error)),
+            _ => (),
+        }
+        //Users were already told about the orders cancelled before the restart
+        game.company_manager.take_cancelled_orders();
+
+        position = entry_position;
+    }
+
+    Ok(position)
+}
@@ -0,0 +1,73 @@
+use super::data_saving::{SaveData, Record};
+
+/// The version of the save files written by this build
+/// Bump it and add a migration whenever the saved records change in a way older builds can't read
+pub const SAVE_VERSION : u32 = 1;
+
+/// Upgrades the data of a save file by one version
+type Migration = fn(&str) -> Result<String, String>;
+
+/// MIGRATIONS[n] upgrades version n + 1 to version n + 2
+/// Version 1 is the first format saves were written in, so nothing needs upgrading yet
+const MIGRATIONS : [Migration; (SAVE_VERSION - 1) as usize] = [];
+
+/// Makes the first line of a save file
+pub fn header(kind : &str) -> String {
+    Record::new("fake_stocks")
+        .with("version", SAVE_VERSION)
+        .with("kind", kind)
+        .get_data()
+}
+
+/// Reads the header of a save file and upgrades the data to the current version
+/// Returns the data without the header
+pub fn upgrade(data : &str, kind : &str) -> Result<String, String> {
+    //Nothing was written to the file yet
+    if data.is_empty() {
+        return Ok(String::new());
+    }
+
+    //A file with only the header has an empty body
+    let (first_line, body) = data.split_once('\n').unwrap_or((data, ""));
+    let (version, body) = match first_line.starts_with("fake_stocks\t") {
+        true => {
+            let header = match Record::load_kind(first_line, "fake_stocks") {
+                Ok(header) => header,
+                Err(error) => return Err(error),
+            };
+
+            //Makes sure it's the right kind of file
+            match header.get::<String>("kind") {
+                Ok(file_kind) if file_kind == kind => (),
+                Ok(file_kind) => return Err(format!("Expected a {} file, found a {} file", kind, file_kind)),
+                Err(error) => return Err(error),
+            }
+
+            match header.get::<u32>("version") {
+                Ok(version) => (version, body),
+                Err(error) => return Err(error),
+            }
+        },
+        false => return Err(format!("The {} file has no save header", kind)),
+    };
+
+    migrate(version, body)
+}
+
+/// Upgrades data saved in an older version to the current version
+pub fn migrate(version : u32, body : &str) -> Result<String, String> {
+    if version == 0 || version > SAVE_VERSION {
+        return Err(format!("Save version {} is not supported, this build reads up to version {}", version, SAVE_VERSION));
+    }
+
+    //Runs every migration from the files version up to the current one
+    let mut body = body.to_string();
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        body = match migration(&body) {
+            Ok(body) => body,
+            Err(error) => return Err(format!("Upgrading save from version {}: {}", version, error)),
+        };
+    }
+
+    Ok(body)
+}
@@ -1,2 +1,7 @@
-
-pub mod data_saving;
\ No newline at end of file
+pub mod data_saving;
+pub mod migration;
+pub mod storage;
+pub mod file_storage;
+pub mod database_storage;
+pub mod snapshot;
+pub mod journal;
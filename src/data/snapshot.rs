@@ -0,0 +1,105 @@
+use crate::companies::company_manager::CompanyManager;
+use crate::users::ranking::RankerHistory;
+use crate::users::user_manager::UserManager;
+use super::data_saving::{SaveData, LoadData, Record};
+use super::storage::Storage;
+
+/// The folder the snapshots are written to
+pub const SAVE_DIRECTORY : &str = "saves";
+
+/// Everything that is restored when the server starts
+pub struct Snapshot {
+    pub user_manager : UserManager,
+    pub company_manager : CompanyManager,
+    pub ranker_history : RankerHistory,
+    /// The last journal entry that is already part of the snapshot
+    pub journal_position : u64,
+}
+
+impl Snapshot {
+    /// Makes an empty snapshot for a brand new game
+    pub fn new() -> Snapshot {
+        Snapshot {
+            user_manager : UserManager::new(),
+            company_manager : CompanyManager::new(),
+            ranker_history : RankerHistory::new(),
+            journal_position : 0,
+        }
+    }
+}
+
+/// Writes the current state of the game to the storage
+/// The users, companies and rankings are always saved together
+pub fn save_snapshot(storage : &mut dyn Storage, user_manager : &UserManager, company_manager : &CompanyManager, ranker_history : &RankerHistory, journal_position : u64) -> Result<(), String> {
+    storage.write(&[
+        ("journal", Record::new("journal").with("position", journal_position).with("exchange_mode", company_manager.exchange_mode())
+            .with("seed", company_manager.seed())
+            .with("tick", company_manager.tick())
+            .get_data()),
+        ("users", user_manager.save_data()),
+        ("companies", company_manager.save_data()),
+        ("rankings", ranker_history.save_data()),
+    ])
+}
+
+/// Loads the last snapshot from the storage
+/// Returns None if no snapshot was ever saved
+pub fn load_snapshot(storage : &mut dyn Storage) -> Result<Option<Snapshot>, String> {
+    //Nothing has been saved yet
+    let journal_table = match storage.read("journal") {
+        Ok(Some(journal_table)) => journal_table,
+        Ok(None) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    let journal_record = match Record::load_kind(&journal_table, "journal") {
+        Ok(record) => record,
+        Err(error) => return Err(error),
+    };
+    let journal_position : u64 = match journal_record.get("position") {
+        Ok(value) => value,
+        Err(error) => return Err(error),
+    };
+    //Saves from before exchange mode always traded with the house
+    let exchange_mode : bool = match journal_record.get_or("exchange_mode", false) {
+        Ok(value) => value,
+        Err(error) => return Err(error),
+    };
+
+    let user_manager = match load_table::<UserManager>(storage, "users") {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(format!("Loading users: {}", error)),
+    };
+
+    let mut company_manager = match load_table::<CompanyManager>(storage, "companies") {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(format!("Loading companies: {}", error)),
+    };
+    company_manager.set_exchange_mode(exchange_mode);
+    //Saves from before seeding keep the random seed they loaded with
+    let seed : u64 = match journal_record.get_or("seed", company_manager.seed()) {
+        Ok(value) => value,
+        Err(error) => return Err(error),
+    };
+    let tick : u64 = match journal_record.get_or("tick", 0) {
+        Ok(value) => value,
+        Err(error) => return Err(error),
+    };
+    company_manager.set_seed(seed, tick);
+    company_manager.count_shares_held(&user_manager);
+
+    let ranker_history = match load_table::<RankerHistory>(storage, "rankings") {
+        Ok(ranker_history) => ranker_history,
+        Err(error) => return Err(format!("Loading rankings: {}", error)),
+    };
+
+    Ok(Some(Snapshot { user_manager, company_manager, ranker_history, journal_position }))
+}
+
+/// Loads a type from a table, a missing table loads as empty
+fn load_table<T : LoadData>(storage : &mut dyn Storage, table : &str) -> Result<T, String> {
+    match storage.read(table) {
+        Ok(data) => T::load_data(&data.unwrap_or_default()),
+        Err(error) => Err(error),
+    }
+}
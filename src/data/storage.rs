@@ -0,0 +1,33 @@
+use super::file_storage::FileStorage;
+use super::database_storage::DatabaseStorage;
+
+/// Picks the storage backend
+const STORAGE_VARIABLE : &str = "FAKE_STOCKS_STORAGE";
+
+/// Somewhere the managers can save their data to
+/// The data is split into named tables ("users", "companies", ...)
+pub trait Storage {
+    /// Gets the name of the backend
+    fn name(&self) -> &str;
+
+    /// Reads a table, None if it was never written
+    fn read(&mut self, table : &str) -> Result<Option<String>, String>;
+
+    /// Writes the tables together, after a crash either all of them are saved or none are
+    fn write(&mut self, tables : &[(&str, String)]) -> Result<(), String>;
+}
+
+/// Opens the storage picked by the FAKE_STOCKS_STORAGE environment variable
+/// "file" (The default) keeps everything in a text file, "database" uses the embedded database
+pub fn open_storage() -> Result<Box<dyn Storage>, String> {
+    let backend = std::env::var(STORAGE_VARIABLE).unwrap_or_else(|_error| String::from("file"));
+
+    match backend.as_str() {
+        "file" => Ok(Box::new(FileStorage::new())),
+        "database" => match DatabaseStorage::open() {
+            Ok(database) => Ok(Box::new(database)),
+            Err(error) => Err(error),
+        },
+        other => Err(format!("Unknown storage '{}', {} should be 'file' or 'database'", other, STORAGE_VARIABLE)),
+    }
+}
@@ -16,13 +16,15 @@ static COUNTER : AtomicUsize = AtomicUsize::new(0);
 /// Default ID functions
 impl ID {
     /// Generates a new ID
-    pub fn new<'a>() -> ID {
+    pub fn new() -> ID {
         let prev_val = COUNTER.fetch_add(1, Ordering::Relaxed);
         ID { id: prev_val }
     }
 
-    /// Force generated an ID
-    fn new_forced_id(id_num : usize) -> ID {
+    /// Restores an ID that was saved to disk
+    /// Moves the counter past it so new IDs never collide with loaded ones
+    pub fn load(id_num : usize) -> ID {
+        COUNTER.fetch_max(id_num + 1, Ordering::Relaxed);
         ID { id: id_num }
     }
 
@@ -36,22 +38,6 @@ impl ID {
         self.value() == other.value()
     }
 
-    /// Makes an ID from a string
-    /// Expects it in the form of "ID=654"
-    pub fn from_string(string : &String) -> Result<ID, String> {
-        //Checks that there is an ID
-        if string.contains("ID=") && string.len() > 3  {
-            let value_str = &string[3..].to_string();
-
-            //Error parsing the ID
-            match value_str.parse::<usize>() {
-                Ok(value) => return Ok(Self::new_forced_id(value)),
-                Err(error) => return Err(error.to_string()),
-            };
-        }
-        //Could not find ID
-        Err(String::from("No ID found"))
-    }
 }
 
 /*
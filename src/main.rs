@@ -1,23 +1,33 @@
 
 
+// Errors are passed up with explicit matches instead of `?`, so they can be reworded on the way
+#![allow(clippy::question_mark)]
+
 //For storing IDs
 use crate::id::ID;
 
 use crate::companies::company::Company;
 use crate::companies::company_manager::CompanyManager;
 use crate::companies::stock::Stock;
+use crate::companies::catalogue::Catalogue;
 use crate::users::ranking::{Ranker, RankerHistory};
 use crate::users::user::User;
 use crate::users::password::Password;
 use crate::users::user_manager::UserManager;
-use crate::data::data_saving::SaveData;
-use crate::servers::server;
+use crate::data::snapshot::{self, Snapshot};
+use crate::data::storage::{self, Storage};
+use crate::data::journal::{self, Journal, JournalEntry};
+use crate::servers::server::{self, ServerState};
 use crate::servers::client_tracker::ClientTracker;
+use crate::servers::config::ServerConfig;
+use crate::servers::worker_pool::WorkerPool;
+use crate::servers::websocket::PriceStream;
+use crate::servers::events::{self, EventFeed, UserEvent};
 
 use std::time::{Instant, Duration};
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
 
 //So it can use accounts and companies
 mod servers;
@@ -26,38 +36,205 @@ mod companies;
 mod users;
 mod id;
 
-/// Resets the company manager
-fn reset_company_manager(company_manager : &mut CompanyManager) -> Result<(), String> {
-    //Resets Apple
-    match company_manager.get_company_by_name_mut(&String::from("Apple")) {
-        Ok(company) => { company.reset_company(200.0).unwrap(); company.id()},
-        Err(_error) => company_manager.new_company(String::from("Apple"), 200.0),
+/// Resets the company manager to the companies of the catalogue
+/// Companies that left the catalogue keep trading, reset at their current price
+/// The reset companies are written to the journal
+fn reset_company_manager(company_manager : &mut CompanyManager, journal : &mut Journal, catalogue : &Catalogue, liquidity : usize) -> Result<(), String> {
+    for company in company_manager.companies_mut() {
+        let price = company.stock_price();
+        if let Err(error) = company.reset_company(price) {
+            return Err(error);
+        }
+    }
+
+    //Companies move off the tickers the catalogue gives to other companies, so tickers can be swapped or taken from companies that left
+    let tickers : Vec<String> = catalogue.companies().iter().map(|entry| entry.ticker().clone()).collect();
+    for entry in catalogue.companies() {
+        let holder = match company_manager.get_company_by_ticker(entry.ticker()) {
+            Ok(company) => company.id(),
+            Err(_error) => continue,
+        };
+        match company_manager.get_company_by_name(entry.name()) {
+            Ok(company) if company.id().equals(holder) => (),
+            _ => if let Err(error) = company_manager.release_ticker(holder, &tickers) {
+                println!("Could not free the ticker {}: {}", entry.ticker(), error);
+            },
+        }
+    }
+
+    //Resets the companies of the catalogue to their starting prices, adding the new ones
+    //A company that can't be set up is skipped, a broken catalogue shouldn't stop the game
+    for entry in catalogue.companies() {
+        let company_id = match company_manager.get_company_by_name_mut(entry.name()) {
+            Ok(company) => match company.reset_company(entry.price()) {
+                Ok(()) => company.id(),
+                Err(error) => {
+                    println!("Could not reset {}, it is skipped: {}", entry.name(), error);
+                    continue;
+                },
+            },
+            Err(_error) => match company_manager.new_company(entry.name().clone(), entry.price()) {
+                Ok(company_id) => company_id,
+                Err(error) => {
+                    println!("Could not add {}, it is skipped: {}", entry.name(), error);
+                    continue;
+                },
+            },
+        };
+
+        let configured = match company_manager.get_company_by_id_mut(company_id) {
+            Ok(company) => entry.configure(company, liquidity),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = configured.and_then(|_| company_manager.set_ticker(company_id, entry.ticker().clone())) {
+            println!("Could not set up {}, it is skipped: {}", entry.name(), error);
+        }
+    }
+
+    let first_tick = company_manager.tick();
+    for _ in 0..catalogue.warmup_ticks() {
+        company_manager.update();
+    }
+
+    //Records the reset companies
+    for company in company_manager.companies() {
+        if let Err(error) = journal.record(&JournalEntry::company_state(company)) {
+            return Err(error);
+        }
+    }
+
+    //Records the news that broke while the prices were made
+    for event in company_manager.news().iter().filter(|event| event.tick() > first_tick) {
+        if let Err(error) = journal.record(&JournalEntry::news(event)) {
+            return Err(error);
+        }
+    }
+
+    //The updates above aren't ticks in the journal, so replaying has to skip past them
+    journal.record(&JournalEntry::Seed(company_manager.seed(), company_manager.tick()))
+}
+
+
+/// Saves the current state of the game to disk
+/// The journal is emptied as the snapshot now holds all of its entries
+fn save_game(storage : &mut dyn Storage, user_manager_rw : &Arc<RwLock<UserManager>>, company_manager_rw : &Arc<RwLock<CompanyManager>>, ranker_history_rw : &Arc<RwLock<RankerHistory>>, journal_rw : &Arc<RwLock<Journal>>) -> Result<(), String> {
+    let user_manager = match user_manager_rw.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(error.to_string()),
     };
 
-    //Resets Amazon
-    match company_manager.get_company_by_name_mut(&String::from("Amazon")) {
-        Ok(company) => { company.reset_company(200.0).unwrap(); company.id()},
-        Err(_error) => company_manager.new_company(String::from("Amazon"), 200.0),
+    let company_manager = match company_manager_rw.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(error.to_string()),
     };
 
-    for _ in 0..50 {
-        company_manager.update();
+    let ranker_history = match ranker_history_rw.read() {
+        Ok(ranker_history) => ranker_history,
+        Err(error) => return Err(error.to_string()),
+    };
+
+    let mut journal = match journal_rw.write() {
+        Ok(journal) => journal,
+        Err(error) => return Err(error.to_string()),
+    };
+
+    if let Err(error) = snapshot::save_snapshot(storage, &user_manager, &company_manager, &ranker_history, journal.position()) {
+        return Err(error);
     }
 
-    Ok(())
+    journal.clear()
 }
 
 
 fn main() -> Result<(), String> {
+    //The web server and market settings
+    let config = match ServerConfig::from_env() {
+        Ok(config) => config,
+        Err(error) => return Err(error),
+    };
+
+    //The companies new games and seasons are played with
+    let mut catalogue = match Catalogue::load(&config.catalogue_path) {
+        Ok(catalogue) => catalogue,
+        Err(error) => {
+            //Main only prints its error on one line, so the problems are printed first
+            println!("{}", error);
+            return Err(String::from("Could not load the company catalogue, the problems are listed above"));
+        },
+    };
+    println!("Loaded {} companies from {}", catalogue.companies().len(), config.catalogue_path);
+
+    //Opens the storage the game is saved in
+    let mut storage = match storage::open_storage() {
+        Ok(storage) => storage,
+        Err(error) => return Err(error),
+    };
+    println!("Saving to the {} storage", storage.name());
+
+    //Restores the last saved game
+    let mut game = match snapshot::load_snapshot(&mut *storage) {
+        Ok(Some(game)) => game,
+        Ok(None) => Snapshot::new(),
+        Err(error) => return Err(format!("Could not load the saved game: {}", error)),
+    };
+
+    //Replays everything that happened after the snapshot
+    let journal_position = match journal::replay_journal(&mut game) {
+        Ok(journal_position) => journal_position,
+        Err(error) => return Err(format!("Could not replay the journal: {}", error)),
+    };
+
+    //Folds the replayed journal into a fresh snapshot (Also upgrades saves from older builds)
+    if let Err(error) = snapshot::save_snapshot(&mut *storage, &game.user_manager, &game.company_manager, &game.ranker_history, journal_position) {
+        return Err(format!("Could not save the game: {}", error));
+    }
+
+    //Starts a new journal now that the snapshot holds the old one
+    let mut journal = match Journal::create(journal_position) {
+        Ok(journal) => journal,
+        Err(error) => return Err(error),
+    };
+
+    //Matches users with each other or with the house, as the settings say
+    if game.company_manager.exchange_mode() != config.exchange_mode {
+        game.company_manager.set_exchange_mode(config.exchange_mode);
+        if let Err(error) = journal.record(&JournalEntry::ExchangeMode(config.exchange_mode)) {
+            return Err(error);
+        }
+    }
+    println!("Trading in {} mode", if config.exchange_mode { "exchange" } else { "house" });
+
+    //Draws the market from the seed in the settings, a loaded game keeps its own otherwise
+    match config.seed {
+        Some(seed) if seed != game.company_manager.seed() => {
+            game.company_manager.set_seed(seed, 0);
+            if let Err(error) = journal.record(&JournalEntry::Seed(seed, 0)) {
+                return Err(error);
+            }
+        },
+        _ => (),
+    }
+    println!("Market seed {}", game.company_manager.seed());
+
+    //Resets the company manager (A loaded game keeps its prices)
+    if game.company_manager.companies().is_empty() {
+        if let Err(error) = reset_company_manager(&mut game.company_manager, &mut journal, &catalogue, config.liquidity) {
+            return Err(error);
+        }
+    }
+
     //Read / Write locks
-    let company_manager_rw : Arc<RwLock<CompanyManager>> = Arc::new(RwLock::new(CompanyManager::new()));
-    let user_manager_rw : Arc<RwLock<UserManager>> = Arc::new(RwLock::new(UserManager::new()));
+    let company_manager_rw : Arc<RwLock<CompanyManager>> = Arc::new(RwLock::new(game.company_manager));
+    let user_manager_rw : Arc<RwLock<UserManager>> = Arc::new(RwLock::new(game.user_manager));
     let ranker_rw : Arc<RwLock<Ranker>> = Arc::new(RwLock::new(Ranker::new()));
-    let ranker_history_rw : Arc<RwLock<RankerHistory>> = Arc::new(RwLock::new(RankerHistory::new()));
+    let ranker_history_rw : Arc<RwLock<RankerHistory>> = Arc::new(RwLock::new(game.ranker_history));
     let client_tracker_rw : Arc<RwLock<ClientTracker>> = Arc::new(RwLock::new(ClientTracker::new()));
+    let journal_rw : Arc<RwLock<Journal>> = Arc::new(RwLock::new(journal));
 
-    //Resets the company manager
-    _ = reset_company_manager(&mut company_manager_rw.write().unwrap());
+    //Pushes each tick to the WebSocket clients
+    let price_stream_rw : Arc<RwLock<PriceStream>> = Arc::new(RwLock::new(PriceStream::new(config.max_streams)));
+    //Sends each session the events of its user
+    let event_feed_rw : Arc<RwLock<EventFeed>> = Arc::new(RwLock::new(EventFeed::new(config.max_streams)));
 
     //Web Listener testing
     let listener = match TcpListener::bind("127.0.0.1:8000") {
@@ -66,11 +243,29 @@ fn main() -> Result<(), String> {
     };
 
     // The company manager / user manager shared across threads!
-    let thread_company_manager : Arc<RwLock<CompanyManager>> = Arc::clone(&company_manager_rw);
-    let thread_user_manager : Arc<RwLock<UserManager>> = Arc::clone(&user_manager_rw);
-    let thread_ranker : Arc<RwLock<Ranker>> = Arc::clone(&ranker_rw);
-    let thread_ranker_history : Arc<RwLock<RankerHistory>> = Arc::clone(&ranker_history_rw);
-    let thread_client_tracker : Arc<RwLock<ClientTracker>> = Arc::clone(&client_tracker_rw);
+    let server_state = ServerState {
+        client_tracker : Arc::clone(&client_tracker_rw),
+        company_manager : Arc::clone(&company_manager_rw),
+        user_manager : Arc::clone(&user_manager_rw),
+        ranker : Arc::clone(&ranker_rw),
+        ranker_history : Arc::clone(&ranker_history_rw),
+        journal : Arc::clone(&journal_rw),
+        price_stream : Arc::clone(&price_stream_rw),
+        event_feed : Arc::clone(&event_feed_rw),
+    };
+    let router = server::make_router();
+
+    // Requests are handled by a pool of workers, so one slow client doesn't hold up the others
+    let max_body_size = config.max_body_size;
+    let worker_pool = match WorkerPool::new(config.workers, config.queue_size, move |stream : TcpStream| {
+        //Handles a request from a client
+        if let Err(error) = server::handle_connection(stream, &router, &server_state, max_body_size) {
+            println!("Error: {}", error);
+        }
+    }) {
+        Ok(worker_pool) => worker_pool,
+        Err(error) => return Err(error),
+    };
 
     // Spawns a thread to listen to web requests!
     thread::spawn(move || {
@@ -78,11 +273,12 @@ fn main() -> Result<(), String> {
             //Checks for a stream
             match stream {
                 Ok(stream) => {
-                    //Handles a request from a client
-                    match server::handle_connection(stream, &thread_client_tracker, &thread_company_manager, &thread_user_manager, &thread_ranker, &thread_ranker_history) {
-                        Err(error) => println!("Error: {}", error),
-                        _ => (),
-                    };
+                    //Turns the client away when the queue is full
+                    if let Err(stream) = worker_pool.try_execute(stream) {
+                        if let Err(error) = server::reject_busy(stream) {
+                            println!("Error: {}", error);
+                        }
+                    }
                 },
                 Err(error) => println!("{}", error),
             }
@@ -92,9 +288,11 @@ fn main() -> Result<(), String> {
     //Gets the time of start-up
     let mut time = Instant::now();
     let mut reset_time = time;
+    let mut save_time = time;
 
     const LOOP_DELAY : u64 = 5;
     const RESET_DELAY : u64 = 600;
+    const SAVE_DELAY : u64 = 60;
 
     //Forever loops as this will hopefully never crash :)
     loop {
@@ -104,11 +302,11 @@ fn main() -> Result<(), String> {
             reset_time += Duration::new(RESET_DELAY, 0);
 
             println!("Resetting!");
-            // Reset the user manager
-            match user_manager_rw.write() {
-                Ok(mut user_man) => user_man.reset_users(),
+            // Gets the user manager
+            let mut user_manager = match user_manager_rw.write() {
+                Ok(user_manager) => user_manager,
                 Err(error) => return Err(error.to_string()),
-            }
+            };
 
             // Gets the company manager
             let mut company_manager = match company_manager_rw.write() {
@@ -116,10 +314,48 @@ fn main() -> Result<(), String> {
                 Err(error) => return Err(error.to_string()),
             };
 
-            // Resets the stock history / prices of all the companies
-            match reset_company_manager(&mut company_manager) {
+            // Gets the journal
+            let mut journal = match journal_rw.write() {
+                Ok(journal) => journal,
+                Err(error) => return Err(error.to_string()),
+            };
+
+            // Ranks the users one last time, writes the ranker to the history
+            // And clears the new ranker
+            match ranker_history_rw.write() {
+                Ok(mut history) => {
+                    match ranker_rw.write() {
+                        Ok(mut ranker) => {
+                            if let Err(error) = ranker.rank_users(&user_manager, &company_manager) {
+                                return Err(error);
+                            }
+                            history.add(ranker.clone());
+                            ranker.clear()
+                        },
+                        Err(error) => return Err(error.to_string()),
+                    }
+                },
                 Err(error) => return Err(error.to_string()),
-                _ => (),
+            }
+
+            // Reset the user manager
+            user_manager.reset_users();
+
+            // Records the end of the season
+            if let Err(error) = journal.record(&JournalEntry::SeasonReset) {
+                return Err(error);
+            }
+
+            // Reads the catalogue again, so the next season can be played with other companies
+            // A broken catalogue shouldn't stop the game, the last good one is used instead
+            match Catalogue::load(&config.catalogue_path) {
+                Ok(new_catalogue) => catalogue = new_catalogue,
+                Err(error) => println!("Could not reload the company catalogue, using the last one:\n{}", error),
+            }
+
+            // Resets the stock history / prices of all the companies
+            if let Err(error) = reset_company_manager(&mut company_manager, &mut journal, &catalogue, config.liquidity) {
+                return Err(error.to_string());
             }
 
             //Clears the client tracker (Everyone must re-login)
@@ -128,14 +364,16 @@ fn main() -> Result<(), String> {
                 Err(error) => return Err(error.to_string()),
             }
 
-            // Writes the old ranker to the ranker
-            // And clears the new ranker
-            match ranker_history_rw.write() {
-                Ok(mut history) => {
-                    match ranker_rw.write() {
-                        Ok(mut ranker) => { history.add(ranker.clone()); ranker.clear()},
-                        Err(error) => return Err(error.to_string()),
+            //Tells every open session about the new season, then closes them as they are logged out
+            drop(journal);
+            drop(company_manager);
+            drop(user_manager);
+            match event_feed_rw.write() {
+                Ok(mut event_feed) => {
+                    if let Err(error) = event_feed.send_all(&UserEvent::SeasonReset {}) {
+                        println!("Error sending the season reset: {}", error);
                     }
+                    event_feed.close_all();
                 },
                 Err(error) => return Err(error.to_string()),
             }
@@ -146,6 +384,13 @@ fn main() -> Result<(), String> {
             //Adds 20 seconds to the time
             time += Duration::new(LOOP_DELAY,  0);
 
+            // Gets the user manager, filled orders change users
+            // Locks are always taken users first, then companies, the same order the requests use
+            let mut user_manager = match user_manager_rw.write() {
+                Ok(user_manager) => user_manager,
+                Err(error) => return Err(error.to_string()),
+            };
+
             // Gets the company manager
             let mut company_manager = match company_manager_rw.write() {
                 Ok(company_manager) => company_manager,
@@ -153,24 +398,98 @@ fn main() -> Result<(), String> {
             };
 
             // Update the company manager
-            company_manager.update();
-            
-            // Reads the user manager
-            let user_manager = match user_manager_rw.read() {
-                Ok(user_manager) => user_manager,
-                Err(error) => return Err(error.to_string()),
+            let new_prices = company_manager.update();
+            // Trades the limit orders and protections the new prices crossed, replaying the tick does the same
+            let mut fills = company_manager.fill_orders(&mut user_manager);
+            fills.extend(company_manager.trigger_protections(&mut user_manager));
+            let mut tick_events = match events::events_of_fills(&fills, &user_manager) {
+                Ok(tick_events) => tick_events,
+                Err(error) => return Err(error),
             };
+            // Orders that crossed but couldn't trade were cancelled, their users are told too
+            match events::events_of_cancelled(&company_manager.take_cancelled_orders(), &user_manager) {
+                Ok(cancelled_events) => tick_events.extend(cancelled_events),
+                Err(error) => return Err(error),
+            }
 
-            // Updates the leaderboards
-            match ranker_rw.write() {
+            // Records the new prices
+            match journal_rw.write() {
+                Ok(mut journal) => {
+                    if let Err(error) = journal.record(&JournalEntry::Tick(new_prices)) {
+                        return Err(error);
+                    }
+                    // The news that broke keeps moving prices after a restart
+                    for event in company_manager.breaking_news() {
+                        if let Err(error) = journal.record(&JournalEntry::news(event)) {
+                            return Err(error);
+                        }
+                    }
+                },
+                Err(error) => return Err(error.to_string()),
+            };
+            
+            // Updates the leaderboards, keeping what changed for the price stream
+            let rank_changes = match ranker_rw.write() {
                 Ok(mut ranker) => {
-                    match ranker.rank_users(&user_manager, &company_manager) {
-                        Err(error) => return Err(error.to_string()),
-                        _ => (),
-                    };
+                    let previous_ranker = ranker.clone();
+                    if let Err(error) = ranker.rank_users(&user_manager, &company_manager) {
+                        return Err(error.to_string());
+                    }
+                    ranker.changes_since(&previous_ranker)
                 },
                 Err(error) => return Err(error.to_string()),
             };
+
+            // Tells the WebSocket clients about the tick
+            // The message is made while the managers are locked, then sent once they are let go
+            let mut price_stream = match price_stream_rw.write() {
+                Ok(price_stream) => price_stream,
+                Err(error) => return Err(error.to_string()),
+            };
+            let message = match price_stream.next_tick(&company_manager, &rank_changes) {
+                Ok(message) => message,
+                Err(error) => return Err(error),
+            };
+
+            // Finds who moved on the leaderboard, a change of value alone isn't worth telling them
+            // A rank that doesn't know its user is only logged, it shouldn't stop the market
+            for change in rank_changes.iter().filter(|change| change.previous_rank != Some(change.rank)) {
+                match change.user_id {
+                    Some(user_id) => tick_events.push((user_id, vec![UserEvent::rank(change)])),
+                    None => println!("Could not tell {} they moved on the leaderboard, their rank has no user", change.name),
+                }
+            }
+
+            drop(company_manager);
+            drop(user_manager);
+            price_stream.broadcast(&message);
+            drop(price_stream);
+
+            // Tells each user about their filled orders and how they moved on the leaderboard
+            let mut event_feed = match event_feed_rw.write() {
+                Ok(event_feed) => event_feed,
+                Err(error) => return Err(error.to_string()),
+            };
+            match client_tracker_rw.read() {
+                Ok(client_tracker) => event_feed.close_inactive(&client_tracker),
+                Err(error) => return Err(error.to_string()),
+            }
+            for (user_id, events) in tick_events {
+                if let Err(error) = event_feed.send(user_id, &events) {
+                    println!("Error sending tick events: {}", error);
+                }
+            }
+            event_feed.keep_alive();
+        }
+
+        //Saves a snapshot of the game
+        if save_time.elapsed().as_secs() >= SAVE_DELAY {
+            save_time += Duration::new(SAVE_DELAY, 0);
+
+            //A failed save shouldn't stop the game, the journal still holds everything
+            if let Err(error) = save_game(&mut *storage, &user_manager_rw, &company_manager_rw, &ranker_history_rw, &journal_rw) {
+                println!("Error saving the game: {}", error);
+            }
         }
     }
 }
@@ -180,14 +499,36 @@ fn main() -> Result<(), String> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{companies::company_manager::CompanyManager, id::ID};
+    use crate::{companies::company::{self, Company}, companies::company_manager::CompanyManager, id::ID};
+    use crate::users::{user_manager::UserManager, password::Password};
+    use crate::data::data_saving::{SaveData, LoadData};
+    use crate::data::{journal::JournalEntry, snapshot::Snapshot, migration};
+    use crate::data::{storage::Storage, file_storage::FileStorage, database_storage::DatabaseStorage};
+    use crate::servers::{client_tracker::{self, ClientTracker}, cookie};
+    use crate::servers::request::{self, RequestError};
+    use crate::servers::worker_pool::WorkerPool;
+    use crate::servers::{request::Request, response::Response, router::{Router, Params}};
+    use crate::servers::server::{self, ServerState};
+    use crate::servers::websocket::{self, PriceStream};
+    use crate::servers::events::{self, EventFeed, UserEvent};
+    use crate::companies::order_book::{Order, TradeSide};
+    use crate::companies::protection::{Protection, ProtectionKind};
+    use crate::companies::price_model::{self, PriceModel, UniformWalk, GeometricBrownian, MeanReversion, JumpDiffusion};
+    use crate::companies::news::{self, NewsEvent, NewsKind};
+    use crate::companies::catalogue::Catalogue;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::users::trade_history::TradeKind;
+    use crate::users::ranking::{Rank, Ranker, RankerHistory, RankChange};
+    use crate::data::journal::{self, Journal};
+    use std::sync::{mpsc, Arc, Mutex, RwLock};
+    use std::io::prelude::*;
 
     #[test]
     fn company_manager_test() {
         let mut company_manager: CompanyManager = CompanyManager::new();
 
-        let gamer = company_manager.new_company(String::from("Gamer"), 1.01);
-        let gamerers = company_manager.new_company(String::from("Gamerers"), 2.01);
+        let gamer = company_manager.new_company(String::from("Gamer"), 1.01).unwrap();
+        let gamerers = company_manager.new_company(String::from("Gamerers"), 2.01).unwrap();
 
         company_manager.get_company_by_id(gamer).unwrap();
         company_manager.get_company_by_id(gamerers).unwrap();
@@ -201,18 +542,1265 @@ mod tests {
             panic!("Should not find a company that doesn't exist");
         }
 
+        //Names are unique whatever their case, in new and loaded companies alike
+        assert!(company_manager.new_company(String::from(" gamer"), 3.01).is_err());
+        assert_eq!(company_manager.companies().len(), 2);
+        let saved = company_manager.save_data();
+        let renamed = company_manager.get_company_by_id(gamerers).unwrap().save_data().replace("name=Gamerers", "name=GAMER");
+        assert!(CompanyManager::load_data(&saved.replace(&company_manager.get_company_by_id(gamerers).unwrap().save_data(), &renamed)).is_err());
+
         company_manager.get_company_by_name_mut(&String::from("Gamer")).unwrap();
         company_manager.get_company_by_name_mut(&String::from("Gamerers")).unwrap();
         if let Ok(_company) = company_manager.get_company_by_name_mut(&String::from("Jeff Bezos")) {
             panic!("Should not find a company that doesn't exist");
         }
+
+        //Tickers are made unique, and companies are found by ticker, name or ID whatever the case
+        assert_eq!(company_manager.get_company_by_id(gamer).unwrap().ticker(), "GAME");
+        assert_eq!(company_manager.get_company_by_id(gamerers).unwrap().ticker(), "GAME2");
+        assert!(company_manager.find_company("game2").unwrap().id().equals(gamerers));
+        assert!(company_manager.find_company("GAMERERS").unwrap().id().equals(gamerers));
+        assert!(company_manager.find_company(&gamer.to_string()).unwrap().id().equals(gamer));
+        assert!(company_manager.find_company("GME").is_err());
+
+        //Tickers stay unique when they are changed, and the old one is freed
+        assert!(company_manager.set_ticker(gamerers, String::from("GAME")).is_err());
+        assert!(company_manager.set_ticker(gamerers, String::from("gme")).is_err());
+        company_manager.set_ticker(gamer, String::from("GME")).unwrap();
+        assert!(company_manager.find_company("gme").unwrap().id().equals(gamer));
+        assert!(company_manager.find_company("GAME").is_err());
+
+        //Loaded and restored companies are found the same way
+        let loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        assert!(loaded_companies.find_company("GME").unwrap().id().equals(gamer));
+        let mut restored = Company::load_data(&company_manager.get_company_by_id(gamerers).unwrap().save_data().replace("ticker=GAME2", "ticker=GAMR")).unwrap();
+        restored.set_liquidity(0);
+        company_manager.restore_company(restored);
+        assert!(company_manager.find_company("GAMR").unwrap().id().equals(gamerers));
+        assert!(company_manager.find_company("GAME2").is_err());
+        assert_eq!(company_manager.companies().len(), 2);
+        //Saved tickers are checked like new ones
+        let saved = company_manager.get_company_by_id(gamer).unwrap().save_data();
+        assert!(Company::load_data(&saved.replace("ticker=GME", "ticker=gme")).is_err());
+        assert!(Company::load_data(&saved.replace("ticker=GME", "ticker=")).is_err());
     }
 
-    fn test() {
-        
-        // let user_manager : Arc<RwLock<UserManager>> = Arc::new(RwLock::new(UserManager::new()));
-        // let ranker : Arc<RwLock<Ranker>> = Arc::new(RwLock::new(Ranker::new()));
-        // let ranker_history_rw : Arc<RwLock<RankerHistory>> = Arc::new(RwLock::new(RankerHistory::new()));
-        // let client_tracker_rw : Arc<RwLock<ClientTracker>> = Arc::new(RwLock::new(ClientTracker::new()));
+    #[test]
+    fn save_load_test() {
+        let mut company_manager = CompanyManager::new();
+        let apple = company_manager.new_company(String::from("Apple"), 200.0).unwrap();
+        company_manager.update();
+
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("left-right-up-down-up-up")).unwrap();
+        let user_id = user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+        company_manager.trade(&mut user_manager, user_id, TradeSide::Buy, apple, 2, TradeKind::Market).unwrap();
+
+        //Saves and loads both managers
+        let loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        let loaded_users = UserManager::load_data(&user_manager.save_data()).unwrap();
+
+        assert_eq!(loaded_companies.save_data(), company_manager.save_data());
+        assert_eq!(loaded_users.save_data(), user_manager.save_data());
+
+        //The loaded user still works with the loaded companies
+        let user = loaded_users.get_user_by_username(&String::from("ozone")).unwrap();
+        assert!(user.password().verify("left-right-up-down-up-up"));
+        assert!(!user.password().verify("left-right-up-down-up-down"));
+        assert_eq!(user.stock_amount(), 2);
+
+        //Corrupted password hashes don't load
+        let saved = user.password().get_data();
+        let parts : Vec<&str> = saved.split('$').collect();
+        assert!(Password::load_data(&saved).is_ok());
+        for corrupted in [
+            format!("{}$0${}${}", parts[0], parts[2], parts[3]),
+            format!("{}${}$${}", parts[0], parts[1], parts[3]),
+            format!("{}${}${}$", parts[0], parts[1], parts[2]),
+            format!("{}${}${}${}", parts[0], parts[1], &parts[2][2..], parts[3]),
+            format!("{}${}$+f{}${}", parts[0], parts[1], &parts[2][2..], parts[3]),
+        ] {
+            assert!(Password::load_data(&corrupted).is_err(), "{}", corrupted);
+        }
+        assert_eq!(user.value(&loaded_companies).unwrap(), user_manager.get_user_by_id(user_id).unwrap().value(&company_manager).unwrap());
+
+        //New IDs never collide with loaded ones
+        assert!(ID::new().value() > user.id().value());
+    }
+
+    #[test]
+    fn journal_replay_test() {
+        let mut game = Snapshot::new();
+        let mut replayed = Snapshot::new();
+        let mut entries : Vec<JournalEntry> = Vec::new();
+
+        //Plays a short game, recording every change
+        let apple = game.company_manager.new_company(String::from("Apple"), 200.0).unwrap();
+        entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(apple).unwrap()));
+
+        let password = Password::from_text(&String::from("up-up-down-down-left-right")).unwrap();
+        let user_id = game.user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+        entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(user_id).unwrap()));
+
+        game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Buy, apple, 3, TradeKind::Market).unwrap();
+        entries.push(JournalEntry::Buy(user_id, apple, 3));
+
+        entries.push(JournalEntry::Tick(game.company_manager.update()));
+        //News that broke on the tick is journaled after it, as the server does
+        for event in game.company_manager.breaking_news() {
+            entries.push(JournalEntry::news(event));
+        }
+
+        game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Sell, apple, 2, TradeKind::Market).unwrap();
+        entries.push(JournalEntry::Sell(user_id, apple, 2));
+
+        //Requests are written before they are applied, so ones the game refused are in the journal too
+        let refused = JournalEntry::Sell(user_id, apple, 100);
+        assert!(refused.is_request());
+        assert!(game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Sell, apple, 100, TradeKind::Market).is_err());
+        entries.push(refused);
+
+        //Replays the written entries on an empty game, skipping the refused request
+        let folder = std::env::temp_dir().join(format!("fake_stocks_replay_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut journal = Journal::create_at(&folder.join("journal.txt"), 0).unwrap();
+        for entry in entries.iter() {
+            journal.record(entry).unwrap();
+        }
+        let data = std::fs::read_to_string(folder.join("journal.txt")).unwrap();
+        assert_eq!(journal::replay_data(&mut replayed, data).unwrap(), entries.len() as u64);
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(replayed.user_manager.save_data(), game.user_manager.save_data());
+        assert_eq!(replayed.company_manager.save_data(), game.company_manager.save_data());
+    }
+
+    #[test]
+    fn migration_test() {
+        //Current saves are left alone, newer saves are refused
+        let current = format!("{}\nseason", migration::header("journal"));
+        assert_eq!(migration::upgrade(&current, "journal").unwrap(), "season");
+        let newer = current.replace(&format!("version={}", migration::SAVE_VERSION), &format!("version={}", migration::SAVE_VERSION + 1));
+        assert!(migration::upgrade(&newer, "journal").is_err());
+        assert!(migration::upgrade(&current, "snapshot").is_err());
+
+        //Every save is written with a header, so a file without one isn't a save
+        assert!(migration::upgrade("season", "journal").is_err());
+        assert_eq!(migration::upgrade("", "journal").unwrap(), "");
+    }
+
+    #[test]
+    fn storage_test() {
+        let folder = std::env::temp_dir().join(format!("fake_stocks_storage_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let database_file = folder.join("game.db").to_string_lossy().to_string();
+        let snapshot_file = folder.join("snapshot.txt").to_string_lossy().to_string();
+
+        let mut backends : Vec<Box<dyn Storage>> = vec![
+            Box::new(FileStorage::with_file(&snapshot_file)),
+            Box::new(DatabaseStorage::open_file(&database_file).unwrap()),
+        ];
+
+        for storage in backends.iter_mut() {
+            storage.write(&[("users", String::from("first")), ("companies", String::from("apple"))]).unwrap();
+            storage.write(&[("users", String::from("second"))]).unwrap();
+            assert_eq!(storage.read("users").unwrap(), Some(String::from("second")));
+            assert_eq!(storage.read("companies").unwrap(), Some(String::from("apple")));
+            assert_eq!(storage.read("rankings").unwrap(), None);
+        }
+
+        //Both backends read back what they wrote after being re-opened
+        let mut file_storage = FileStorage::with_file(&snapshot_file);
+        assert_eq!(file_storage.read("users").unwrap(), Some(String::from("second")));
+
+        //A batch cut off part way is dropped
+        let size = std::fs::metadata(&database_file).unwrap().len();
+        let mut database = DatabaseStorage::open_file(&database_file).unwrap();
+        database.write(&[("users", String::from("third"))]).unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(&database_file).unwrap();
+        file.set_len(size + 10).unwrap();
+
+        let mut database = DatabaseStorage::open_file(&database_file).unwrap();
+        assert_eq!(database.read("users").unwrap(), Some(String::from("second")));
+        database.write(&[("users", String::from("fourth"))]).unwrap();
+        let mut database = DatabaseStorage::open_file(&database_file).unwrap();
+        assert_eq!(database.read("users").unwrap(), Some(String::from("fourth")));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn session_test() {
+        let mut client_tracker = ClientTracker::new();
+        let user_id = ID::new();
+
+        //Each device gets its own unguessable session
+        let phone = client_tracker.add_client(user_id);
+        let laptop = client_tracker.add_client(user_id);
+        assert_ne!(phone, laptop);
+        assert_eq!(phone.len(), 64);
+        assert!(client_tracker.authenticate(&phone).unwrap().equals(user_id));
+        assert!(client_tracker.authenticate(&laptop).unwrap().equals(user_id));
+
+        //Logging out only ends that session
+        client_tracker.remove_client(&phone).unwrap();
+        assert!(client_tracker.authenticate(&phone).is_err());
+        assert!(client_tracker.authenticate(&laptop).is_ok());
+        assert!(client_tracker.authenticate("0").is_err());
+
+        //Sessions stop working once they expire
+        let mut expiring_tracker = ClientTracker::with_timeout(std::time::Duration::ZERO);
+        let token = expiring_tracker.add_client(user_id);
+        assert!(expiring_tracker.authenticate(&token).is_err());
+    }
+
+    #[test]
+    fn cookie_test() {
+        //Other cookies don't get in the way of the session
+        let header = "theme=dark; ID=abc123;lang=\"en\" ; ID=second";
+        assert_eq!(cookie::get_cookie(header, "ID"), Some(String::from("abc123")));
+        assert_eq!(cookie::get_cookie(header, "lang"), Some(String::from("en")));
+        assert_eq!(cookie::get_cookie(header, "missing"), None);
+        assert_eq!(cookie::parse_cookies(header).len(), 4);
+
+        //Malformed pairs are skipped without losing the good ones
+        let malformed = ";;novalue; =empty_name; bad name=1; ID=tok,en; ok=1; ID=good";
+        assert_eq!(cookie::parse_cookies(malformed), vec![(String::from("ok"), String::from("1")), (String::from("ID"), String::from("good"))]);
+        assert_eq!(cookie::get_cookie("", "ID"), None);
+        assert_eq!(cookie::get_cookie("ID=", "ID"), Some(String::new()));
+
+        //The session cookie can't be read by scripts and expires with the session
+        let set_cookie = cookie::session_cookie("abc123");
+        assert!(set_cookie.starts_with("ID=abc123;"));
+        assert!(set_cookie.contains("HttpOnly"));
+        assert!(set_cookie.contains("SameSite=Lax"));
+        assert!(set_cookie.contains(&format!("Max-Age={};", client_tracker::SESSION_TIMEOUT.as_secs())));
+        assert!(set_cookie.contains("Max-Age=7200;"));
+        assert!(cookie::expired_session_cookie().contains("Max-Age=0"));
+
+        //Live sessions get their cookie again, so its expiry slides with the session
+        let client_tracker_rw = Arc::new(RwLock::new(ClientTracker::new()));
+        let token = client_tracker_rw.write().unwrap().add_client(ID::new());
+        let written = |request_text : String, response : Response| {
+            let request = request::read_request(&mut request_text.as_bytes(), 0).unwrap();
+            let mut written : Vec<u8> = Vec::new();
+            server::refresh_session_cookie(&request, response, &client_tracker_rw).write_to(&mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        let refreshed = written(format!("GET /money HTTP/1.1\r\nCookie: ID={}\r\n\r\n", token), Response::ok(String::new()));
+        assert!(refreshed.contains(&format!("Set-Cookie: {}\r\n", cookie::session_cookie(&token))));
+
+        //Unknown sessions, requests without a cookie and responses that set their own are left alone
+        assert!(!written(String::from("GET /money HTTP/1.1\r\nCookie: ID=unknown\r\n\r\n"), Response::ok(String::new())).contains("Set-Cookie"));
+        assert!(!written(String::from("GET /money HTTP/1.1\r\n\r\n"), Response::ok(String::new())).contains("Set-Cookie"));
+        let logged_out = written(format!("POST /logout HTTP/1.1\r\nCookie: ID={}\r\n\r\n", token), Response::ok(String::new()).with_header("Set-Cookie", &cookie::expired_session_cookie()));
+        assert_eq!(logged_out.matches("Set-Cookie").count(), 1);
+    }
+
+    #[test]
+    fn request_test() {
+        //Bodies bigger than one read are put back together
+        let body = "x".repeat(5000);
+        let data = format!("POST /login?next=home HTTP/1.1\r\nHost: localhost\r\ncookie: a=1\r\nCookie: ID=abc\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+        let request = request::read_request(&mut data.as_bytes(), 10_000).unwrap();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.path(), "/login");
+        assert_eq!(request.header_values("COOKIE"), vec!["a=1", "ID=abc"]);
+        assert_eq!(request.body_text().unwrap(), body);
+
+        //Requests without a body have an empty one
+        let request = request::read_request(&mut "GET / HTTP/1.1\r\n\r\n".as_bytes(), 10_000).unwrap();
+        assert_eq!(request.body_text().unwrap(), "");
+
+        //Too large bodies are refused before being read
+        match request::read_request(&mut data.as_bytes(), 4999) {
+            Err(RequestError::BodyTooLarge(size)) => assert_eq!(size, 5000),
+            _ => panic!("A body over the limit should be refused"),
+        }
+
+        //Cut off and malformed requests fail
+        assert!(matches!(request::read_request(&mut &data.as_bytes()[..3000], 10_000), Err(RequestError::Connection(_))));
+        assert!(matches!(request::read_request(&mut "GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n".as_bytes(), 10_000), Err(RequestError::Invalid(_))));
+        let huge_headers = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "x".repeat(20_000));
+        assert!(matches!(request::read_request(&mut huge_headers.as_bytes(), 10_000), Err(RequestError::HeadersTooLarge)));
+    }
+
+    #[test]
+    fn worker_pool_test() {
+        let (started_sender, started) = mpsc::channel::<u32>();
+        let (release, release_receiver) = mpsc::channel::<()>();
+        let release_receiver = Mutex::new(release_receiver);
+
+        //One worker that holds each job until it is released
+        let worker_pool = WorkerPool::new(1, 1, move |job : u32| {
+            started_sender.send(job).unwrap();
+            release_receiver.lock().unwrap().recv().unwrap();
+        }).unwrap();
+
+        //The first job runs, the second waits in the queue and the third is turned away
+        worker_pool.try_execute(1).unwrap();
+        assert_eq!(started.recv().unwrap(), 1);
+        worker_pool.try_execute(2).unwrap();
+        assert_eq!(worker_pool.try_execute(3), Err(3));
+
+        //Queued jobs still run before the pool stops
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        drop(worker_pool);
+        assert_eq!(started.try_iter().collect::<Vec<u32>>(), vec![2]);
+
+        assert!(WorkerPool::new(0, 1, |_job : u32| ()).is_err());
+    }
+
+    #[test]
+    fn router_test() {
+        fn money(_request : &Request, _params : &Params, state : &u32) -> Result<Response, String> {
+            Ok(Response::ok(format!("money {}", state)))
+        }
+        fn company(_request : &Request, params : &Params, _state : &u32) -> Result<Response, String> {
+            Ok(Response::ok(format!("company {}", params.get("name").unwrap())))
+        }
+
+        let router = Router::new()
+            .add("GET", "/money", money)
+            .add("POST", "/money", money)
+            .add("GET", "/companies/{name}", company);
+
+        //Sends a request through the router, giving back the written response
+        let respond = |request_line : &str| -> String {
+            let data = format!("{} HTTP/1.1\r\n\r\n", request_line);
+            let request = request::read_request(&mut data.as_bytes(), 0).unwrap();
+            let mut written : Vec<u8> = Vec::new();
+            router.route(&request, &7).unwrap().write_to(&mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        assert!(respond("GET /money").ends_with("money 7"));
+        assert!(respond("GET /money?refresh=1").ends_with("money 7"));
+        assert!(respond("GET /companies/Jeff%20Bezos").ends_with("company Jeff Bezos"));
+
+        //Paths only match exactly
+        assert!(respond("GET /moneybags").starts_with("HTTP/1.1 404"));
+        assert!(respond("GET /").starts_with("HTTP/1.1 404"));
+        assert!(respond("GET /companies").starts_with("HTTP/1.1 404"));
+        assert!(respond("GET /companies/Apple/extra").starts_with("HTTP/1.1 404"));
+        assert!(respond("GET /companies/bad%zz").starts_with("HTTP/1.1 404"));
+
+        //Known paths with the wrong method say which methods work
+        let wrong_method = respond("DELETE /money");
+        assert!(wrong_method.starts_with("HTTP/1.1 405"));
+        assert!(wrong_method.contains("Allow: GET, POST\r\n"));
+    }
+
+    #[test]
+    fn api_test() {
+        let folder = std::env::temp_dir().join(format!("fake_stocks_api_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let mut company_manager = CompanyManager::new();
+        //Trades fill at the shown price so the totals are round
+        let apple = company_manager.new_company(String::from("Apple"), 200.0).unwrap();
+        company_manager.get_company_by_id_mut(apple).unwrap().set_liquidity(0);
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("up-up-down-down-left-right")).unwrap();
+        let user_id = user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+        let mut client_tracker = ClientTracker::new();
+        let token = client_tracker.add_client(user_id);
+
+        let state = ServerState {
+            client_tracker : Arc::new(RwLock::new(client_tracker)),
+            company_manager : Arc::new(RwLock::new(company_manager)),
+            user_manager : Arc::new(RwLock::new(user_manager)),
+            ranker : Arc::new(RwLock::new(Ranker::new())),
+            ranker_history : Arc::new(RwLock::new(RankerHistory::new())),
+            journal : Arc::new(RwLock::new(Journal::create_at(&folder.join("journal"), 0).unwrap())),
+            price_stream : Arc::new(RwLock::new(PriceStream::new(1))),
+            event_feed : Arc::new(RwLock::new(EventFeed::new(1))),
+        };
+        let router = server::make_router();
+
+        //Sends a request through the router, giving back the written response
+        let respond = |request_line : &str, headers : &str, body : &str| -> String {
+            let data = format!("{} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", request_line, headers, body.len(), body);
+            let request = request::read_request(&mut data.as_bytes(), 1024).unwrap();
+            let mut written : Vec<u8> = Vec::new();
+            router.route(&request, &state).unwrap().write_to(&mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        let auth = format!("Authorization: Bearer {}\r\n", token);
+
+        let companies = respond("GET /api/v1/companies", "", "");
+        assert!(companies.contains("Content-Type: application/json\r\n"));
+        assert!(companies.contains(r#""name":"Apple","ticker":"APPL","sector":"General","price":200.0,"shares_outstanding":1000,"shares_held":0,"market_cap":200000.0,"held_percent":0.0}]"#));
+        assert!(respond("GET /api/v1/companies/Apple/history", "", "").ends_with(r#"{"name":"Apple","history":[200.0]}"#));
+
+        //Errors have a code programs can match on
+        assert!(respond("GET /api/v1/companies/Jeff", "", "").contains(r#"{"error":{"code":"not_found","#));
+        assert!(respond("GET /api/v1/cash", "", "").starts_with("HTTP/1.1 401"));
+        assert!(respond("POST /api/v1/trades", &auth, "3 Apple").starts_with("HTTP/1.1 400"));
+        assert!(respond("POST /api/v1/trades", &auth, r#"{"side":"hold","company":"Apple","amount":3}"#).starts_with("HTTP/1.1 400"));
+        assert!(respond("POST /api/v1/trades", &auth, r#"{"side":"buy","company":"Jeff","amount":3}"#).starts_with("HTTP/1.1 404"));
+        let rejected = respond("POST /api/v1/trades", &auth, r#"{"side":"sell","company":"Apple","amount":3}"#);
+        assert!(rejected.starts_with("HTTP/1.1 422"));
+        assert!(rejected.contains(r#""code":"trade_rejected""#));
+
+        //A trade shows up in the portfolio
+        let bought = respond("POST /api/v1/trades", &auth, r#"{"side":"buy","company":"Apple","amount":3}"#);
+        assert!(bought.starts_with("HTTP/1.1 200"));
+        assert!(bought.contains(r#""side":"buy""#));
+        assert!(bought.contains(r#""price":200.0,"quoted_price":200.0,"slippage":0.0,"total":600.0"#));
+        assert!(bought.contains(r#""shares_held":3"#));
+        let portfolio = respond("GET /api/v1/portfolio", &auth, "");
+        assert!(portfolio.contains(r#""company":"Apple","amount":3,"reserved":0,"average_price":200.0,"price":200.0,"value":600.0}]"#));
+        let apple = respond("GET /api/v1/companies/Apple", "", "");
+        assert!(apple.contains(r#""shares_held":3,"market_cap":200000.0,"held_percent":0.3,"shareholders":[{"name":"Ozone","shares":3,"percent":0.3}]"#));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn websocket_test() {
+        //The example handshake from RFC 6455
+        assert_eq!(websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        //Client frames are masked
+        let masked_ping = [0x89, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2];
+        let (frame, size) = websocket::decode_frame(&masked_ping).unwrap().unwrap();
+        assert_eq!((frame.opcode, frame.payload, size), (0x9, b"hi".to_vec(), 8));
+        assert_eq!(websocket::decode_frame(&masked_ping[..5]), Ok(None));
+        assert!(websocket::decode_frame(&[0x89, 0x02, b'h', b'i']).is_err());
+        assert_eq!(websocket::encode_frame(0x1, &[b'a'; 200])[..4], [0x81, 126, 0, 200]);
+
+        //Only users whose place or value moved are sent
+        let mut company_manager = CompanyManager::new();
+        let apple = company_manager.new_company(String::from("Apple"), 200.0).unwrap();
+        company_manager.get_company_by_id_mut(apple).unwrap().set_liquidity(0);
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("up-up-down-down-left-right")).unwrap();
+        let rich = user_manager.new_user(String::from("rich"), String::from("Rich"), password.clone()).unwrap();
+        user_manager.new_user(String::from("poor"), String::from("Poor"), password).unwrap();
+        let mut before = Ranker::new();
+        before.rank_users(&user_manager, &company_manager).unwrap();
+        company_manager.trade(&mut user_manager, rich, TradeSide::Buy, apple, 1, TradeKind::Market).unwrap();
+        company_manager.get_company_by_id_mut(apple).unwrap().set_stock_price(400.0).unwrap();
+        let mut after = Ranker::new();
+        after.rank_users(&user_manager, &company_manager).unwrap();
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].name.as_str(), changes[0].rank, changes[0].value), ("Rich", 1, 1200.0));
+        assert!(changes[0].user_id.unwrap().equals(rich));
+        //Ranks are saved with their user, ones saved before that still load
+        assert!(after.ranks()[0].save_data().ends_with(&format!("user_id={}", rich)));
+        assert!(Rank::load_data("rank\tname=Rich\tvalue=1200").is_ok());
+        assert!(after.changes_since(&after).is_empty());
+
+        //A client connects, gets a tick, then closes
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET /price_stream HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+
+        let price_stream = RwLock::new(PriceStream::new(1));
+        let (mut server_side, _address) = listener.accept().unwrap();
+        let upgrade = request::read_request(&mut server_side, 0).unwrap();
+        assert!(websocket::is_upgrade(&upgrade));
+        websocket::accept(server_side, &upgrade, &price_stream).unwrap();
+        assert!(price_stream.read().unwrap().is_full());
+
+        let mut handshake = [0u8; 129];
+        client.read_exact(&mut handshake).unwrap();
+        let handshake = String::from_utf8(handshake.to_vec()).unwrap();
+        assert!(handshake.starts_with("HTTP/1.1 101"));
+        assert!(handshake.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"));
+
+        let message = price_stream.write().unwrap().next_tick(&company_manager, &changes).unwrap();
+        assert!(message.starts_with(r#"{"type":"tick","tick":1,"prices":[{"id":"#));
+        assert!(message.ends_with(r#""leaderboard":[{"name":"Rich","previous_rank":1,"rank":1,"value":1200.0}]}"#));
+        price_stream.write().unwrap().broadcast(&message);
+
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let mut length = header[1] as usize;
+        if length == 126 {
+            let mut extended = [0u8; 2];
+            client.read_exact(&mut extended).unwrap();
+            length = u16::from_be_bytes(extended) as usize;
+        }
+        let mut received = vec![0u8; length];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), message);
+
+        //The close is answered and the client is dropped
+        client.write_all(&[0x88, 0x82, 0, 0, 0, 0, 0x03, 0xE8]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        price_stream.write().unwrap().broadcast(&message);
+        assert!(!price_stream.read().unwrap().is_full());
+        let mut close = [0u8; 4];
+        client.read_exact(&mut close).unwrap();
+        assert_eq!(close, [0x88, 2, 0x03, 0xE8]);
+    }
+
+    #[test]
+    fn events_test() {
+        //Falling down the leaderboard is flagged
+        let change = RankChange { user_id : None, name : String::from("Ozone"), previous_rank : Some(1), rank : 2, value : 900.0 };
+        assert_eq!(UserEvent::rank(&change).to_event_text().unwrap(), "event: rank\ndata: {\"previous_rank\":1,\"rank\":2,\"value\":900.0,\"overtaken\":true}\n\n");
+        assert_eq!(UserEvent::SeasonReset {}.to_event_text().unwrap(), "event: season_reset\ndata: {}\n\n");
+
+        let mut client_tracker = ClientTracker::new();
+        let user_id = ID::new();
+        let token = client_tracker.add_client(user_id);
+
+        //A session opens its stream
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_side, _address) = listener.accept().unwrap();
+        let event_feed = RwLock::new(EventFeed::new(1));
+        events::accept(server_side, token.clone(), user_id, &event_feed).unwrap();
+        assert!(event_feed.read().unwrap().is_full());
+
+        let headers = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+        let mut received = vec![0u8; headers.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), headers);
+
+        //Only the users own events are sent
+        event_feed.write().unwrap().send(ID::new(), &[UserEvent::Cash { cash : 1.0 }]).unwrap();
+        event_feed.write().unwrap().send(user_id, &[UserEvent::Cash { cash : 2.5 }]).unwrap();
+        let expected = "event: cash\ndata: {\"cash\":2.5}\n\n";
+        let mut received = vec![0u8; expected.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), expected);
+
+        //Logging out closes the stream
+        client_tracker.remove_client(&token).unwrap();
+        event_feed.write().unwrap().close_inactive(&client_tracker);
+        assert!(!event_feed.read().unwrap().is_full());
+        let mut rest : Vec<u8> = Vec::new();
+        client.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn orders_test() {
+        let mut game = Snapshot::new();
+        let mut entries : Vec<JournalEntry> = Vec::new();
+
+        let apple = game.company_manager.new_company(String::from("Apple"), 100.0).unwrap();
+        game.company_manager.get_company_by_id_mut(apple).unwrap().set_liquidity(0);
+        entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(apple).unwrap()));
+
+        let password = Password::from_text(&String::from("down-down-up-up-right-left")).unwrap();
+        let user_id = game.user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+        entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(user_id).unwrap()));
+        let starting_money = game.user_manager.get_user_by_id(user_id).unwrap().money();
+
+        //A buy order holds back the most it could cost
+        let buy = Order::new(apple, user_id, TradeSide::Buy, 2, 90.0);
+        game.company_manager.place_order(&mut game.user_manager, buy.clone()).unwrap();
+        entries.push(JournalEntry::place_order(&buy));
+        let user = game.user_manager.get_user_by_id(user_id).unwrap();
+        assert_eq!(user.reserved_money(), 180.0);
+        assert_eq!(user.available_money(), starting_money - 180.0);
+
+        //Reserved money can't be spent twice
+        let too_big = Order::new(apple, user_id, TradeSide::Buy, 1, starting_money);
+        assert!(game.company_manager.place_order(&mut game.user_manager, too_big).is_err());
+
+        //Cancelling gives the reservation back
+        let cancelled = Order::new(apple, user_id, TradeSide::Buy, 1, 50.0);
+        game.company_manager.place_order(&mut game.user_manager, cancelled.clone()).unwrap();
+        entries.push(JournalEntry::place_order(&cancelled));
+        game.company_manager.cancel_order(&mut game.user_manager, cancelled.id()).unwrap();
+        entries.push(JournalEntry::CancelOrder(cancelled.id()));
+        assert_eq!(game.user_manager.get_user_by_id(user_id).unwrap().reserved_money(), 180.0);
+        assert_eq!(game.company_manager.orders_of_user(user_id).len(), 1);
+
+        //Open orders and reservations are saved
+        let loaded_companies = CompanyManager::load_data(&game.company_manager.save_data()).unwrap();
+        let loaded_users = UserManager::load_data(&game.user_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.save_data(), game.company_manager.save_data());
+        assert_eq!(loaded_users.save_data(), game.user_manager.save_data());
+
+        //A price above the limit leaves the order resting
+        game.company_manager.apply_prices(&[(apple, 95.0)]).unwrap();
+        assert!(game.company_manager.fill_orders(&mut game.user_manager).is_empty());
+        entries.push(JournalEntry::Tick(vec![(apple, 95.0)]));
+
+        //Once the price crosses the limit the order trades at that price
+        game.company_manager.apply_prices(&[(apple, 80.0)]).unwrap();
+        let fills = game.company_manager.fill_orders(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(apple, 80.0)]));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].trade.price(), 80.0);
+
+        let user = game.user_manager.get_user_by_id(user_id).unwrap();
+        assert_eq!(user.reserved_money(), 0.0);
+        assert_eq!(user.stock_amount(), 2);
+        assert_eq!(user.money(), starting_money - 160.0);
+
+        //A sell order holds back its shares
+        let sell = Order::new(apple, user_id, TradeSide::Sell, 2, 120.0);
+        game.company_manager.place_order(&mut game.user_manager, sell.clone()).unwrap();
+        entries.push(JournalEntry::place_order(&sell));
+        let user = game.user_manager.get_user_by_id_mut(user_id).unwrap();
+        assert_eq!(user.wallet().holders()[0].reserved_amount(), 2);
+        assert!(user.sell_stock_at(apple, 1, 120.0).is_err());
+
+        //An order the fills before it pushed the price away from stays open
+        let banana = game.company_manager.new_company(String::from("Banana"), 100.0).unwrap();
+        game.company_manager.get_company_by_id_mut(banana).unwrap().set_liquidity(10);
+        entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(banana).unwrap()));
+        let first = Order::new(banana, user_id, TradeSide::Buy, 1, 95.0);
+        let second = Order::new(banana, user_id, TradeSide::Buy, 1, 94.0);
+        for order in [&first, &second] {
+            game.company_manager.place_order(&mut game.user_manager, order.clone()).unwrap();
+            entries.push(JournalEntry::place_order(order));
+        }
+        game.company_manager.apply_prices(&[(banana, 90.0)]).unwrap();
+        let fills = game.company_manager.fill_orders(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(banana, 90.0)]));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].trade.price(), 94.5);
+        assert_eq!(game.company_manager.get_company_by_id(banana).unwrap().stock_price(), 99.0);
+        assert!(game.company_manager.orders_of_user(user_id).iter().any(|order| order.id().equals(second.id())));
+
+        //Replaying the journal ends at the same game
+        let mut replayed = Snapshot::new();
+        for entry in entries {
+            let written = JournalEntry::load_data(&entry.get_data()).unwrap();
+            written.apply(&mut replayed).unwrap();
+        }
+        assert_eq!(replayed.user_manager.save_data(), game.user_manager.save_data());
+        assert_eq!(replayed.company_manager.save_data(), game.company_manager.save_data());
+    }
+
+    #[test]
+    fn protections_test() {
+        let mut game = Snapshot::new();
+        let mut entries : Vec<JournalEntry> = Vec::new();
+
+        let apple = game.company_manager.new_company(String::from("Apple"), 100.0).unwrap();
+        game.company_manager.get_company_by_id_mut(apple).unwrap().set_liquidity(0);
+        entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(apple).unwrap()));
+
+        let password = Password::from_text(&String::from("right-left-down-up-down-up")).unwrap();
+        let user_id = game.user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+        entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(user_id).unwrap()));
+
+        game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Buy, apple, 6, TradeKind::Market).unwrap();
+        entries.push(JournalEntry::Buy(user_id, apple, 6));
+        let user = game.user_manager.get_user_by_id_mut(user_id).unwrap();
+
+        //Protections that would trigger straight away are refused
+        assert!(Protection::new(apple, user_id, ProtectionKind::StopLoss, 1, 110.0, 100.0).is_err());
+        assert!(Protection::new(apple, user_id, ProtectionKind::TakeProfit, 1, 90.0, 100.0).is_err());
+        assert!(Protection::new(apple, user_id, ProtectionKind::TrailingStop, 1, 100.0, 100.0).is_err());
+        //Only owned shares can be protected
+        let too_many = Protection::new(apple, user_id, ProtectionKind::StopLoss, 7, 90.0, 100.0).unwrap();
+        assert!(user.protect(too_many).is_err());
+
+        let stop_loss = Protection::new(apple, user_id, ProtectionKind::StopLoss, 2, 90.0, 100.0).unwrap();
+        let trailing_stop = Protection::new(apple, user_id, ProtectionKind::TrailingStop, 2, 10.0, 100.0).unwrap();
+        let take_profit = Protection::new(apple, user_id, ProtectionKind::TakeProfit, 2, 150.0, 100.0).unwrap();
+        for protection in [&stop_loss, &trailing_stop, &take_profit] {
+            user.protect(protection.clone()).unwrap();
+            entries.push(JournalEntry::protect(protection));
+        }
+        user.cancel_protection(take_profit.id()).unwrap();
+        entries.push(JournalEntry::CancelProtection(user_id, take_profit.id()));
+
+        //Protections and the trade history are saved
+        let loaded_users = UserManager::load_data(&game.user_manager.save_data()).unwrap();
+        assert_eq!(loaded_users.save_data(), game.user_manager.save_data());
+        assert_eq!(loaded_users.get_user_by_id(user_id).unwrap().wallet().protections().len(), 2);
+
+        //The trailing stop follows the price up, the stop-loss stays put
+        game.company_manager.apply_prices(&[(apple, 120.0)]).unwrap();
+        assert!(game.company_manager.trigger_protections(&mut game.user_manager).is_empty());
+        entries.push(JournalEntry::Tick(vec![(apple, 120.0)]));
+        let trailing_price = game.user_manager.get_user_by_id(user_id).unwrap().wallet().protections()[1].trigger_price();
+        assert_eq!(trailing_price, 110.0);
+
+        //Falling through the trailing stop sells, the stop-loss waits for its own price
+        game.company_manager.apply_prices(&[(apple, 105.0)]).unwrap();
+        let fills = game.company_manager.trigger_protections(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(apple, 105.0)]));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].trade.kind(), TradeKind::TrailingStop);
+        assert_eq!(fills[0].trade.price(), 105.0);
+
+        //Shares held back for a limit order aren't sold by a protection
+        let sell = Order::new(apple, user_id, TradeSide::Sell, 3, 200.0);
+        game.company_manager.place_order(&mut game.user_manager, sell.clone()).unwrap();
+        entries.push(JournalEntry::place_order(&sell));
+
+        game.company_manager.apply_prices(&[(apple, 80.0)]).unwrap();
+        let fills = game.company_manager.trigger_protections(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(apple, 80.0)]));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].trade.amount(), 1);
+
+        let user = game.user_manager.get_user_by_id(user_id).unwrap();
+        assert_eq!(user.stock_amount(), 3);
+        assert!(user.wallet().protections().is_empty());
+        let kinds : Vec<TradeKind> = user.trade_history().trades().iter().map(|trade| trade.kind()).collect();
+        assert_eq!(kinds, vec![TradeKind::Market, TradeKind::TrailingStop, TradeKind::StopLoss]);
+
+        //Replaying the journal ends at the same game
+        let mut replayed = Snapshot::new();
+        for entry in entries {
+            let written = JournalEntry::load_data(&entry.get_data()).unwrap();
+            written.apply(&mut replayed).unwrap();
+        }
+        assert_eq!(replayed.user_manager.save_data(), game.user_manager.save_data());
+        assert_eq!(replayed.company_manager.save_data(), game.company_manager.save_data());
+    }
+
+    #[test]
+    fn exchange_history_test() {
+        let mut company_manager = CompanyManager::new();
+        company_manager.set_exchange_mode(true);
+        let apple = company_manager.new_company(String::from("Apple"), 100.0).unwrap();
+
+        //Every tick adds a point to the history, even without trades
+        for _tick in 0..5 {
+            company_manager.update();
+        }
+        let company = company_manager.get_company_by_id(apple).unwrap();
+        assert_eq!(company.stock_price_history().len(), 6);
+        assert_eq!(company.stock_price_history().last(), Some(&company.stock_price()));
+        assert_eq!(company.order_book().quotes().len(), 2);
+
+        //The random walk carries on from the fair price without trades, and the market maker quotes around it
+        assert_eq!(company.stock_price(), 100.0);
+        assert_ne!(company.fair_price(), 100.0);
+        let quoted : f32 = company.order_book().quotes().iter().map(|quote| quote.price()).sum::<f32>() / 2.0;
+        assert!((quoted - company.fair_price()).abs() < 0.001);
+
+        //The fair price is saved, and a replayed tick walks on from it
+        let mut loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.get_company_by_id(apple).unwrap().fair_price(), company.fair_price());
+        loaded_companies.set_exchange_mode(true);
+        loaded_companies.apply_prices(&[(apple, 120.0)]).unwrap();
+        assert_eq!(loaded_companies.get_company_by_id(apple).unwrap().fair_price(), 120.0);
+        assert_eq!(loaded_companies.get_company_by_id(apple).unwrap().stock_price(), 100.0);
+    }
+
+    #[test]
+    fn exchange_test() {
+        let mut game = Snapshot::new();
+        let mut entries : Vec<JournalEntry> = Vec::new();
+
+        game.company_manager.set_exchange_mode(true);
+        entries.push(JournalEntry::ExchangeMode(true));
+        let apple = game.company_manager.new_company(String::from("Apple"), 100.0).unwrap();
+        entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(apple).unwrap()));
+
+        let password = Password::from_text(&String::from("left-up-right-down-left-up")).unwrap();
+        let seller_id = game.user_manager.new_user(String::from("ozone"), String::from("Ozone"), password.clone()).unwrap();
+        entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(seller_id).unwrap()));
+        let buyer_id = game.user_manager.new_user(String::from("quartz"), String::from("Quartz"), password).unwrap();
+        entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(buyer_id).unwrap()));
+
+        //An empty book can't fill a market trade
+        assert!(game.company_manager.trade(&mut game.user_manager, seller_id, TradeSide::Buy, apple, 5, TradeKind::Market).is_err());
+
+        //The random walk only moves the market makers quotes
+        game.company_manager.apply_prices(&[(apple, 100.0)]).unwrap();
+        game.company_manager.fill_orders(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(apple, 100.0)]));
+        assert_eq!(game.company_manager.get_company_by_id(apple).unwrap().order_book().quotes().len(), 2);
+
+        //Buying from the market maker pays its ask, which becomes the price
+        let fills = game.company_manager.trade(&mut game.user_manager, seller_id, TradeSide::Buy, apple, 5, TradeKind::Market).unwrap();
+        entries.push(JournalEntry::Buy(seller_id, apple, 5));
+        assert!(fills[0].trade.price() > 100.0);
+        assert_eq!(game.company_manager.get_company_by_id(apple).unwrap().stock_price(), fills[0].trade.price());
+
+        //More than the market maker offers is refused
+        assert!(game.company_manager.trade(&mut game.user_manager, buyer_id, TradeSide::Buy, apple, 1000, TradeKind::Market).is_err());
+
+        //Users sell below the market makers ask, so they are first in line
+        let worse = Order::new(apple, seller_id, TradeSide::Sell, 3, 100.5);
+        let better = Order::new(apple, seller_id, TradeSide::Sell, 2, 100.25);
+        for order in [&worse, &better] {
+            assert!(game.company_manager.place_order(&mut game.user_manager, order.clone()).unwrap().is_empty());
+            entries.push(JournalEntry::place_order(order));
+        }
+
+        //The best price trades first, each at the resting price, the rest of the older order stays open
+        let buy = Order::new(apple, buyer_id, TradeSide::Buy, 4, 102.0);
+        let fills = game.company_manager.place_order(&mut game.user_manager, buy.clone()).unwrap();
+        entries.push(JournalEntry::place_order(&buy));
+        let prices : Vec<f32> = fills.iter().skip(1).map(|fill| fill.trade.price()).collect();
+        assert_eq!(prices, vec![100.25, 100.5]);
+        assert!(fills[0].user_id.equals(buyer_id));
+        assert_eq!(fills[0].trade.amount(), 4);
+        assert_eq!(game.company_manager.get_company_by_id(apple).unwrap().stock_price(), 100.5);
+
+        let buyer = game.user_manager.get_user_by_id(buyer_id).unwrap();
+        assert_eq!(buyer.stock_amount(), 4);
+        assert_eq!(buyer.reserved_money(), 0.0);
+        let open = game.company_manager.orders_of_user(seller_id);
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].amount(), 1);
+
+        //Shares that can't trade go back where they were, from orders and quotes alike
+        let mut book = game.company_manager.get_company_by_id(apple).unwrap().order_book().clone();
+        let before = book.get_data();
+        let probe = Order::new(apple, buyer_id, TradeSide::Buy, 1, 200.0);
+        let from_order = book.take_best(&probe).unwrap();
+        let from_quote = book.take_best(&probe).unwrap();
+        assert!(from_order.order.is_some() && from_quote.order.is_none());
+        book.put_back(from_quote, TradeSide::Buy);
+        book.put_back(from_order, TradeSide::Buy);
+        assert_eq!(book.get_data(), before);
+
+        //The market maker buys the resting order once its bid crosses it
+        game.company_manager.apply_prices(&[(apple, 110.0)]).unwrap();
+        let fills = game.company_manager.fill_orders(&mut game.user_manager);
+        entries.push(JournalEntry::Tick(vec![(apple, 110.0)]));
+        assert_eq!(fills.len(), 1);
+        assert!(fills[0].trade.price() > 100.5);
+        assert_eq!(game.company_manager.get_company_by_id(apple).unwrap().stock_price(), fills[0].trade.price());
+        assert!(game.company_manager.orders_of_user(seller_id).is_empty());
+
+        //Quotes are saved with their company
+        let loaded_companies = CompanyManager::load_data(&game.company_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.save_data(), game.company_manager.save_data());
+
+        //Replaying the journal ends at the same game
+        let mut replayed = Snapshot::new();
+        for entry in entries {
+            let written = JournalEntry::load_data(&entry.get_data()).unwrap();
+            written.apply(&mut replayed).unwrap();
+        }
+        assert!(replayed.company_manager.exchange_mode());
+        assert_eq!(replayed.user_manager.save_data(), game.user_manager.save_data());
+        assert_eq!(replayed.company_manager.save_data(), game.company_manager.save_data());
+    }
+
+    #[test]
+    fn unfillable_order_test() {
+        let mut company_manager = CompanyManager::new();
+        let penny = company_manager.new_company(String::from("Penny"), 0.5).unwrap();
+        company_manager.get_company_by_id_mut(penny).unwrap().set_liquidity(0);
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("left-left-up-down-right-up")).unwrap();
+        let first = user_manager.new_user(String::from("ozone"), String::from("Ozone"), password.clone()).unwrap();
+        let second = user_manager.new_user(String::from("quartz"), String::from("Quartz"), password).unwrap();
+
+        //The order is placed while the house has the shares, then someone else buys them first
+        let order = Order::new(penny, first, TradeSide::Buy, 10, 0.4);
+        company_manager.place_order(&mut user_manager, order.clone()).unwrap();
+        company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 995, TradeKind::Market).unwrap();
+
+        //Once it crosses it can't trade, so it is cancelled and gives back what it held
+        company_manager.apply_prices(&[(penny, 0.3)]).unwrap();
+        assert!(company_manager.fill_orders(&mut user_manager).is_empty());
+        assert!(company_manager.orders_of_user(first).is_empty());
+        let user = user_manager.get_user_by_id(first).unwrap();
+        assert_eq!((user.reserved_money(), user.money(), user.stock_amount()), (0.0, 1000.0, 0));
+
+        //Its user is told once
+        let cancelled = company_manager.take_cancelled_orders();
+        assert_eq!(cancelled.len(), 1);
+        assert!(cancelled[0].order.id().equals(order.id()));
+        let cancelled_events = events::events_of_cancelled(&cancelled, &user_manager).unwrap();
+        assert!(cancelled_events[0].0.equals(first));
+        assert_eq!(cancelled_events[0].1[0].name(), "order_cancelled");
+        assert!(company_manager.take_cancelled_orders().is_empty());
+    }
+
+    #[test]
+    fn share_supply_test() {
+        let mut company_manager = CompanyManager::new();
+        let penny = company_manager.new_company(String::from("Penny"), 0.5).unwrap();
+        company_manager.get_company_by_id_mut(penny).unwrap().set_liquidity(0);
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("up-down-up-down-left-left")).unwrap();
+        let first = user_manager.new_user(String::from("ozone"), String::from("Ozone"), password.clone()).unwrap();
+        let second = user_manager.new_user(String::from("quartz"), String::from("Quartz"), password).unwrap();
+
+        //Only the shares the house holds can be bought
+        assert!(company_manager.trade(&mut user_manager, first, TradeSide::Buy, penny, 1001, TradeKind::Market).is_err());
+        company_manager.trade(&mut user_manager, first, TradeSide::Buy, penny, 600, TradeKind::Market).unwrap();
+        assert!(company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 500, TradeKind::Market).is_err());
+        assert!(company_manager.place_order(&mut user_manager, Order::new(penny, second, TradeSide::Buy, 500, 0.5)).is_err());
+
+        let company = company_manager.get_company_by_id(penny).unwrap();
+        assert_eq!((company.shares_held(), company.house_shares()), (600, 400));
+        assert_eq!(company.market_cap(), 500.0);
+        assert_eq!(company.held_percent(), 60.0);
+
+        //Selling gives the shares back to the house
+        company_manager.trade(&mut user_manager, first, TradeSide::Sell, penny, 100, TradeKind::Market).unwrap();
+        assert_eq!(company_manager.get_company_by_id(penny).unwrap().house_shares(), 500);
+
+        company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 460, TradeKind::Market).unwrap();
+
+        //The market maker only offers what the house holds, players trading with each other keep the count
+        company_manager.set_exchange_mode(true);
+        company_manager.apply_prices(&[(penny, 0.5)]).unwrap();
+        assert!(company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 41, TradeKind::Market).is_err());
+        company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 40, TradeKind::Market).unwrap();
+        company_manager.place_order(&mut user_manager, Order::new(penny, first, TradeSide::Sell, 100, 0.5)).unwrap();
+        company_manager.trade(&mut user_manager, second, TradeSide::Buy, penny, 50, TradeKind::Market).unwrap();
+        assert_eq!(company_manager.get_company_by_id(penny).unwrap().house_shares(), 0);
+
+        //The held shares are counted again from the wallets when a game loads
+        let mut loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        let loaded_users = UserManager::load_data(&user_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.get_company_by_id(penny).unwrap().shares_held(), 0);
+        loaded_companies.count_shares_held(&loaded_users);
+        assert_eq!(loaded_companies.get_company_by_id(penny).unwrap().shares_held(), 1000);
+    }
+
+    #[test]
+    fn price_impact_test() {
+        let mut company_manager = CompanyManager::new();
+        let whale = company_manager.new_company(String::from("Whale"), 100.0).unwrap();
+        company_manager.get_company_by_id_mut(whale).unwrap().set_liquidity(100);
+        let mut user_manager = UserManager::new();
+        let password = Password::from_text(&String::from("down-left-down-left-up-up")).unwrap();
+        let user_id = user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+
+        //Bigger trades get a worse price
+        let company = company_manager.get_company_by_id(whale).unwrap();
+        assert!(company.execution_price(TradeSide::Buy, 1) < company.execution_price(TradeSide::Buy, 5));
+        assert!(company.execution_price(TradeSide::Sell, 1) > company.execution_price(TradeSide::Sell, 5));
+
+        //A buy fills halfway to the price it pushes the company to
+        let fills = company_manager.trade(&mut user_manager, user_id, TradeSide::Buy, whale, 5, TradeKind::Market).unwrap();
+        assert!((fills[0].trade.price() - 102.5).abs() < 0.001);
+        assert!((company_manager.get_company_by_id(whale).unwrap().stock_price() - 105.0).abs() < 0.001);
+
+        //Selling the same amount pushes it back down
+        let fills = company_manager.trade(&mut user_manager, user_id, TradeSide::Sell, whale, 5, TradeKind::Market).unwrap();
+        assert!((fills[0].trade.price() - 102.5).abs() < 0.001);
+        assert!((company_manager.get_company_by_id(whale).unwrap().stock_price() - 100.0).abs() < 0.001);
+
+        //Limit orders move the price too, but never fill past their limit
+        company_manager.place_order(&mut user_manager, Order::new(whale, user_id, TradeSide::Buy, 2, 100.5)).unwrap();
+        let fills = company_manager.fill_orders(&mut user_manager);
+        assert_eq!(fills[0].trade.price(), 100.5);
+        assert!((company_manager.get_company_by_id(whale).unwrap().stock_price() - 102.0).abs() < 0.001);
+        assert_eq!(user_manager.get_user_by_id(user_id).unwrap().reserved_money(), 0.0);
+        //Trades move the price without adding to the history
+        assert_eq!(company_manager.get_company_by_id(whale).unwrap().stock_price_history(), &vec![100.0]);
+
+        //The liquidity and moved price are saved, no liquidity means no impact
+        let mut loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        let company = loaded_companies.get_company_by_id_mut(whale).unwrap();
+        assert_eq!(company.liquidity(), 100);
+        assert!((company.stock_price() - 102.0).abs() < 0.001);
+        company.set_liquidity(0);
+        assert_eq!(company.execution_price(TradeSide::Buy, 500), company.stock_price());
+    }
+
+    #[test]
+    fn price_model_test() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let models : Vec<Box<dyn PriceModel>> = vec![
+            Box::new(UniformWalk::new(0.0, 20.0)),
+            Box::new(GeometricBrownian::new(0.0, 0.05)),
+            Box::new(MeanReversion::new(200.0, 0.1, 0.05)),
+            Box::new(JumpDiffusion::new(0.0, 0.03, 0.5, 0.2)),
+        ];
+
+        //A cheap stock never goes below the lowest price, whatever the model
+        let mut company_manager = CompanyManager::new();
+        for model in models {
+            let company_id = company_manager.new_company(String::from(model.name()), 5.0).unwrap();
+            company_manager.get_company_by_id_mut(company_id).unwrap().set_price_model(model);
+        }
+        for _ in 0..500 {
+            company_manager.update();
+        }
+        for company in company_manager.companies() {
+            assert!(company.stock_price_history().iter().all(|price| price.is_finite() && *price >= price_model::MIN_PRICE));
+        }
+
+        //Without volatility the models only drift
+        assert_eq!(GeometricBrownian::new(0.0, 0.0).next_price(50.0, &mut rng), 50.0);
+        assert!((GeometricBrownian::new(0.01, 0.0).next_price(50.0, &mut rng) - 50.0 * 0.01f32.exp()).abs() < 0.001);
+        assert_eq!(UniformWalk::new(1.0, 0.0).next_price(50.0, &mut rng), 51.0);
+
+        //Mean reversion pulls a price back towards the mean
+        let reverting = MeanReversion::new(200.0, 0.2, 0.02);
+        let mut price = 20.0;
+        for _ in 0..100 {
+            price = reverting.next_price(price, &mut rng);
+        }
+        assert!(price > 150.0 && price < 250.0);
+
+        //The model and its parameters are saved with the company, bad ones are refused
+        let loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.save_data(), company_manager.save_data());
+        assert_eq!(loaded_companies.companies()[3].price_model().parameters(), vec![("drift", 0.0), ("volatility", 0.03), ("jump_chance", 0.5), ("jump_size", 0.2)]);
+        assert!(Company::load_data("company\tid=0\tname=Bad\tprice=5\tmodel=sideways").is_err());
+        assert!(Company::load_data("company\tid=0\tname=Bad\tprice=5\tmodel=geometric_brownian\tvolatility=-1").is_err());
+    }
+
+    #[test]
+    fn seeded_market_test() {
+        //Plays the same trades on a market drawn from the seed, recording every change
+        let play = |seed : u64| -> (Snapshot, Vec<JournalEntry>) {
+            let mut game = Snapshot::new();
+            let mut entries : Vec<JournalEntry> = Vec::new();
+            game.company_manager.set_seed(seed, 0);
+            entries.push(JournalEntry::Seed(seed, 0));
+
+            let apple = game.company_manager.new_company(String::from("Apple"), 200.0).unwrap();
+            game.company_manager.get_company_by_id_mut(apple).unwrap().set_price_model(Box::new(JumpDiffusion::new(0.0, 0.03, 0.2, 0.15)));
+            entries.push(JournalEntry::company_state(game.company_manager.get_company_by_id(apple).unwrap()));
+
+            let password = Password::from_text(&String::from("up-up-down-down-left-right")).unwrap();
+            let user_id = game.user_manager.new_user(String::from("ozone"), String::from("Ozone"), password).unwrap();
+            entries.push(JournalEntry::new_user(game.user_manager.get_user_by_id(user_id).unwrap()));
+
+            for step in 0..30 {
+                entries.push(JournalEntry::Tick(game.company_manager.update()));
+                for event in game.company_manager.breaking_news() {
+                    entries.push(JournalEntry::news(event));
+                }
+                if step == 5 {
+                    game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Buy, apple, 2, TradeKind::Market).unwrap();
+                    entries.push(JournalEntry::Buy(user_id, apple, 2));
+                }
+                if step == 20 {
+                    game.company_manager.trade(&mut game.user_manager, user_id, TradeSide::Sell, apple, 2, TradeKind::Market).unwrap();
+                    entries.push(JournalEntry::Sell(user_id, apple, 2));
+                }
+            }
+
+            (game, entries)
+        };
+
+        //The same seed and trades give the same prices, another seed doesn't
+        let (mut game, entries) = play(42);
+        let (same, _entries) = play(42);
+        let (other, _entries) = play(43);
+        let history = game.company_manager.companies()[0].stock_price_history().clone();
+        assert_eq!(same.company_manager.companies()[0].stock_price_history(), &history);
+        assert_ne!(other.company_manager.companies()[0].stock_price_history(), &history);
+
+        //A replayed game goes on drawing the prices the live one does
+        let mut replayed = Snapshot::new();
+        for entry in entries {
+            let written = JournalEntry::load_data(&entry.get_data()).unwrap();
+            written.apply(&mut replayed).unwrap();
+        }
+        assert_eq!(replayed.company_manager.tick(), game.company_manager.tick());
+        replayed.company_manager.update();
+        game.company_manager.update();
+        assert_eq!(replayed.company_manager.save_data(), game.company_manager.save_data());
+    }
+
+    #[test]
+    fn news_test() {
+        //Without volatility only the market, the sector and the news move prices
+        let mut company_manager = CompanyManager::new();
+        company_manager.set_seed(7, 0);
+        let apple = company_manager.new_company(String::from("Apple"), 100.0).unwrap();
+        let amazon = company_manager.new_company(String::from("Amazon"), 100.0).unwrap();
+        company_manager.get_company_by_id_mut(apple).unwrap().set_price_model(Box::new(GeometricBrownian::new(0.0, 0.0)));
+        company_manager.get_company_by_id_mut(amazon).unwrap().set_price_model(Box::new(GeometricBrownian::new(0.0, 0.0)));
+
+        //A scandal jumps the price down when it breaks, then drags it down while it lasts
+        //Both companies are in the same sector, so only the news sets them apart
+        company_manager.add_news(NewsEvent::new(NewsKind::Scandal, vec![apple], String::from("Scandal rocks Apple"), 1, -0.1, -0.01, 2));
+        for _ in 0..3 {
+            company_manager.update();
+        }
+        assert_eq!(company_manager.news().len(), 1);
+        let apple_price = company_manager.get_company_by_id(apple).unwrap().stock_price();
+        let amazon_price = company_manager.get_company_by_id(amazon).unwrap().stock_price();
+        assert!((apple_price / amazon_price - 0.9 * (-0.02f32).exp()).abs() < 0.001);
+
+        //News breaks now and then, only the latest is kept
+        for _ in 0..500 {
+            company_manager.update();
+        }
+        assert!(company_manager.news().len() > 1 && company_manager.news().len() <= news::MAX_NEWS);
+        assert!(company_manager.news().iter().all(|event| !event.company_ids().is_empty()));
+
+        //The news is saved with the companies
+        let loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.save_data(), company_manager.save_data());
+        assert!(NewsEvent::load_data("news\tkind=rumour\tcompany_ids=0\theadline=Hm\ttick=1\tjump=0\tdrift=0\tduration=1").is_err());
+    }
+
+    #[test]
+    fn sector_test() {
+        //Two technology companies and an energy one, with little noise of their own
+        let mut company_manager = CompanyManager::new();
+        company_manager.set_seed(11, 0);
+        let mut company_ids : Vec<ID> = Vec::new();
+        for (name, sector) in [("Apple", "Technology"), ("Microsoft", "Technology"), ("Exxon", "Energy")].iter() {
+            let company_id = company_manager.new_company(String::from(*name), 100.0).unwrap();
+            let company = company_manager.get_company_by_id_mut(company_id).unwrap();
+            company.set_sector(String::from(*sector));
+            company.set_price_model(Box::new(GeometricBrownian::new(0.0, 0.005)));
+            company_ids.push(company_id);
+        }
+        for _ in 0..400 {
+            company_manager.update();
+        }
+
+        //Gets the correlation of the price changes of two companies
+        let returns = |company_id : ID| -> Vec<f32> {
+            let history = company_manager.get_company_by_id(company_id).unwrap().stock_price_history();
+            history.windows(2).map(|prices| (prices[1] / prices[0]).ln()).collect()
+        };
+        let correlation = |a : &Vec<f32>, b : &Vec<f32>| -> f32 {
+            let mean_a = a.iter().sum::<f32>() / a.len() as f32;
+            let mean_b = b.iter().sum::<f32>() / b.len() as f32;
+            let covariance : f32 = a.iter().zip(b.iter()).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+            let variance_a : f32 = a.iter().map(|x| (x - mean_a) * (x - mean_a)).sum();
+            let variance_b : f32 = b.iter().map(|y| (y - mean_b) * (y - mean_b)).sum();
+            covariance / (variance_a * variance_b).sqrt()
+        };
+        let apple = returns(company_ids[0]);
+        let microsoft = returns(company_ids[1]);
+        let exxon = returns(company_ids[2]);
+        let same_sector = correlation(&apple, &microsoft);
+        let other_sector = correlation(&apple, &exxon);
+
+        //Companies in a sector move together, and the market moves every sector a little
+        //News about a single company keeps them from moving exactly together
+        assert!(same_sector > 0.4);
+        assert!(other_sector > 0.05 && other_sector < same_sector - 0.2);
+
+        //The sector is saved with the company, older saves are in the default sector
+        let loaded_companies = CompanyManager::load_data(&company_manager.save_data()).unwrap();
+        assert_eq!(loaded_companies.companies()[2].sector(), "Energy");
+        assert_eq!(Company::load_data("company\tid=0\tname=Old\tprice=5").unwrap().sector(), company::DEFAULT_SECTOR);
+    }
+
+    #[test]
+    fn catalogue_test() {
+        //The catalogue shipped with the game is valid
+        assert!(Catalogue::load("config/companies.json").is_ok());
+
+        let catalogue = Catalogue::parse(r#"{
+            "warmup_ticks": 5,
+            "companies": [
+                { "name": "Apple", "ticker": "AAPL", "sector": "Technology", "price": 150.0, "shares": 200, "model": { "name": "mean_reversion", "mean": 150.0, "speed": 0.2 } },
+                { "name": "Shell", "ticker": "SHEL", "price": 30.0, "liquidity": 0 }
+            ]
+        }"#).unwrap();
+
+        //A new game gets the companies as they are written, with their warm up
+        let folder = std::env::temp_dir().join(format!("fake_stocks_catalogue_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut journal = Journal::create_at(&folder.join("journal"), 0).unwrap();
+        let mut company_manager = CompanyManager::new();
+        let old_company = company_manager.new_company(String::from("Pets.com"), 10.0).unwrap();
+        super::reset_company_manager(&mut company_manager, &mut journal, &catalogue, 100).unwrap();
+
+        let apple = company_manager.get_company_by_name(&String::from("Apple")).unwrap();
+        assert_eq!((apple.ticker().as_str(), apple.sector().as_str(), apple.shares_outstanding(), apple.liquidity()), ("AAPL", "Technology", 200, 100));
+        assert_eq!(apple.price_model().parameters(), vec![("mean", 150.0), ("speed", 0.2), ("volatility", 0.05)]);
+        assert_eq!(apple.stock_price_history().len(), 6);
+        let shell = company_manager.get_company_by_name(&String::from("Shell")).unwrap();
+        assert_eq!((shell.sector().as_str(), shell.shares_outstanding(), shell.liquidity()), (company::DEFAULT_SECTOR, company::DEFAULT_SHARES_OUTSTANDING, 0));
+        //Companies that left the catalogue keep trading
+        assert!(company_manager.get_company_by_id(old_company).is_ok());
+
+        //Tickers can be swapped, or taken from a company that left the catalogue
+        let swapped = Catalogue::parse(r#"{ "warmup_ticks": 0, "companies": [
+            { "name": "Apple", "ticker": "SHEL", "price": 150.0 },
+            { "name": "Shell", "ticker": "AAPL", "price": 30.0 },
+            { "name": "Pets", "ticker": "PETS", "price": 1.0 }
+        ] }"#).unwrap();
+        let before_swap = company_manager.save_data();
+        super::reset_company_manager(&mut company_manager, &mut journal, &swapped, 100).unwrap();
+        assert_eq!(company_manager.find_company("Apple").unwrap().ticker(), "SHEL");
+        assert_eq!(company_manager.find_company("Shell").unwrap().ticker(), "AAPL");
+        assert_eq!(company_manager.find_company("Pets").unwrap().ticker(), "PETS");
+        assert_eq!(company_manager.get_company_by_id(old_company).unwrap().ticker(), "PETS2");
+
+        //Replaying the reset gives every company the same ticker
+        let mut replayed = CompanyManager::load_data(&before_swap).unwrap();
+        for company in company_manager.companies() {
+            replayed.restore_company(Company::load_data(&company.save_data()).unwrap());
+        }
+        for company in company_manager.companies() {
+            assert_eq!(replayed.get_company_by_id(company.id()).unwrap().ticker(), company.ticker());
+        }
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        //Every problem is reported with the company it is in
+        let errors = Catalogue::parse(r#"{ "companies": [
+            { "name": "Apple", "ticker": "AAPL", "price": -1.0 },
+            { "name": "apple", "ticker": "AAPL", "price": 5.0, "model": { "name": "geometric_brownian", "volatilty": 0.1 } }
+        ] }"#).err().unwrap();
+        assert!(errors.contains("company 1 (Apple): the price should be above 0, found -1"));
+        assert!(errors.contains("company 2 (apple): another company has the same name"));
+        assert!(Catalogue::parse(r#"{ "companies": [ { "name": "Shell", "ticker": "SHEL", "price": 5.0 }, { "name": " shell ", "ticker": "SHL", "price": 5.0 } ] }"#).err().unwrap().contains("another company has the same name"));
+        assert!(errors.contains("company 2 (apple): another company has the ticker AAPL"));
+        assert!(errors.contains("has no parameter 'volatilty'"));
+        assert!(Catalogue::parse(r#"{ "companies": [ { "name": "Apple", "ticker": "aapl", "price": 5.0 } ] }"#).err().unwrap().contains("The ticker 'aapl'"));
+        assert!(Catalogue::parse(r#"{ "companies": [ { "name": "Apple", "ticker": "AAPL", "prize": 5.0 } ] }"#).err().unwrap().contains("unknown field `prize`"));
+        assert!(Catalogue::parse(r#"{ "companies": [] }"#).is_err());
+    }
+
+    /// Times lookups as the game grows, they should cost about the same at every size
+    /// Run with `cargo test --release -- --ignored --nocapture lookup_benchmark > bench_output.txt`
+    #[test]
+    #[ignore]
+    fn lookup_benchmark() {
+        const LOOKUPS : usize = 100_000;
+        let password = Password::new("left-left-left-left-left-left");
+        let mut results : Vec<(usize, [f64; 5])> = Vec::new();
+
+        for size in [100, 1_000, 10_000] {
+            //Hashing is slow, so every user shares a password
+            let mut user_manager = UserManager::new();
+            let mut client_tracker = ClientTracker::new();
+            let mut company_manager = CompanyManager::new();
+            let mut user_ids : Vec<ID> = Vec::new();
+            let mut tokens : Vec<String> = Vec::new();
+            let mut company_ids : Vec<ID> = Vec::new();
+            for number in 0..size {
+                let user_id = user_manager.new_user(format!("user{}", number), format!("User {}", number), password.clone()).unwrap();
+                tokens.push(client_tracker.add_client(user_id));
+                user_ids.push(user_id);
+                //Hex names give every company its own ticker
+                company_ids.push(company_manager.new_company(format!("{:X}", number), 10.0).unwrap());
+            }
+            let usernames : Vec<String> = (0..size).map(|number| format!("user{}", number)).collect();
+            let display_names : Vec<String> = (0..size).map(|number| format!("User {}", number)).collect();
+
+            //Looks up spread out entries, so the last users are timed too
+            let nanos_per_lookup = |lookup : &mut dyn FnMut(usize)| {
+                let start = std::time::Instant::now();
+                for number in 0..LOOKUPS {
+                    lookup(number * 7919 % size);
+                }
+                start.elapsed().as_nanos() as f64 / LOOKUPS as f64
+            };
+            let timings = [
+                nanos_per_lookup(&mut |index| assert!(user_manager.get_user_by_id(user_ids[index]).is_ok())),
+                nanos_per_lookup(&mut |index| assert!(user_manager.get_user_by_username(&usernames[index]).is_ok())),
+                nanos_per_lookup(&mut |index| assert!(user_manager.get_user_by_display_name(&display_names[index]).is_ok())),
+                nanos_per_lookup(&mut |index| assert!(client_tracker.authenticate(&tokens[index]).unwrap().equals(user_ids[index]))),
+                nanos_per_lookup(&mut |index| assert!(company_manager.get_company_by_id(company_ids[index]).is_ok())),
+            ];
+            results.push((size, timings));
+        }
+
+        println!("{:>8} {:>10} {:>10} {:>13} {:>10} {:>10}", "users", "user id", "username", "display name", "session", "company id");
+        for (size, timings) in results.iter() {
+            println!("{:>8} {:>8.1}ns {:>8.1}ns {:>11.1}ns {:>8.1}ns {:>8.1}ns", size, timings[0], timings[1], timings[2], timings[3], timings[4]);
+        }
+
+        //A linear scan would be about 100 times slower at 10,000 users than at 100
+        let (smallest, largest) = (results[0].1, results[results.len() - 1].1);
+        for (small, large) in smallest.iter().zip(largest.iter()) {
+            assert!(*large < small * 10.0, "Lookups slowed from {:.1}ns to {:.1}ns", small, large);
+        }
     }
 }
@@ -0,0 +1,859 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+use crate::companies::company::Company;
+use crate::companies::company_manager::CompanyManager;
+use crate::companies::news::NewsKind;
+use crate::servers::request::Request;
+use crate::servers::response::Response;
+use crate::servers::router::{Router, Params};
+use crate::companies::order_book::{Order, TradeSide};
+use crate::companies::protection::{Protection, ProtectionKind};
+use crate::users::trade_history::TradeKind;
+use crate::servers::server::{self, ServerState, TradeError};
+use crate::users::ranking::Ranker;
+use crate::ID;
+
+/// The body of every failed API request
+#[derive(Serialize)]
+struct ErrorBody {
+    error : ApiError,
+}
+
+/// What went wrong, code is stable for programs to match on and message is for people
+#[derive(Serialize)]
+struct ApiError {
+    code : &'static str,
+    message : String,
+}
+
+#[derive(Serialize)]
+struct CompanySummary {
+    id : usize,
+    name : String,
+    /// The short symbol players trade the company by
+    ticker : String,
+    /// Companies in the same sector move together
+    sector : String,
+    price : f32,
+    shares_outstanding : usize,
+    /// Shares held by players, the house holds the rest
+    shares_held : usize,
+    market_cap : f32,
+    held_percent : f32,
+}
+
+#[derive(Serialize)]
+struct CompanyDetails {
+    id : usize,
+    name : String,
+    ticker : String,
+    sector : String,
+    price : f32,
+    shares_outstanding : usize,
+    shares_held : usize,
+    market_cap : f32,
+    held_percent : f32,
+    /// Every player holding shares, largest first
+    shareholders : Vec<Shareholder>,
+    price_model : PriceModelDetails,
+    history : Vec<f32>,
+}
+
+/// How the price of a company moves each tick
+#[derive(Serialize)]
+struct PriceModelDetails {
+    name : &'static str,
+    parameters : BTreeMap<&'static str, f32>,
+}
+
+#[derive(Serialize)]
+struct Shareholder {
+    name : String,
+    shares : usize,
+    percent : f32,
+}
+
+#[derive(Serialize)]
+struct PriceHistory {
+    name : String,
+    history : Vec<f32>,
+}
+
+/// The shares on offer at one price, from both users and the market maker
+#[derive(Serialize)]
+struct BookLevel {
+    price : f32,
+    amount : usize,
+}
+
+/// The buy and sell side of a company's order book, best prices first
+#[derive(Serialize)]
+struct OrderBookDepth {
+    name : String,
+    exchange_mode : bool,
+    bids : Vec<BookLevel>,
+    asks : Vec<BookLevel>,
+}
+
+/// A story that moved or is moving prices
+#[derive(Serialize)]
+struct NewsItem {
+    tick : u64,
+    kind : NewsKind,
+    headline : String,
+    /// The names of the companies it moves
+    companies : Vec<String>,
+    /// The fraction the prices jumped by when it broke
+    jump : f32,
+    /// Added to the drift of the prices each tick it lasts
+    drift : f32,
+    /// If it still moves prices on the next tick
+    active : bool,
+}
+
+#[derive(Serialize)]
+struct Holding {
+    company_id : usize,
+    company : String,
+    amount : usize,
+    /// Shares held back for open sell orders
+    reserved : usize,
+    average_price : f32,
+    price : f32,
+    value : f32,
+}
+
+#[derive(Serialize)]
+struct Portfolio {
+    cash : f32,
+    /// Cash held back for open buy orders, part of cash
+    reserved_cash : f32,
+    stock_value : f32,
+    total_value : f32,
+    holdings : Vec<Holding>,
+}
+
+#[derive(Serialize)]
+struct Cash {
+    cash : f32,
+}
+
+#[derive(Serialize)]
+struct Ranking {
+    rank : usize,
+    name : String,
+    value : f32,
+}
+
+#[derive(Serialize)]
+struct Leaderboard {
+    rankings : Vec<Ranking>,
+}
+
+/// The body of a trade request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TradeRequest {
+    side : TradeSide,
+    /// The ticker, name or ID of the company
+    company : String,
+    amount : usize,
+}
+
+/// The body of a limit order request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrderRequest {
+    side : TradeSide,
+    /// The ticker, name or ID of the company
+    company : String,
+    amount : usize,
+    limit_price : f32,
+}
+
+#[derive(Serialize)]
+struct OrderDetails {
+    id : usize,
+    side : TradeSide,
+    company_id : usize,
+    company : String,
+    amount : usize,
+    limit_price : f32,
+}
+
+/// The body of a protection request, stop-losses and take-profits need a trigger_price, trailing stops a distance
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProtectionRequest {
+    kind : ProtectionKind,
+    /// The ticker, name or ID of the company
+    company : String,
+    amount : usize,
+    trigger_price : Option<f32>,
+    distance : Option<f32>,
+}
+
+#[derive(Serialize)]
+struct ProtectionDetails {
+    id : usize,
+    kind : ProtectionKind,
+    company_id : usize,
+    company : String,
+    amount : usize,
+    trigger_price : f32,
+    distance : f32,
+}
+
+#[derive(Serialize)]
+struct PastTrade {
+    kind : TradeKind,
+    side : TradeSide,
+    company_id : usize,
+    company : String,
+    amount : usize,
+    price : f32,
+}
+
+#[derive(Serialize)]
+struct TradeReceipt {
+    side : TradeSide,
+    company_id : usize,
+    company : String,
+    /// The shares that traded, fewer than asked for if the book ran out of ones that could
+    amount : usize,
+    /// The average price each share traded at
+    price : f32,
+    /// The price before the trade
+    quoted_price : f32,
+    /// How much worse the trade went than at the quoted price
+    slippage : f32,
+    total : f32,
+    cash : f32,
+    shares_held : usize,
+}
+
+/// Makes a response with a JSON body
+fn json<T : Serialize>(status_line : &'static str, body : &T) -> Response {
+    match serde_json::to_string(body) {
+        Ok(body) => Response::with_status(status_line, body).with_header("Content-Type", "application/json"),
+        Err(error) => internal_error(error.to_string()),
+    }
+}
+
+/// Makes a successful JSON response
+fn json_ok<T : Serialize>(body : &T) -> Response {
+    json("HTTP/1.1 200 OK", body)
+}
+
+/// Makes a JSON error response
+fn api_error(status_line : &'static str, code : &'static str, message : String) -> Response {
+    let body = ErrorBody { error : ApiError { code, message } };
+    match serde_json::to_string(&body) {
+        Ok(body) => Response::with_status(status_line, body).with_header("Content-Type", "application/json"),
+        Err(_error) => Response::with_status("HTTP/1.1 500 INTERNAL SERVER ERROR", String::new()),
+    }
+}
+
+fn bad_request(message : String) -> Response {
+    api_error("HTTP/1.1 400 BAD REQUEST", "bad_request", message)
+}
+
+fn unauthorized(message : String) -> Response {
+    api_error("HTTP/1.1 401 UNAUTHORIZED", "unauthorized", message)
+}
+
+fn not_found(message : String) -> Response {
+    api_error("HTTP/1.1 404 NOT FOUND", "not_found", message)
+}
+
+fn internal_error(message : String) -> Response {
+    api_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "internal_error", message)
+}
+
+/// Gets the user of the request, or the error response to send back
+fn authenticate(request : &Request, state : &ServerState) -> Result<ID, Response> {
+    server::get_user_id_from_request(request, &state.client_tracker).map_err(unauthorized)
+}
+
+/// Finds the company whose ticker, name or ID is in the path, or the error response to send back
+fn find_company<'a>(params : &Params, company_manager : &'a CompanyManager) -> Result<&'a Company, Response> {
+    let name = match params.get("name") {
+        Some(name) => name,
+        None => return Err(bad_request(String::from("No company name in the path"))),
+    };
+
+    company_manager.find_company(name).map_err(not_found)
+}
+
+/// Lists every company and its current price
+fn list_companies(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let companies : Vec<CompanySummary> = company_manager.companies()
+        .iter()
+        .map(|company| CompanySummary {
+            id : company.id().value(),
+            name : company.name().clone(),
+            ticker : company.ticker().clone(),
+            sector : company.sector().clone(),
+            price : company.stock_price(),
+            shares_outstanding : company.shares_outstanding(),
+            shares_held : company.shares_held(),
+            market_cap : company.market_cap(),
+            held_percent : company.held_percent(),
+        })
+        .collect();
+
+    Ok(json_ok(&companies))
+}
+
+/// Gets one company with its shareholders and price history
+fn get_company(_request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let company = match find_company(params, &company_manager) {
+        Ok(company) => company,
+        Err(response) => return Ok(response),
+    };
+
+    let mut shareholders : Vec<Shareholder> = Vec::new();
+    for user in user_manager.users() {
+        let shares = user.wallet().holders()
+            .iter()
+            .filter(|holder| holder.company_id().equals(company.id()))
+            .map(|holder| holder.stock_amount())
+            .sum();
+        if shares > 0 {
+            let percent = shares as f32 * 100.0 / company.shares_outstanding() as f32;
+            shareholders.push(Shareholder { name : user.display_name().clone(), shares, percent });
+        }
+    }
+    shareholders.sort_by_key(|shareholder| std::cmp::Reverse(shareholder.shares));
+
+    Ok(json_ok(&CompanyDetails {
+        id : company.id().value(),
+        name : company.name().clone(),
+        ticker : company.ticker().clone(),
+        sector : company.sector().clone(),
+        price : company.stock_price(),
+        shares_outstanding : company.shares_outstanding(),
+        shares_held : company.shares_held(),
+        market_cap : company.market_cap(),
+        held_percent : company.held_percent(),
+        shareholders,
+        price_model : PriceModelDetails {
+            name : company.price_model().name(),
+            parameters : company.price_model().parameters().into_iter().collect(),
+        },
+        history : company.stock_price_history().clone(),
+    }))
+}
+
+/// Gets the price history of one company, oldest first
+fn get_price_history(_request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    match find_company(params, &company_manager) {
+        Ok(company) => Ok(json_ok(&PriceHistory { name : company.name().clone(), history : company.stock_price_history().clone() })),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Adds up the shares on one side of a book at each price, best price first
+fn book_levels(company : &Company, side : TradeSide) -> Vec<BookLevel> {
+    let order_book = company.order_book();
+    let offers = order_book.orders()
+        .iter()
+        .filter(|order| order.side() == side)
+        .map(|order| (order.limit_price(), order.amount()))
+        .chain(order_book.quotes()
+            .iter()
+            .filter(|quote| quote.side() == side)
+            .map(|quote| (quote.price(), quote.amount())));
+
+    let mut levels : Vec<BookLevel> = Vec::new();
+    for (price, amount) in offers {
+        match levels.iter_mut().find(|level| level.price == price) {
+            Some(level) => level.amount += amount,
+            None => levels.push(BookLevel { price, amount }),
+        }
+    }
+
+    //Buyers want the highest price first, sellers the lowest
+    match side {
+        TradeSide::Buy => levels.sort_by(|a, b| b.price.total_cmp(&a.price)),
+        TradeSide::Sell => levels.sort_by(|a, b| a.price.total_cmp(&b.price)),
+    }
+    levels
+}
+
+/// Gets the latest news, newest first
+/// The page reads it from /news as well
+pub fn get_news(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    //News about a company that was removed leaves it out
+    let news : Vec<NewsItem> = company_manager.news()
+        .iter()
+        .rev()
+        .map(|event| NewsItem {
+            tick : event.tick(),
+            kind : event.kind(),
+            headline : event.headline().clone(),
+            companies : event.company_ids()
+                .iter()
+                .filter_map(|company_id| company_manager.get_company_by_id(*company_id).ok())
+                .map(|company| company.name().clone())
+                .collect(),
+            jump : event.jump(),
+            drift : event.drift(),
+            active : event.active(company_manager.tick() + 1),
+        })
+        .collect();
+
+    Ok(json_ok(&news))
+}
+
+/// Gets the open orders and market maker quotes of one company
+fn get_order_book(_request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let company = match find_company(params, &company_manager) {
+        Ok(company) => company,
+        Err(response) => return Ok(response),
+    };
+
+    Ok(json_ok(&OrderBookDepth {
+        name : company.name().clone(),
+        exchange_mode : company_manager.exchange_mode(),
+        bids : book_levels(company, TradeSide::Buy),
+        asks : book_levels(company, TradeSide::Sell),
+    }))
+}
+
+/// Gets the users cash and every stock they hold
+fn get_portfolio(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    //Users are locked before companies, the same as trades
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let user = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Ok(unauthorized(error)),
+    };
+
+    let mut holdings : Vec<Holding> = Vec::new();
+    let mut stock_value = 0.0;
+    for holder in user.wallet().holders() {
+        let company = match company_manager.get_company_by_id(holder.company_id()) {
+            Ok(company) => company,
+            Err(error) => return Ok(internal_error(error)),
+        };
+        let value = company.stock_price() * holder.stock_amount() as f32;
+        stock_value += value;
+
+        holdings.push(Holding {
+            company_id : holder.company_id().value(),
+            company : holder.company_name().clone(),
+            amount : holder.stock_amount(),
+            reserved : holder.reserved_amount(),
+            average_price : holder.avg_purchase_price(),
+            price : company.stock_price(),
+            value,
+        });
+    }
+
+    Ok(json_ok(&Portfolio { cash : user.money(), reserved_cash : user.reserved_money(), stock_value, total_value : user.money() + stock_value, holdings }))
+}
+
+/// Gets the users cash
+fn get_cash(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    match user_manager.get_user_by_id(user_id) {
+        Ok(user) => Ok(json_ok(&Cash { cash : user.money() })),
+        Err(error) => Ok(unauthorized(error)),
+    }
+}
+
+/// Turns a ranker into the leaderboard sent to the client
+fn leaderboard(ranker : &Ranker) -> Leaderboard {
+    let rankings = ranker.ranks()
+        .iter()
+        .enumerate()
+        .map(|(pos, rank)| Ranking { rank : pos + 1, name : rank.name().clone(), value : rank.value() })
+        .collect();
+
+    Leaderboard { rankings }
+}
+
+/// Gets the current leaderboard
+fn get_leaderboard(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    match state.ranker.read() {
+        Ok(ranker) => Ok(json_ok(&leaderboard(&ranker))),
+        Err(error) => Ok(internal_error(error.to_string())),
+    }
+}
+
+/// Gets the leaderboard from before the last reset
+fn get_previous_leaderboard(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let ranker_history = match state.ranker_history.read() {
+        Ok(ranker_history) => ranker_history,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    match ranker_history.get_recent() {
+        Some(ranker) => Ok(json_ok(&leaderboard(ranker))),
+        None => Ok(not_found(String::from("There is no previous leaderboard yet"))),
+    }
+}
+
+/// Buys or sells stock, the body is {"side": "buy" or "sell", "company": name, "amount": shares}
+fn post_trade(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let body = match request.body_text() {
+        Ok(body) => body,
+        Err(error) => return Ok(bad_request(error)),
+    };
+
+    let trade : TradeRequest = match serde_json::from_str(&body) {
+        Ok(trade) => trade,
+        Err(error) => return Ok(bad_request(format!("Invalid trade: {}", error))),
+    };
+
+    if trade.amount == 0 {
+        return Ok(bad_request(String::from("Amount must be at least 1")));
+    }
+
+    match server::execute_trade(state, user_id, trade.side, &trade.company, trade.amount) {
+        Ok(result) => Ok(json_ok(&TradeReceipt {
+            side : trade.side,
+            company_id : result.company_id.value(),
+            company : result.company_name,
+            amount : result.amount,
+            price : result.price,
+            quoted_price : result.quoted_price,
+            slippage : match trade.side {
+                TradeSide::Buy => (result.price - result.quoted_price) * result.amount as f32,
+                TradeSide::Sell => (result.quoted_price - result.price) * result.amount as f32,
+            },
+            total : result.price * result.amount as f32,
+            cash : result.money,
+            shares_held : result.shares_held,
+        })),
+        Err(error) => Ok(trade_error(error)),
+    }
+}
+
+/// Turns a failed trade or order into its error response
+fn trade_error(error : TradeError) -> Response {
+    match error {
+        TradeError::UnknownCompany(error) => not_found(error),
+        TradeError::UnknownOrder(error) => not_found(error),
+        TradeError::Rejected(error) => api_error("HTTP/1.1 422 UNPROCESSABLE ENTITY", "trade_rejected", error),
+        TradeError::Failed(error) => internal_error(error),
+    }
+}
+
+/// Turns an order into what is sent to the client
+fn order_details(order : &Order, company_manager : &CompanyManager) -> OrderDetails {
+    let company = company_manager.get_company_by_id(order.company_id())
+        .map(|company| company.name().clone())
+        .unwrap_or_default();
+
+    OrderDetails {
+        id : order.id().value(),
+        side : order.side(),
+        company_id : order.company_id().value(),
+        company,
+        amount : order.amount(),
+        limit_price : order.limit_price(),
+    }
+}
+
+/// Places a limit order, the body is {"side": "buy" or "sell", "company": name, "amount": shares, "limit_price": price}
+fn post_order(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let body = match request.body_text() {
+        Ok(body) => body,
+        Err(error) => return Ok(bad_request(error)),
+    };
+
+    let order : OrderRequest = match serde_json::from_str(&body) {
+        Ok(order) => order,
+        Err(error) => return Ok(bad_request(format!("Invalid order: {}", error))),
+    };
+
+    if order.amount == 0 {
+        return Ok(bad_request(String::from("Amount must be at least 1")));
+    }
+    if !order.limit_price.is_finite() || order.limit_price <= 0.0 {
+        return Ok(bad_request(String::from("Limit price must be above 0")));
+    }
+
+    let placed = match server::place_order(state, user_id, order.side, &order.company, order.amount, order.limit_price) {
+        Ok(placed) => placed,
+        Err(error) => return Ok(trade_error(error)),
+    };
+
+    match state.company_manager.read() {
+        Ok(company_manager) => Ok(json("HTTP/1.1 201 CREATED", &order_details(&placed, &company_manager))),
+        Err(error) => Ok(internal_error(error.to_string())),
+    }
+}
+
+/// Lists the users open orders
+fn list_orders(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let orders : Vec<OrderDetails> = company_manager.orders_of_user(user_id)
+        .iter()
+        .map(|order| order_details(order, &company_manager))
+        .collect();
+
+    Ok(json_ok(&orders))
+}
+
+/// Cancels one of the users open orders
+fn delete_order(request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let order_id = match params.get("id").map(|id| id.parse::<usize>()) {
+        Some(Ok(order_id)) => order_id,
+        _ => return Ok(bad_request(String::from("Order ID must be a number"))),
+    };
+
+    let cancelled = match server::cancel_order(state, user_id, order_id) {
+        Ok(cancelled) => cancelled,
+        Err(error) => return Ok(trade_error(error)),
+    };
+
+    match state.company_manager.read() {
+        Ok(company_manager) => Ok(json_ok(&order_details(&cancelled, &company_manager))),
+        Err(error) => Ok(internal_error(error.to_string())),
+    }
+}
+
+/// Turns a protection into what is sent to the client
+fn protection_details(protection : &Protection, company_manager : &CompanyManager) -> ProtectionDetails {
+    let company = company_manager.get_company_by_id(protection.company_id())
+        .map(|company| company.name().clone())
+        .unwrap_or_default();
+
+    ProtectionDetails {
+        id : protection.id().value(),
+        kind : protection.kind(),
+        company_id : protection.company_id().value(),
+        company,
+        amount : protection.amount(),
+        trigger_price : protection.trigger_price(),
+        distance : protection.distance(),
+    }
+}
+
+/// Attaches a stop-loss, trailing stop or take-profit to the users shares
+/// The body is {"kind": "stop_loss", "trailing_stop" or "take_profit", "company": name, "amount": shares, "trigger_price" or "distance": price}
+fn post_protection(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let body = match request.body_text() {
+        Ok(body) => body,
+        Err(error) => return Ok(bad_request(error)),
+    };
+
+    let protection : ProtectionRequest = match serde_json::from_str(&body) {
+        Ok(protection) => protection,
+        Err(error) => return Ok(bad_request(format!("Invalid protection: {}", error))),
+    };
+
+    if protection.amount == 0 {
+        return Ok(bad_request(String::from("Amount must be at least 1")));
+    }
+
+    //Each kind takes the one field it needs
+    let level = match (protection.kind, protection.trigger_price, protection.distance) {
+        (ProtectionKind::TrailingStop, None, Some(distance)) => distance,
+        (ProtectionKind::TrailingStop, _, _) => return Ok(bad_request(String::from("A trailing stop needs a distance and no trigger_price"))),
+        (_, Some(trigger_price), None) => trigger_price,
+        (_, _, _) => return Ok(bad_request(String::from("A stop-loss or take-profit needs a trigger_price and no distance"))),
+    };
+
+    let placed = match server::protect_holding(state, user_id, protection.kind, &protection.company, protection.amount, level) {
+        Ok(placed) => placed,
+        Err(error) => return Ok(trade_error(error)),
+    };
+
+    match state.company_manager.read() {
+        Ok(company_manager) => Ok(json("HTTP/1.1 201 CREATED", &protection_details(&placed, &company_manager))),
+        Err(error) => Ok(internal_error(error.to_string())),
+    }
+}
+
+/// Lists the protections on the users shares
+fn list_protections(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    //Users are locked before companies, the same as trades
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let user = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Ok(unauthorized(error)),
+    };
+
+    let protections : Vec<ProtectionDetails> = user.wallet().protections()
+        .iter()
+        .map(|protection| protection_details(protection, &company_manager))
+        .collect();
+
+    Ok(json_ok(&protections))
+}
+
+/// Removes one of the users protections
+fn delete_protection(request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let protection_id = match params.get("id").map(|id| id.parse::<usize>()) {
+        Some(Ok(protection_id)) => protection_id,
+        _ => return Ok(bad_request(String::from("Protection ID must be a number"))),
+    };
+
+    let cancelled = match server::cancel_protection(state, user_id, protection_id) {
+        Ok(cancelled) => cancelled,
+        Err(error) => return Ok(trade_error(error)),
+    };
+
+    match state.company_manager.read() {
+        Ok(company_manager) => Ok(json_ok(&protection_details(&cancelled, &company_manager))),
+        Err(error) => Ok(internal_error(error.to_string())),
+    }
+}
+
+/// Lists the users latest trades, oldest first
+fn get_trade_history(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    let user_id = match authenticate(request, state) {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Ok(internal_error(error.to_string())),
+    };
+
+    let user = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Ok(unauthorized(error)),
+    };
+
+    let trades : Vec<PastTrade> = user.trade_history().trades()
+        .iter()
+        .map(|trade| PastTrade {
+            kind : trade.kind(),
+            side : trade.side(),
+            company_id : trade.company_id().value(),
+            company : trade.company_name().clone(),
+            amount : trade.amount(),
+            price : trade.price(),
+        })
+        .collect();
+
+    Ok(json_ok(&trades))
+}
+
+/// Adds the JSON API to a router, every path is versioned under /api/v1
+pub fn add_routes(router : Router<ServerState>) -> Router<ServerState> {
+    router
+        .add("GET", "/api/v1/companies", list_companies)
+        .add("GET", "/api/v1/companies/{name}", get_company)
+        .add("GET", "/api/v1/companies/{name}/history", get_price_history)
+        .add("GET", "/api/v1/companies/{name}/book", get_order_book)
+        .add("GET", "/api/v1/news", get_news)
+        .add("GET", "/api/v1/portfolio", get_portfolio)
+        .add("GET", "/api/v1/cash", get_cash)
+        .add("GET", "/api/v1/leaderboard", get_leaderboard)
+        .add("GET", "/api/v1/leaderboard/previous", get_previous_leaderboard)
+        .add("POST", "/api/v1/trades", post_trade)
+        .add("POST", "/api/v1/orders", post_order)
+        .add("GET", "/api/v1/orders", list_orders)
+        .add("DELETE", "/api/v1/orders/{id}", delete_order)
+        .add("POST", "/api/v1/protections", post_protection)
+        .add("GET", "/api/v1/protections", list_protections)
+        .add("DELETE", "/api/v1/protections/{id}", delete_protection)
+        .add("GET", "/api/v1/history", get_trade_history)
+}
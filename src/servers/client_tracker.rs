@@ -1,27 +1,35 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+use rand::RngCore;
+use rand::rngs::OsRng;
 
 use crate::id::ID;
 
+/// Sessions that aren't used for this long are logged out
+pub const SESSION_TIMEOUT : Duration = Duration::from_secs(2 * 60 * 60);
+/// Expired sessions are swept out at most this often, so logging in doesn't visit every session
+const SWEEP_INTERVAL : Duration = Duration::from_secs(60);
+/// The amount of random bytes in a session token
+const TOKEN_LENGTH : usize = 32;
 
-/// Stores a client by their IP and user ID
+/// A logged in session of a user, a user can have one on each device
 #[derive(Clone)]
 pub struct ConnectedClient {
-    client_id : ID,
+    token : String,
     user_id : ID,
-    user_name : String,
-    display_name : String,
+    last_used : Instant,
 }
 
 
 impl ConnectedClient {
-    /// Creates a new Connected client from the IP and user_id
-    fn new(user_id : ID, user_name : String, display_name : String) -> ConnectedClient {
-        ConnectedClient { client_id : ID::new(), user_id, user_name, display_name }
-    }
+    /// Creates a new session for the user with a random token
+    fn new(user_id : ID) -> ConnectedClient {
+        let mut bytes = [0u8; TOKEN_LENGTH];
+        OsRng.fill_bytes(&mut bytes);
+        let token : String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
 
-    /// Gets the client ID
-    pub fn client_id(&self) -> ID {
-        self.client_id
+        ConnectedClient { token, user_id, last_used : Instant::now() }
     }
 
     /// Gets the user ID
@@ -29,32 +37,31 @@ impl ConnectedClient {
         self.user_id
     }
 
-    /// Gets the username
-    pub fn user_name(&self) -> &String {
-        &self.user_name
-    }
-
-    /// Checks if the IDs are identical
-    fn equal_client_id(&self, other_id : ID) -> bool {
-        self.client_id.equals(other_id)
-    }
-
-    /// Checks if the two user IDs are identical
-    fn equal_user_id(&self, other_user_id : ID) -> bool {
-        self.user_id.equals(other_user_id)
+    /// Checks if the session hasn't been used for too long
+    fn expired(&self, timeout : Duration) -> bool {
+        self.last_used.elapsed() >= timeout
     }
 }
 
-/// Tracks the IPs of the user that are connected
+/// Tracks the sessions of the users that are logged in
 #[derive(Clone)]
 pub struct ClientTracker {
-    clients : Vec<ConnectedClient>,
+    /// The sessions, by their token
+    clients : HashMap<String, ConnectedClient>,
+    timeout : Duration,
+    /// When expired sessions were last swept out
+    last_swept : Instant,
 }
 
 impl ClientTracker {
-    /// Creates a new empty Socket tracker
+    /// Creates a new empty client tracker
     pub fn new() -> ClientTracker {
-        ClientTracker { clients: Vec::new() }
+        Self::with_timeout(SESSION_TIMEOUT)
+    }
+
+    /// Creates a client tracker that logs out sessions after they go unused for the timeout
+    pub fn with_timeout(timeout : Duration) -> ClientTracker {
+        ClientTracker { clients: HashMap::new(), timeout, last_swept : Instant::now() }
     }
 
     /// Clears the client tracker
@@ -62,111 +69,59 @@ impl ClientTracker {
         self.clients.clear()
     }
 
-    /// Adds a client to the list
-    /// Returns the new clients ID
-    pub fn add_client(&mut self, user_id : ID, user_name : String, display_name : String) -> Result<ID, String> {
-        //Ensures the client has not already been added
-        if self.contains_user_id(user_id) { return Err(String::from("Already has a client with that user ID Address")); }
-        if self.contains_user_name(&user_name) { return Err(format!("User name already in use: {}", user_name)); }
-        if self.contains_display_name(&display_name) { return Err(format!("Display name already in use: {}", display_name)); }
-
-        //Makes the new client
-        let new_client = ConnectedClient::new(user_id, user_name, display_name);
-        //Stores the ID to return
-        let stored_id = new_client.client_id;
-        self.clients.push(new_client);
-
-        Ok(stored_id)
-    }
-
-    /// Gets the client by their ID
-    pub fn get_client_by_client_id(&self, client_id : ID) -> Result<&ConnectedClient, String> {
-        // Filters for the remaining client
-        let filtered : Vec<&ConnectedClient> = self.clients.iter()
-            .filter(|client| client.client_id().equals(client_id))
-            .collect();
-
-        match filtered.len() {
-            0 => Err(format!("No client with ID {} exists.", client_id)),
-            _ => Ok(filtered[0]),
+    /// Starts a new session for the user
+    /// Returns the sessions token
+    pub fn add_client(&mut self, user_id : ID) -> String {
+        //Old sessions are dropped now and then so they don't pile up
+        if self.last_swept.elapsed() >= SWEEP_INTERVAL {
+            self.remove_expired();
         }
-    }
 
-    /// Gets a users ID from the clients ID
-    pub fn get_user_id_by_client_id(&self, client_id : ID) -> Result<ID, String> {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.equal_client_id(client_id))
-            .collect();
+        let new_client = ConnectedClient::new(user_id);
+        let token = new_client.token.clone();
+        self.clients.insert(token.clone(), new_client);
 
-        // The length should be 1
-        if filtered.len() != 1 { return Err(format!("No client with ID {} found", client_id)); }
-
-        // Gets the users ID
-        Ok(filtered[0].user_id)
+        token
     }
 
-    /// Gets a clients ID from the users ID
-    pub fn get_client_id_by_user_id(&self, user_id : ID) -> Result<ID, String> {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.equal_user_id(user_id))
-            .collect();
-
-        // The length should be 1
-        if filtered.len() != 1 { return Err(format!("No client with ID {} found", user_id)); }
-
-        // Gets the users ID
-        Ok(filtered[0].client_id)
-    }
+    /// Gets the user of a session, keeping the session alive
+    pub fn authenticate(&mut self, token : &str) -> Result<ID, String> {
+        let timeout = self.timeout;
+        let expired = match self.clients.get(token) {
+            Some(client) => client.expired(timeout),
+            None => return Err(String::from("Invalid session")),
+        };
 
-    /// Checks if the client is already connected
-    pub fn contains_client_id(&self, client_id : ID) -> bool {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.equal_client_id(client_id))
-            .collect();
+        if expired {
+            self.clients.remove(token);
+            return Err(String::from("Invalid session, it has expired"));
+        }
 
-        // The length should be 1
-        filtered.len() == 1
+        let client = self.clients.get_mut(token).unwrap();
+        client.last_used = Instant::now();
+        Ok(client.user_id())
     }
 
-    /// Checks if the user is already contained
-    pub fn contains_user_id(&self, user_id : ID) -> bool {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.equal_user_id(user_id))
-            .collect();
-
-        // The length should not be 0
-        filtered.len() != 0
+    /// Checks if a session is still logged in, without keeping it alive
+    pub fn is_active(&self, token : &str) -> bool {
+        match self.clients.get(token) {
+            Some(client) => !client.expired(self.timeout),
+            None => false,
+        }
     }
 
-    /// Checks if the client tracker contains the user name
-    pub fn contains_user_name(&self, user_name : &String) -> bool {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.user_name.eq(user_name))
-            .collect();
-
-        // The length should not be 0
-        filtered.len() != 0
+    /// Logs out a session, the token can't be used again
+    pub fn remove_client(&mut self, token : &str) -> Result<(), String> {
+        match self.clients.remove(token) {
+            Some(_client) => Ok(()),
+            None => Err(String::from("Invalid session")),
+        }
     }
 
-    /// Checks if the display name is already in the tracker
-    pub fn contains_display_name(&self, display_name : &String) -> bool {
-        // Filters all clients for matching addresses
-        let filtered : Vec<&ConnectedClient> = self.clients
-            .iter()
-            .filter(|client| client.display_name.eq(display_name))
-            .collect();
-
-        // The length should be 1
-        filtered.len() == 1
+    /// Removes every session that has expired
+    pub fn remove_expired(&mut self) {
+        let timeout = self.timeout;
+        self.clients.retain(|_token, client| !client.expired(timeout));
+        self.last_swept = Instant::now();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,125 @@
+use crate::companies::company::DEFAULT_LIQUIDITY;
+
+/// Sets the largest request body the server accepts
+const MAX_BODY_VARIABLE : &str = "FAKE_STOCKS_MAX_BODY_SIZE";
+/// Sets how many requests are handled at once
+const WORKERS_VARIABLE : &str = "FAKE_STOCKS_WORKERS";
+/// Sets how many requests can wait for a worker
+const QUEUE_VARIABLE : &str = "FAKE_STOCKS_QUEUE_SIZE";
+/// Sets how many clients can stream prices, and how many can stream events, at once
+const STREAMS_VARIABLE : &str = "FAKE_STOCKS_MAX_STREAMS";
+/// Turns on exchange mode, where users trade with each other and a market maker
+const EXCHANGE_MODE_VARIABLE : &str = "FAKE_STOCKS_EXCHANGE_MODE";
+/// Sets how many shares it takes to move a new companies price by its whole value, 0 turns price impact off
+const LIQUIDITY_VARIABLE : &str = "FAKE_STOCKS_LIQUIDITY";
+/// Sets the seed the market is drawn from, so a game can be played again
+const SEED_VARIABLE : &str = "FAKE_STOCKS_SEED";
+/// Sets the file the companies of the game are read from
+const CATALOGUE_VARIABLE : &str = "FAKE_STOCKS_CATALOGUE";
+
+/// The largest request body accepted when the variable isn't set
+const DEFAULT_MAX_BODY_SIZE : usize = 64 * 1024;
+const DEFAULT_WORKERS : usize = 8;
+const DEFAULT_QUEUE_SIZE : usize = 128;
+const DEFAULT_MAX_STREAMS : usize = 256;
+const DEFAULT_CATALOGUE_PATH : &str = "config/companies.json";
+
+/// Settings for the web server, read from environment variables
+pub struct ServerConfig {
+    /// Requests with a bigger body get Error 413
+    pub max_body_size : usize,
+    /// The amount of worker threads handling requests
+    pub workers : usize,
+    /// Requests that arrive when this many are already waiting get Error 503
+    pub queue_size : usize,
+    /// Price stream or event stream clients past this many get Error 503
+    pub max_streams : usize,
+    /// Orders are matched between users, and the random walk only moves the market maker
+    pub exchange_mode : bool,
+    /// The liquidity companies get when they are made or reset
+    pub liquidity : usize,
+    /// The seed the market is drawn from, None keeps the saved one or picks a random one
+    pub seed : Option<u64>,
+    /// The company catalogue, read when a game starts and at every season reset
+    pub catalogue_path : String,
+}
+
+impl ServerConfig {
+    /// Reads the settings, using the defaults for any that aren't set
+    pub fn from_env() -> Result<ServerConfig, String> {
+        let max_body_size = match read_setting(MAX_BODY_VARIABLE, DEFAULT_MAX_BODY_SIZE) {
+            Ok(max_body_size) => max_body_size,
+            Err(error) => return Err(error),
+        };
+
+        let workers = match read_setting(WORKERS_VARIABLE, DEFAULT_WORKERS) {
+            Ok(0) => return Err(format!("{} must be at least 1", WORKERS_VARIABLE)),
+            Ok(workers) => workers,
+            Err(error) => return Err(error),
+        };
+
+        let queue_size = match read_setting(QUEUE_VARIABLE, DEFAULT_QUEUE_SIZE) {
+            Ok(queue_size) => queue_size,
+            Err(error) => return Err(error),
+        };
+
+        let max_streams = match read_setting(STREAMS_VARIABLE, DEFAULT_MAX_STREAMS) {
+            Ok(max_streams) => max_streams,
+            Err(error) => return Err(error),
+        };
+
+        let exchange_mode = match read_flag(EXCHANGE_MODE_VARIABLE) {
+            Ok(exchange_mode) => exchange_mode,
+            Err(error) => return Err(error),
+        };
+
+        let liquidity = match read_setting(LIQUIDITY_VARIABLE, DEFAULT_LIQUIDITY) {
+            Ok(liquidity) => liquidity,
+            Err(error) => return Err(error),
+        };
+
+        let seed = match read_seed(SEED_VARIABLE) {
+            Ok(seed) => seed,
+            Err(error) => return Err(error),
+        };
+
+        //The catalogue is checked when it is read
+        let catalogue_path = std::env::var(CATALOGUE_VARIABLE).unwrap_or_else(|_error| String::from(DEFAULT_CATALOGUE_PATH));
+
+        Ok(ServerConfig { max_body_size, workers, queue_size, max_streams, exchange_mode, liquidity, seed, catalogue_path })
+    }
+}
+
+/// Reads a number from an environment variable, or the default if it isn't set
+fn read_setting(variable : &str, default : usize) -> Result<usize, String> {
+    match std::env::var(variable) {
+        Ok(value) => match value.trim().parse::<usize>() {
+            Ok(value) => Ok(value),
+            Err(_error) => Err(format!("{} should be a number, found '{}'", variable, value)),
+        },
+        Err(_error) => Ok(default),
+    }
+}
+
+/// Reads an on or off setting from an environment variable, off if it isn't set
+fn read_flag(variable : &str) -> Result<bool, String> {
+    match std::env::var(variable) {
+        Ok(value) => match value.trim() {
+            "1" | "true" | "on" => Ok(true),
+            "0" | "false" | "off" => Ok(false),
+            _ => Err(format!("{} should be on or off, found '{}'", variable, value)),
+        },
+        Err(_error) => Ok(false),
+    }
+}
+
+/// Reads a seed from an environment variable, None if it isn't set
+fn read_seed(variable : &str) -> Result<Option<u64>, String> {
+    match std::env::var(variable) {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(value) => Ok(Some(value)),
+            Err(_error) => Err(format!("{} should be a number, found '{}'", variable, value)),
+        },
+        Err(_error) => Ok(None),
+    }
+}
@@ -0,0 +1,66 @@
+use crate::servers::client_tracker::SESSION_TIMEOUT;
+
+/// The name of the cookie holding the session token
+pub const SESSION_COOKIE : &str = "ID";
+
+/// Parses a Cookie header ("name=value; name2=value2") into its cookies
+/// Pairs that are malformed are skipped, like browsers do
+pub fn parse_cookies(header : &str) -> Vec<(String, String)> {
+    let mut cookies : Vec<(String, String)> = Vec::new();
+
+    for pair in header.split(';') {
+        let (name, value) = match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+
+        //Names can't be empty or hold separators
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            continue;
+        }
+
+        //Values may be wrapped in quotes
+        let value = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+            Some(unquoted) => unquoted,
+            None => value,
+        };
+
+        if !value.chars().all(is_value_char) {
+            continue;
+        }
+
+        cookies.push((name.to_string(), value.to_string()));
+    }
+
+    cookies
+}
+
+/// Gets a cookie from a Cookie header, the first one wins if it is sent twice
+pub fn get_cookie(header : &str, name : &str) -> Option<String> {
+    parse_cookies(header)
+        .into_iter()
+        .find(|(cookie_name, _value)| cookie_name == name)
+        .map(|(_name, value)| value)
+}
+
+/// Makes the Set-Cookie value that hands a session to the browser
+/// Scripts can't read it, and it lasts as long as the session does
+/// It is sent again on authenticated requests, since using the session keeps it from expiring
+pub fn session_cookie(token : &str) -> String {
+    format!("{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax", SESSION_COOKIE, token, SESSION_TIMEOUT.as_secs())
+}
+
+/// Makes the Set-Cookie value that removes the session from the browser
+pub fn expired_session_cookie() -> String {
+    format!("{}=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax", SESSION_COOKIE)
+}
+
+/// Checks if a character can be in a cookie name (RFC 6265 token)
+fn is_token_char(c : char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+/// Checks if a character can be in a cookie value (RFC 6265 cookie-octet)
+fn is_value_char(c : char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
@@ -0,0 +1,239 @@
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::id::ID;
+use crate::servers::client_tracker::ClientTracker;
+use crate::servers::response::Response;
+use crate::companies::company_manager::CancelledOrder;
+use crate::companies::order_book::TradeSide;
+use crate::users::ranking::RankChange;
+use crate::users::trade_history::Fill;
+use crate::users::user_manager::UserManager;
+
+/// The path clients open an event stream on
+pub const EVENTS_PATH : &str = "/events";
+
+/// A client that can't take an event this quickly is dropped, so it can't hold up the game
+const WRITE_TIMEOUT : Duration = Duration::from_secs(1);
+
+/// Something that happened to a user, sent to each of their open sessions
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum UserEvent {
+    /// A buy or sell went through
+    Trade {
+        side : TradeSide,
+        company : String,
+        amount : usize,
+        price : f32,
+        shares_held : usize,
+    },
+    /// An open order crossed but couldn't trade, so it was cancelled and gave back what it held
+    OrderCancelled {
+        order_id : usize,
+        side : TradeSide,
+        company : String,
+        amount : usize,
+        reason : String,
+    },
+    /// The users money changed
+    Cash {
+        cash : f32,
+    },
+    /// The users place on the leaderboard changed, overtaken when they fell
+    Rank {
+        previous_rank : Option<usize>,
+        rank : usize,
+        value : f32,
+        overtaken : bool,
+    },
+    /// The season ended, every user starts again and has to log back in
+    SeasonReset {},
+}
+
+impl UserEvent {
+    /// Makes the event for a users leaderboard change
+    pub fn rank(change : &RankChange) -> UserEvent {
+        UserEvent::Rank {
+            previous_rank : change.previous_rank,
+            rank : change.rank,
+            value : change.value,
+            overtaken : change.previous_rank.is_some_and(|previous_rank| change.rank > previous_rank),
+        }
+    }
+
+    /// Gets the name the client listens for
+    pub fn name(&self) -> &'static str {
+        match self {
+            UserEvent::Trade { .. } => "trade",
+            UserEvent::OrderCancelled { .. } => "order_cancelled",
+            UserEvent::Cash { .. } => "cash",
+            UserEvent::Rank { .. } => "rank",
+            UserEvent::SeasonReset {} => "season_reset",
+        }
+    }
+
+    /// Writes the event in the text/event-stream format
+    pub fn to_event_text(&self) -> Result<String, String> {
+        match serde_json::to_string(self) {
+            Ok(data) => Ok(format!("event: {}\ndata: {}\n\n", self.name(), data)),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+}
+
+/// An open event stream of one session
+struct Listener {
+    token : String,
+    user_id : ID,
+    stream : TcpStream,
+}
+
+impl Listener {
+    /// Sends text down the stream, false if the client is gone
+    fn send(&mut self, text : &str) -> bool {
+        self.stream.write_all(text.as_bytes()).and_then(|_| self.stream.flush()).is_ok()
+    }
+}
+
+/// Sends each session the events of its user
+pub struct EventFeed {
+    listeners : Vec<Listener>,
+    max_listeners : usize,
+}
+
+impl EventFeed {
+    /// Makes a feed that takes up to max_listeners open streams
+    pub fn new(max_listeners : usize) -> EventFeed {
+        EventFeed { listeners : Vec::new(), max_listeners }
+    }
+
+    /// Checks if another stream can be opened
+    pub fn is_full(&self) -> bool {
+        self.listeners.len() >= self.max_listeners
+    }
+
+    /// Sends events to every open session of a user
+    pub fn send(&mut self, user_id : ID, events : &[UserEvent]) -> Result<(), String> {
+        let mut text = String::new();
+        for event in events {
+            match event.to_event_text() {
+                Ok(event_text) => text.push_str(&event_text),
+                Err(error) => return Err(error),
+            }
+        }
+
+        self.listeners.retain_mut(|listener| !listener.user_id.equals(user_id) || listener.send(&text));
+        Ok(())
+    }
+
+    /// Sends an event to every open session
+    pub fn send_all(&mut self, event : &UserEvent) -> Result<(), String> {
+        let text = match event.to_event_text() {
+            Ok(text) => text,
+            Err(error) => return Err(error),
+        };
+
+        self.listeners.retain_mut(|listener| listener.send(&text));
+        Ok(())
+    }
+
+    /// Sends a comment to every stream, so proxies keep them open and closed clients are found
+    pub fn keep_alive(&mut self) {
+        self.listeners.retain_mut(|listener| listener.send(": keep-alive\n\n"));
+    }
+
+    /// Closes the streams of sessions that logged out or expired
+    pub fn close_inactive(&mut self, client_tracker : &ClientTracker) {
+        self.listeners.retain(|listener| client_tracker.is_active(&listener.token));
+    }
+
+    /// Closes the stream of one session
+    pub fn close_session(&mut self, token : &str) {
+        self.listeners.retain(|listener| listener.token != token);
+    }
+
+    /// Closes every stream
+    pub fn close_all(&mut self) {
+        self.listeners.clear();
+    }
+}
+
+/// Makes the trade and cash events of each fill, for the user that made it
+pub fn events_of_fills(fills : &[Fill], user_manager : &UserManager) -> Result<Vec<(ID, Vec<UserEvent>)>, String> {
+    let mut events : Vec<(ID, Vec<UserEvent>)> = Vec::new();
+
+    for fill in fills {
+        let user = match user_manager.get_user_by_id(fill.user_id) {
+            Ok(user) => user,
+            Err(error) => return Err(error),
+        };
+
+        let shares_held = user.wallet().holders()
+            .iter()
+            .find(|holder| holder.company_id().equals(fill.trade.company_id()))
+            .map(|holder| holder.stock_amount())
+            .unwrap_or(0);
+
+        events.push((user.id(), vec![
+            UserEvent::Trade { side : fill.trade.side(), company : fill.trade.company_name().clone(), amount : fill.trade.amount(), price : fill.trade.price(), shares_held },
+            UserEvent::Cash { cash : user.money() },
+        ]));
+    }
+
+    Ok(events)
+}
+
+/// Makes the events of each order that was cancelled because it couldn't trade, for the user that placed it
+pub fn events_of_cancelled(cancelled : &[CancelledOrder], user_manager : &UserManager) -> Result<Vec<(ID, Vec<UserEvent>)>, String> {
+    let mut events : Vec<(ID, Vec<UserEvent>)> = Vec::new();
+
+    for cancelled_order in cancelled {
+        let order = &cancelled_order.order;
+        let user = match user_manager.get_user_by_id(order.user_id()) {
+            Ok(user) => user,
+            Err(error) => return Err(error),
+        };
+
+        events.push((user.id(), vec![
+            UserEvent::OrderCancelled { order_id : order.id().value(), side : order.side(), company : cancelled_order.company_name.clone(), amount : order.amount(), reason : cancelled_order.reason.clone() },
+            UserEvent::Cash { cash : user.money() },
+        ]));
+    }
+
+    Ok(events)
+}
+
+/// Starts an event stream for a logged in session
+/// The worker is free again once this returns, the feed keeps the connection
+pub fn accept(mut stream : TcpStream, token : String, user_id : ID, event_feed : &RwLock<EventFeed>) -> Result<(), String> {
+    let mut event_feed = match event_feed.write() {
+        Ok(event_feed) => event_feed,
+        Err(error) => return Err(error.to_string()),
+    };
+
+    if event_feed.is_full() {
+        return Response::with_status("HTTP/1.1 503 SERVICE UNAVAILABLE", String::from("Too many clients are streaming events"))
+            .with_header("Retry-After", "30")
+            .write_to(&mut stream);
+    }
+
+    //The stream has no length, it lasts until either side closes it
+    let headers = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+
+    let ready = stream.set_read_timeout(None)
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .and_then(|_| stream.write_all(headers.as_bytes()))
+        .and_then(|_| stream.flush());
+    match ready {
+        Ok(_) => {
+            event_feed.listeners.push(Listener { token, user_id, stream });
+            Ok(())
+        },
+        Err(error) => Err(error.to_string()),
+    }
+}
@@ -1,3 +1,11 @@
-
 pub mod server;
-pub mod client_tracker;
\ No newline at end of file
+pub mod client_tracker;
+pub mod cookie;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod api;
+pub mod websocket;
+pub mod events;
+pub mod config;
+pub mod worker_pool;
@@ -0,0 +1,181 @@
+use std::io::prelude::*;
+
+use httparse;
+
+/// The request line and headers can't be bigger than this
+const MAX_HEADER_SIZE : usize = 16 * 1024;
+/// The most headers a request can have
+const MAX_HEADERS : usize = 64;
+/// How much is read from the connection at a time
+const READ_SIZE : usize = 1024;
+
+/// Why a request could not be read
+#[derive(Debug)]
+pub enum RequestError {
+    /// The body is bigger than the maximum body size
+    BodyTooLarge(usize),
+    /// The request line and headers are bigger than the maximum header size
+    HeadersTooLarge,
+    /// The request isn't valid HTTP
+    Invalid(String),
+    /// The connection failed or closed part way
+    Connection(String),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RequestError::BodyTooLarge(size) => write!(f, "Request body of {} bytes is too large", size),
+            RequestError::HeadersTooLarge => write!(f, "Request headers are larger than {} bytes", MAX_HEADER_SIZE),
+            RequestError::Invalid(error) => write!(f, "Invalid request: {}", error),
+            RequestError::Connection(error) => write!(f, "Connection error: {}", error),
+        }
+    }
+}
+
+/// A HTTP request read from a client
+pub struct Request {
+    method : String,
+    path : String,
+    headers : Vec<(String, String)>,
+    body : Vec<u8>,
+}
+
+impl Request {
+    /// Gets the method ("GET", "POST", ...)
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Gets the path without the query string
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Gets every value of a header, header names aren't case sensitive
+    pub fn header_values(&self, name : &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(header_name, _value)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_name, value)| value.as_str())
+            .collect()
+    }
+
+    /// Gets the body as text
+    pub fn body_text(&self) -> Result<String, String> {
+        match std::str::from_utf8(&self.body) {
+            Ok(text) => Ok(text.to_string()),
+            Err(error) => Err(format!("Request body is not text: {}", error)),
+        }
+    }
+}
+
+/// Reads a request from the client
+/// The headers are read until they are complete, then the body is read up to its Content-Length
+pub fn read_request<R : Read>(stream : &mut R, max_body_size : usize) -> Result<Request, RequestError> {
+    let mut buffer : Vec<u8> = Vec::new();
+
+    //Reads until the headers are complete
+    let (method, path, headers, header_size) = loop {
+        let mut header_storage = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut header_storage);
+
+        match request.parse(&buffer) {
+            Ok(httparse::Status::Complete(header_size)) => {
+                let method = request.method.unwrap_or_default().to_string();
+                let target = request.path.unwrap_or_default();
+                let path = target.split('?').next().unwrap_or_default().to_string();
+
+                let mut headers : Vec<(String, String)> = Vec::new();
+                for header in request.headers.iter() {
+                    match std::str::from_utf8(header.value) {
+                        Ok(value) => headers.push((header.name.to_string(), value.to_string())),
+                        Err(_error) => return Err(RequestError::Invalid(format!("Header {} is not text", header.name))),
+                    }
+                }
+
+                break (method, path, headers, header_size);
+            },
+            Ok(httparse::Status::Partial) => (),
+            Err(error) => return Err(RequestError::Invalid(error.to_string())),
+        }
+
+        if buffer.len() >= MAX_HEADER_SIZE {
+            return Err(RequestError::HeadersTooLarge);
+        }
+
+        match read_more(stream, &mut buffer) {
+            Ok(0) => return Err(RequestError::Connection(String::from("Closed before the headers were sent"))),
+            Ok(_) => (),
+            Err(error) => return Err(error),
+        }
+    };
+
+    //Works out how big the body is before reading any of it
+    let content_length = match content_length(&headers) {
+        Ok(content_length) => content_length,
+        Err(error) => return Err(error),
+    };
+
+    if content_length > max_body_size {
+        return Err(RequestError::BodyTooLarge(content_length));
+    }
+
+    //Reads the rest of the body
+    let mut body = buffer.split_off(header_size);
+    while body.len() < content_length {
+        match read_more(stream, &mut body) {
+            Ok(0) => return Err(RequestError::Connection(format!("Closed after {} of {} body bytes", body.len(), content_length))),
+            Ok(_) => (),
+            Err(error) => return Err(error),
+        }
+    }
+    body.truncate(content_length);
+
+    Ok(Request { method, path, headers, body })
+}
+
+/// Gets the Content-Length of a request, requests without one have no body
+fn content_length(headers : &[(String, String)]) -> Result<usize, RequestError> {
+    //Chunked bodies aren't supported
+    if headers.iter().any(|(name, _value)| name.eq_ignore_ascii_case("Transfer-Encoding")) {
+        return Err(RequestError::Invalid(String::from("Transfer-Encoding is not supported, send a Content-Length")));
+    }
+
+    let mut content_length : Option<usize> = None;
+    for (name, value) in headers {
+        if !name.eq_ignore_ascii_case("Content-Length") {
+            continue;
+        }
+
+        let length = match value.trim().parse::<usize>() {
+            Ok(length) => length,
+            Err(_error) => return Err(RequestError::Invalid(format!("Content-Length '{}' is not a number", value))),
+        };
+
+        //Different lengths could be read differently by a proxy
+        match content_length {
+            Some(previous) if previous != length => return Err(RequestError::Invalid(String::from("Content-Length was sent with different values"))),
+            _ => content_length = Some(length),
+        }
+    }
+
+    Ok(content_length.unwrap_or(0))
+}
+
+/// Reads the next part of the request onto the end of the buffer
+/// Returns the amount of bytes read, 0 when the connection closed
+fn read_more<R : Read>(stream : &mut R, buffer : &mut Vec<u8>) -> Result<usize, RequestError> {
+    let mut chunk = [0u8; READ_SIZE];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(size) => {
+                buffer.extend_from_slice(&chunk[..size]);
+                return Ok(size);
+            },
+            //A signal stopped the read, it can be tried again
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(RequestError::Connection(error.to_string())),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::io::prelude::*;
+
+use crate::data::data_saving::read_from_file;
+
+/// A response to send back to the client
+pub struct Response {
+    status_line : &'static str,
+    headers : Vec<(String, String)>,
+    body : String,
+}
+
+impl Response {
+    /// Makes a successful response
+    pub fn ok(body : String) -> Response {
+        Self::with_status("HTTP/1.1 200 OK", body)
+    }
+
+    /// Makes a response with any status
+    pub fn with_status(status_line : &'static str, body : String) -> Response {
+        Response { status_line, headers : Vec::new(), body }
+    }
+
+    /// Tells the client the page doesn't exist
+    pub fn not_found() -> Response {
+        let body = read_from_file("html/404.html").unwrap_or_else(|_error| String::from("Not found"));
+        Self::with_status("HTTP/1.1 404 NOT FOUND", body)
+    }
+
+    /// Tells the client the page exists, but not for the method it used
+    pub fn method_not_allowed(allowed : &[&str]) -> Response {
+        Self::with_status("HTTP/1.1 405 METHOD NOT ALLOWED", String::from("Method not allowed"))
+            .with_header("Allow", &allowed.join(", "))
+    }
+
+    /// Sends the client to another page
+    pub fn redirect(location : &str) -> Response {
+        Self::with_status("HTTP/1.1 303 SEE OTHER", String::new())
+            .with_header("Location", location)
+    }
+
+    /// Adds a header to the response
+    pub fn with_header(mut self, name : &str, value : &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Checks if the response has a header, whatever its case
+    pub fn has_header(&self, name : &str) -> bool {
+        self.headers.iter().any(|(header_name, _value)| header_name.eq_ignore_ascii_case(name))
+    }
+
+    /// Writes the response to the client
+    pub fn write_to<W : Write>(&self, stream : &mut W) -> Result<(), String> {
+        let mut response_text = format!("{}\r\nContent-Length: {}\r\n", self.status_line, self.body.len());
+        for (name, value) in &self.headers {
+            response_text.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response_text.push_str("\r\n");
+        response_text.push_str(&self.body);
+
+        match stream.write_all(response_text.as_bytes()).and_then(|_| stream.flush()) {
+            Err(error) => Err(error.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
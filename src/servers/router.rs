@@ -0,0 +1,141 @@
+use crate::servers::request::Request;
+use crate::servers::response::Response;
+
+/// Handles a request that matched a route
+/// S is the state shared by every handler
+pub type Handler<S> = fn(&Request, &Params, &S) -> Result<Response, String>;
+
+/// The values taken from the path, "/companies/{name}" matching "/companies/Apple" has name = "Apple"
+pub struct Params {
+    values : Vec<(String, String)>,
+}
+
+impl Params {
+    /// Gets a path parameter by its name
+    pub fn get(&self, name : &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(param_name, _value)| param_name == name)
+            .map(|(_name, value)| value.as_str())
+    }
+}
+
+/// A part of a route's path
+enum Segment {
+    /// Has to match exactly
+    Fixed(String),
+    /// Matches any one segment, saved as a parameter
+    Param(String),
+}
+
+struct Route<S> {
+    method : &'static str,
+    segments : Vec<Segment>,
+    handler : Handler<S>,
+}
+
+/// Picks the handler for a request by its method and path
+pub struct Router<S> {
+    routes : Vec<Route<S>>,
+}
+
+impl<S> Router<S> {
+    /// Makes a router with no routes
+    pub fn new() -> Router<S> {
+        Router { routes : Vec::new() }
+    }
+
+    /// Adds a route, segments of the path in braces are parameters ("/companies/{name}")
+    pub fn add(mut self, method : &'static str, path : &str, handler : Handler<S>) -> Router<S> {
+        let segments = split_path(path)
+            .into_iter()
+            .map(|segment| match segment.strip_prefix('{').and_then(|segment| segment.strip_suffix('}')) {
+                Some(name) => Segment::Param(name.to_string()),
+                None => Segment::Fixed(segment.to_string()),
+            })
+            .collect();
+
+        self.routes.push(Route { method, segments, handler });
+        self
+    }
+
+    /// Sends the request to its handler
+    /// Paths that don't exist get Error 404, paths that exist with another method get Error 405
+    pub fn route(&self, request : &Request, state : &S) -> Result<Response, String> {
+        let path = split_path(request.path());
+        let mut allowed : Vec<&str> = Vec::new();
+
+        for route in &self.routes {
+            let params = match match_path(&route.segments, &path) {
+                Some(params) => params,
+                None => continue,
+            };
+
+            if route.method == request.method() {
+                return (route.handler)(request, &params, state);
+            }
+
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return Ok(Response::not_found());
+        }
+        Ok(Response::method_not_allowed(&allowed))
+    }
+}
+
+/// Splits a path into its segments, "/" has none
+fn split_path(path : &str) -> Vec<&str> {
+    path.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+/// Checks if a path matches a route, returns the parameters if it does
+fn match_path(segments : &[Segment], path : &[&str]) -> Option<Params> {
+    if segments.len() != path.len() {
+        return None;
+    }
+
+    let mut values : Vec<(String, String)> = Vec::new();
+    for (segment, part) in segments.iter().zip(path) {
+        match segment {
+            Segment::Fixed(fixed) if fixed == part => (),
+            Segment::Fixed(_fixed) => return None,
+            Segment::Param(name) => match percent_decode(part) {
+                Some(value) => values.push((name.clone(), value)),
+                None => return None,
+            },
+        }
+    }
+
+    Some(Params { values })
+}
+
+/// Decodes "%20" style escapes in a path segment
+/// None if an escape is broken or the result isn't text
+fn percent_decode(text : &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded : Vec<u8> = Vec::new();
+
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' {
+            let hex = match bytes.get(pos + 1..pos + 3) {
+                Some(hex) => String::from_utf8_lossy(hex),
+                None => return None,
+            };
+            match u8::from_str_radix(&hex, 16) {
+                Ok(byte) if hex.chars().all(|digit| digit.is_ascii_hexdigit()) => decoded.push(byte),
+                _ => return None,
+            }
+            pos += 3;
+        } else {
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
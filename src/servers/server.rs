@@ -3,145 +3,410 @@
 use std::net::TcpStream;
 use std::io::prelude::*;
 use std::sync::{Arc, RwLock};
-
-use httparse;
+use std::time::Duration;
 
 use crate::users::ranking::{Ranker, RankerHistory};
 use crate::users::user_manager::UserManager;
+use crate::users::trade_history::{Fill, TradeKind};
 use crate::companies::company_manager::CompanyManager;
+use crate::companies::order_book::{Order, TradeSide};
+use crate::companies::protection::{Protection, ProtectionKind};
 use crate::data::data_saving::{SaveData, read_from_file};
+use crate::data::journal::{Journal, JournalEntry};
+use crate::servers::api;
+use crate::servers::cookie;
+use crate::servers::events::{self, EventFeed, UserEvent};
+use crate::servers::request::{self, Request, RequestError};
+use crate::servers::response::Response;
+use crate::servers::router::{Router, Params};
+use crate::servers::websocket::{self, PriceStream};
 use crate::{Password, ClientTracker, User, ID};
 
-/// Gets the sent text from a request
-/// Returns a String with the bodies text!
-fn get_text_from_request(buffer : &[u8; 1024]) -> Result<String, String> {
-    //Makes some headers
-    let mut headers = [httparse::EMPTY_HEADER; 32];
-    //Places the headers into the request
-    let mut request = httparse::Request::new(&mut headers);
-
-    //Parses for the body's position
-    let body_pos;
-    match request.parse(buffer) {
-        //If the size is found
-        Ok(size) => {
-            //Ensures the position is valid
-            body_pos = match size {
-                httparse::Status::Complete(body_pos) => body_pos,
-                httparse::Status::Partial => return Err(String::from("Buffer could not fit entire HTTP request")),
-            }
-        }, 
-        Err(error) => return Err(error.to_string()),
-    };
+/// How long to wait for a client to send its request
+const READ_TIMEOUT : Duration = Duration::from_secs(10);
+
+/// Everything the request handlers share
+#[derive(Clone)]
+pub struct ServerState {
+    pub client_tracker : Arc<RwLock<ClientTracker>>,
+    pub company_manager : Arc<RwLock<CompanyManager>>,
+    pub user_manager : Arc<RwLock<UserManager>>,
+    pub ranker : Arc<RwLock<Ranker>>,
+    pub ranker_history : Arc<RwLock<RankerHistory>>,
+    pub journal : Arc<RwLock<Journal>>,
+    pub price_stream : Arc<RwLock<PriceStream>>,
+    pub event_feed : Arc<RwLock<EventFeed>>,
+}
 
-    //Gets the text from the rest (Unwraps are fine since we would have errored out by here)
-    let str_buffer = std::str::from_utf8(buffer).unwrap();
-    let str_slice = &str_buffer[body_pos..str_buffer.find('\0').unwrap()];
+/// Gets the cookies from a request
+/// Returns the text of every Cookie header joined together
+fn get_cookie_from_request(request : &Request) -> Result<String, String> {
+    //Some clients split the cookies over many headers
+    let cookie_values = request.header_values("Cookie");
+    if cookie_values.is_empty() { return Err(String::from("Invalid session, no cookies were sent")); }
 
-    Ok(String::from(str_slice))
+    Ok(cookie_values.join("; "))
 }
 
-/// Gets the cookie from a request
-/// Returns a String with all the text for the cookie
-fn get_cookie_from_request(buffer : &[u8; 1024]) -> Result<String, String> {
-    //Makes some headers
-    let mut headers = [httparse::EMPTY_HEADER; 32];
-    //Places the headers into the request
-    let mut request = httparse::Request::new(&mut headers);
+/// Gets the session token from a request's cookie, or its Authorization header
+fn get_token_from_request(request : &Request) -> Result<String, String> {
+    //Programs using the API can send the token without a cookie
+    for value in request.header_values("Authorization") {
+        if let Some(token) = value.strip_prefix("Bearer ") {
+            return Ok(token.trim().to_string());
+        }
+    }
 
-    //Parses for the body's position
-    match request.parse(buffer) {
-        //If the size is found
-        Ok(size) => {
-            //Ensures the position is valid
-            match size {
-                httparse::Status::Complete(_) => (),
-                httparse::Status::Partial => return Err(String::from("Buffer could not fit entire HTTP request")),
-            }
-        }, 
-        Err(error) => return Err(error.to_string()),
+    // Gets the cookie text
+    let cookie_text = match get_cookie_from_request(request) {
+        Ok(cookie_text) => cookie_text,
+        Err(error) => return Err(error),
     };
 
-    //Looks for the cookie header
-    let cookie_header : Vec<&httparse::Header> = request.headers
-        .iter()
-        .filter(|header| header.name == "Cookie")
-        .collect();
+    match cookie::get_cookie(&cookie_text, cookie::SESSION_COOKIE) {
+        Some(token) => Ok(token),
+        None => Err(String::from("Invalid session, no session cookie was sent")),
+    }
+}
 
-    if cookie_header.len() != 1 { return Err(String::from("Error parsing the cookie header")); }
+/// Sends the session cookie again with a fresh Max-Age, since the session slides on every request
+/// Only done for live sessions held in a cookie, and never over a cookie the handler already set
+pub fn refresh_session_cookie(request : &Request, response : Response, client_tracker_rw : &Arc<RwLock<ClientTracker>>) -> Response {
+    if response.has_header("Set-Cookie") {
+        return response;
+    }
 
-    //Gets the value
-    match std::str::from_utf8(cookie_header[0].value) {
-        Ok(text) => Ok(text.to_string()),
-        Err(error) => Err(error.to_string()),
+    let token = match get_cookie_from_request(request).ok().and_then(|cookie_text| cookie::get_cookie(&cookie_text, cookie::SESSION_COOKIE)) {
+        Some(token) => token,
+        None => return response,
+    };
+
+    let active = match client_tracker_rw.read() {
+        Ok(client_tracker) => client_tracker.is_active(&token),
+        Err(_error) => false,
+    };
+    match active {
+        true => response.with_header("Set-Cookie", &cookie::session_cookie(&token)),
+        false => response,
     }
 }
 
 /// Gets the Users ID from a request
-fn get_user_id_from_request(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>) -> Result<ID, String> {
-    // Gets the cookie text
-    let mut cookie_text = match get_cookie_from_request(buffer) {
-        Ok(cookie_text) => cookie_text,
+pub fn get_user_id_from_request(request : &Request, client_tracker_rw : &Arc<RwLock<ClientTracker>>) -> Result<ID, String> {
+    //Gets the session token
+    let token = match get_token_from_request(request) {
+        Ok(token) => token,
         Err(error) => return Err(error),
     };
 
-    //Retains only text, no whitespace
-    cookie_text.retain(|c| !c.is_whitespace());
+    //Using the session keeps it from expiring
+    let mut client_tracker = match client_tracker_rw.write() {
+        Ok(client_tracker) => client_tracker,
+        Err(error) => return Err(format!("Cookie parsing error: {}", error)),
+    };
+
+    client_tracker.authenticate(&token)
+}
+
+/// Why a trade didn't happen
+#[derive(Debug)]
+pub enum TradeError {
+    /// There is no company with the name
+    UnknownCompany(String),
+    /// The user can't afford the stock or doesn't have enough to sell
+    Rejected(String),
+    /// The user has no open order or protection with the ID
+    UnknownOrder(String),
+    /// The server couldn't carry out the trade
+    Failed(String),
+}
 
-    //Finds the comma position
-    let comma_pos = match cookie_text.find(',') {
-        Some(x) => x,
-        None => return Err(format!("Error parsing cookie header: {}", cookie_text)),
+impl std::fmt::Display for TradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TradeError::UnknownCompany(error) => write!(f, "{}", error),
+            TradeError::Rejected(error) => write!(f, "{}", error),
+            TradeError::UnknownOrder(error) => write!(f, "{}", error),
+            TradeError::Failed(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// What a finished trade did
+pub struct TradeResult {
+    pub company_id : ID,
+    pub company_name : String,
+    /// The shares that traded, in exchange mode it can be fewer than were asked for
+    pub amount : usize,
+    /// The average price of one share when it was traded
+    pub price : f32,
+    /// The price of one share before the trade
+    pub quoted_price : f32,
+    /// The users money after the trade
+    pub money : f32,
+    /// The shares of the company the user has after the trade
+    pub shares_held : usize,
+}
+
+/// Buys or sells stock for a user and records it in the journal
+pub fn execute_trade(state : &ServerState, user_id : ID, side : TradeSide, company_name : &str, amount : usize) -> Result<TradeResult, TradeError> {
+    // Gets the user manager
+    let mut user_manager = match state.user_manager.write() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
     };
 
-    //Gets the ID position
-    let id_pos = match cookie_text.find("ID=") {
-        Some(x) => x,
-        None => return Err(format!("Cookie does not contain the clients id: {}", cookie_text))
+    // Gets the company manager mutably, in exchange mode the trade goes through the book
+    let mut company_manager = match state.company_manager.write() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
     };
 
-    // Gets the ID from the string
-    let id_str = &cookie_text[id_pos..comma_pos].to_string();
+    //Gets the company and the price it showed before the trade
+    let (company_id, quoted_price) = match company_manager.find_company(company_name) {
+        Ok(company) => (company.id(), company.stock_price()),
+        Err(error) => return Err(TradeError::UnknownCompany(error)),
+    };
 
-    //Gets the Client ID
-    let client_id = match ID::from_string(id_str) {
-        Ok(id) => id,
+    //Records the trade before making it, so a trade the user is told about is never lost
+    let entry = match side {
+        TradeSide::Buy => JournalEntry::Buy(user_id, company_id, amount),
+        TradeSide::Sell => JournalEntry::Sell(user_id, company_id, amount),
+    };
+    match record_to_journal(&state.journal, &entry) {
+        Ok(_) => (),
+        Err(error) => return Err(TradeError::Failed(error)),
+    }
+
+    //Trades the users stock
+    let fills = match company_manager.trade(&mut user_manager, user_id, side, company_id, amount, TradeKind::Market) {
+        Ok(fills) => fills,
+        Err(error) => return Err(TradeError::Rejected(error)),
+    };
+
+    //The users own fill comes first
+    let user = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Err(TradeError::Failed(error)),
+    };
+    let trade = match fills.first() {
+        Some(fill) => &fill.trade,
+        None => return Err(TradeError::Failed(String::from("The trade went through without a fill"))),
+    };
+
+    let result = TradeResult {
+        company_id,
+        company_name : trade.company_name().clone(),
+        amount : trade.amount(),
+        price : trade.price(),
+        quoted_price,
+        money : user.money(),
+        shares_held : user.wallet().holders()
+            .iter()
+            .find(|holder| holder.company_id().equals(company_id))
+            .map(|holder| holder.stock_amount())
+            .unwrap_or(0),
+    };
+
+    //Tells everyone in the trade, once the managers are let go
+    let trade_events = trade_events_of(&fills, &mut company_manager, &user_manager);
+    drop(company_manager);
+    drop(user_manager);
+    send_trade_events(state, trade_events);
+
+    Ok(result)
+}
+
+/// Makes the events of a trade, with those of any resting orders it cancelled because they couldn't trade
+fn trade_events_of(fills : &[Fill], company_manager : &mut CompanyManager, user_manager : &UserManager) -> Result<Vec<(ID, Vec<UserEvent>)>, String> {
+    let mut trade_events = match events::events_of_fills(fills, user_manager) {
+        Ok(trade_events) => trade_events,
         Err(error) => return Err(error),
     };
+    match events::events_of_cancelled(&company_manager.take_cancelled_orders(), user_manager) {
+        Ok(cancelled_events) => trade_events.extend(cancelled_events),
+        Err(error) => return Err(error),
+    }
+    Ok(trade_events)
+}
 
-    //Finds the usernames position
-    let username_pos = match cookie_text.find("USERNAME=") {
-        Some(x) => x + 9,
-        None => return Err(format!("Cookie does not contain the clients username: {}", cookie_text)),
+/// Sends the events of a trade that already happened, so a failed notification is only logged
+fn send_trade_events(state : &ServerState, trade_events : Result<Vec<(ID, Vec<UserEvent>)>, String>) {
+    let trade_events = match trade_events {
+        Ok(trade_events) => trade_events,
+        Err(error) => return println!("Error sending trade events: {}", error),
     };
 
-    //Has the username
-    let username = &cookie_text[username_pos..].to_string();
+    match state.event_feed.write() {
+        Ok(mut event_feed) => {
+            for (user_id, events) in trade_events {
+                if let Err(error) = event_feed.send(user_id, &events) {
+                    println!("Error sending trade events: {}", error);
+                }
+            }
+        },
+        Err(error) => println!("Error sending trade events: {}", error),
+    }
+}
 
-    //Now that we have the username and ID we can check if they exist in the handler
-    let client_tracker = match client_tracker_rw.read() {
-        Ok(client_tracker) => client_tracker,
-        Err(error) => return Err(format!("Cookie parsing error: {}", error)),
+/// Places a limit order for a user, holding back its money or shares until it fills or is cancelled
+/// In exchange mode it trades against the book first, the order returned holds what is left open
+pub fn place_order(state : &ServerState, user_id : ID, side : TradeSide, company_name : &str, amount : usize, limit_price : f32) -> Result<Order, TradeError> {
+    // Gets the user manager
+    let mut user_manager = match state.user_manager.write() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
     };
 
-    //Gets the client
-    let client = match client_tracker.get_client_by_client_id(client_id) {
-        Ok(client) => client,
-        Err(error) => return Err(error),
+    // Gets the company manager mutably, as the order goes in the companies book
+    let mut company_manager = match state.company_manager.write() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
+    };
+
+    let company_id = match company_manager.find_company(company_name) {
+        Ok(company) => company.id(),
+        Err(error) => return Err(TradeError::UnknownCompany(error)),
+    };
+
+    //Records the order before placing it
+    let order = Order::new(company_id, user_id, side, amount, limit_price);
+    match record_to_journal(&state.journal, &JournalEntry::place_order(&order)) {
+        Ok(_) => (),
+        Err(error) => return Err(TradeError::Failed(error)),
+    }
+
+    let fills = match company_manager.place_order(&mut user_manager, order.clone()) {
+        Ok(fills) => fills,
+        Err(error) => return Err(TradeError::Rejected(error)),
+    };
+
+    //What is left of the order, nothing if it all traded
+    let mut open_order = order;
+    let filled : usize = fills.iter()
+        .filter(|fill| fill.user_id.equals(user_id))
+        .map(|fill| fill.trade.amount())
+        .sum();
+    open_order.split_off(filled);
+
+    let trade_events = trade_events_of(&fills, &mut company_manager, &user_manager);
+    drop(company_manager);
+    drop(user_manager);
+    send_trade_events(state, trade_events);
+
+    Ok(open_order)
+}
+
+/// Cancels one of the users open orders, giving back what it held
+pub fn cancel_order(state : &ServerState, user_id : ID, order_id : usize) -> Result<Order, TradeError> {
+    // Gets the user manager
+    let mut user_manager = match state.user_manager.write() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
+    };
+
+    // Gets the company manager mutably, as the order leaves the companies book
+    let mut company_manager = match state.company_manager.write() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
     };
 
-    //Checks if the name matches
-    if client.user_name().eq(username) {
-        return Ok(client.user_id())
+    //Users can only see and cancel their own orders
+    let order_id = match company_manager.orders_of_user(user_id).iter().find(|order| order.id().value() == order_id) {
+        Some(order) => order.id(),
+        None => return Err(TradeError::UnknownOrder(format!("You have no open order with ID {}", order_id))),
+    };
+
+    //Records the cancel before making it
+    match record_to_journal(&state.journal, &JournalEntry::CancelOrder(order_id)) {
+        Ok(_) => (),
+        Err(error) => return Err(TradeError::Failed(error)),
     }
 
-    Err(format!("Cookie header could not be parsed properly: {}", cookie_text))
+    match company_manager.cancel_order(&mut user_manager, order_id) {
+        Ok(order) => Ok(order),
+        Err(error) => Err(TradeError::Failed(error)),
+    }
+}
+
+/// Attaches a stop-loss, trailing-stop or take-profit to shares a user holds
+/// Level is the trigger price, or the distance below the highest price for a trailing stop
+pub fn protect_holding(state : &ServerState, user_id : ID, kind : ProtectionKind, company_name : &str, amount : usize, level : f32) -> Result<Protection, TradeError> {
+    // Gets the user manager
+    let mut user_manager = match state.user_manager.write() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
+    };
+
+    // Gets the user mutably
+    let user : &mut User = match user_manager.get_user_by_id_mut(user_id) {
+        Ok(user) => user,
+        Err(error) => return Err(TradeError::Failed(error)),
+    };
+
+    // Gets the company manager
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
+    };
+
+    let company = match company_manager.find_company(company_name) {
+        Ok(company) => company,
+        Err(error) => return Err(TradeError::UnknownCompany(error)),
+    };
+
+    let protection = match Protection::new(company.id(), user_id, kind, amount, level, company.stock_price()) {
+        Ok(protection) => protection,
+        Err(error) => return Err(TradeError::Rejected(error)),
+    };
+    //Records the protection before attaching it
+    match record_to_journal(&state.journal, &JournalEntry::protect(&protection)) {
+        Ok(_) => (),
+        Err(error) => return Err(TradeError::Failed(error)),
+    }
+
+    match user.protect(protection.clone()) {
+        Ok(_) => Ok(protection),
+        Err(error) => Err(TradeError::Rejected(error)),
+    }
+}
+
+/// Removes one of the users protections
+pub fn cancel_protection(state : &ServerState, user_id : ID, protection_id : usize) -> Result<Protection, TradeError> {
+    // Gets the user manager
+    let mut user_manager = match state.user_manager.write() {
+        Ok(user_manager) => user_manager,
+        Err(error) => return Err(TradeError::Failed(error.to_string())),
+    };
+
+    // Gets the user mutably
+    let user : &mut User = match user_manager.get_user_by_id_mut(user_id) {
+        Ok(user) => user,
+        Err(error) => return Err(TradeError::Failed(error)),
+    };
+
+    //Only the users own protections are searched
+    let protection_id = match user.wallet().protections().iter().find(|protection| protection.id().value() == protection_id) {
+        Some(protection) => protection.id(),
+        None => return Err(TradeError::UnknownOrder(format!("You have no protection with ID {}", protection_id))),
+    };
+
+    //Records the cancel before making it
+    match record_to_journal(&state.journal, &JournalEntry::CancelProtection(user_id, protection_id)) {
+        Ok(_) => (),
+        Err(error) => return Err(TradeError::Failed(error)),
+    }
+
+    match user.cancel_protection(protection_id) {
+        Ok(protection) => Ok(protection),
+        Err(error) => Err(TradeError::Failed(error)),
+    }
 }
 
 /// Sells a stock from a user
-fn sell_stock(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>, company_manager_rw : &Arc<RwLock<CompanyManager>>, user_manager_rw : &Arc<RwLock<UserManager>>) -> Result<String, String> {
+fn sell_stock(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
     //Gets the data from the request
-    let request_data = match get_text_from_request(buffer) {
+    let request_data = match request.body_text() {
         Ok(request_data) => request_data,
         Err(error) => return Err(error),
     };
@@ -165,46 +430,23 @@ fn sell_stock(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracke
     }
     
     //Gets the clients ID from the request
-    let user_id : ID = match get_user_id_from_request(buffer, client_tracker_rw) {
+    let user_id : ID = match get_user_id_from_request(request, &state.client_tracker) {
         Ok(client_id) => client_id,
         Err(error) => return Err(error),
     };
 
-    // Gets the user manager
-    let mut user_manager = match user_manager_rw.write() {
-        Ok(user_manager) => user_manager,
-        Err(error) => return Err(error.to_string()),
-    };
-
-    // Gets the user mutably
-    let user : &mut User = match user_manager.get_user_by_id_mut(user_id) {
-        Ok(user) => user,
-        Err(error) => return Err(error),
-    };
-
-    //Gets the company manager
-    let company_manager = match company_manager_rw.read() {
-        Ok(company_manager) => company_manager,
-        Err(error) => return Err(error.to_string()),
-    };
-
-    //Gets the company
-    let company = match company_manager.get_company_by_name(&company_name) {
-        Ok(company) => company,
-        Err(error) => return Err(error),
-    };
-    
     //Sells the users stock
-    match user.sell_stock(&company_manager, company.id(), sell_amount) {
-        Ok(_) => return Ok(String::from("Sold")),
-        Err(_error) => return Ok(format!("No enough money to purchase stock: {}", company.name())),
+    match execute_trade(state, user_id, TradeSide::Sell, &company_name, sell_amount) {
+        Ok(_) => Ok(Response::ok(String::from("Sold"))),
+        Err(TradeError::Rejected(_error)) => Ok(Response::ok(format!("No enough money to purchase stock: {}", company_name))),
+        Err(error) => Err(error.to_string()),
     }
 }
 
-/// Buys a stock mentioned by the buffer
-fn buy_stock(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>, company_manager_rw : &Arc<RwLock<CompanyManager>>, user_manager_rw : &Arc<RwLock<UserManager>>) -> Result<String, String> {    
+/// Buys a stock mentioned by the request
+fn buy_stock(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
     //Gets the data from the request
-    let request_data = match get_text_from_request(buffer) {
+    let request_data = match request.body_text() {
         Ok(request_data) => request_data,
         Err(error) => return Err(error),
     };
@@ -228,46 +470,22 @@ fn buy_stock(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker
     }
 
     //Gets the clients ID from the request
-    let user_id = match get_user_id_from_request(buffer, client_tracker_rw) {
+    let user_id = match get_user_id_from_request(request, &state.client_tracker) {
         Ok(client_id) => client_id,
         Err(error) => return Err(error),
     };
     
-    // Gets the user manager
-    let mut user_manager = match user_manager_rw.write() {
-        Ok(user_manager) => user_manager,
-        Err(error) => return Err(error.to_string()),
-    };
-
-    // Gets the user mutably
-    let user : &mut User = match user_manager.get_user_by_id_mut(user_id) {
-        Ok(user) => user,
-        Err(error) => return Err(error),
-    };
-
-    // Gets the company manager
-    let company_manager = match company_manager_rw.read() {
-        Ok(company_manager) => company_manager,
-        Err(error) => return Err(error.to_string()),
-    };
-
-    //Gets the company
-    let company= match company_manager.get_company_by_name(&company_name) {
-        Ok(company) => company,
-        Err(error) => return Err(error),
-    };
-
     //Buys the users stock
-    match company.purchase_stock(user, buy_amount) {
-        Ok(_) => return Ok(String::from("Bought")),
-        Err(error) => return Err(error),
-    };
+    match execute_trade(state, user_id, TradeSide::Buy, &company_name, buy_amount) {
+        Ok(_) => Ok(Response::ok(String::from("Bought"))),
+        Err(error) => Err(error.to_string()),
+    }
 }
 
 /// Creates an Account for the user
-fn create_account(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>, user_manager_rw : &Arc<RwLock<UserManager>>) -> Result<String, String> {
+fn create_account(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
     //Gets the data from the request
-    let request_data : String = match get_text_from_request(buffer) {
+    let request_data : String = match request.body_text() {
         Ok(name) => name,
         Err(error) => return Err(error),
     };
@@ -293,26 +511,26 @@ fn create_account(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTr
     //Generates the password from the text
     let user_passord : Password = match Password::from_text(&password) {
         Ok(pass) => pass,
-        Err(error) => return Ok(error.to_string()),
+        Err(error) => return Ok(Response::ok(error.to_string())),
     };
 
     // Validates that the users info is valid
     {
         //The User name / Display name must be less than 20 characters long
-        if user_name.len() > 20 { return Ok(String::from("User name must be less than 20 characters long")); }
-        if display_name.len() > 20 { return Ok(String::from("Display name must be less than 20 characters long"))}
+        if user_name.len() > 20 { return Ok(Response::ok(String::from("User name must be less than 20 characters long"))); }
+        if display_name.len() > 20 { return Ok(Response::ok(String::from("Display name must be less than 20 characters long")))}
 
         // User name / Display name must be at least 3 characters long
-        if user_name.len() < 3 { return Ok(String::from("User name must be more than 2 characters long")); }
-        if display_name.len() < 3 { return Ok(String::from("Display name must be more than 2 characters long"))}
+        if user_name.len() < 3 { return Ok(Response::ok(String::from("User name must be more than 2 characters long"))); }
+        if display_name.len() < 3 { return Ok(Response::ok(String::from("Display name must be more than 2 characters long")))}
 
         // User name / Display name cannot contain spaces!
-        if user_name.contains(char::is_whitespace) { return Ok(String::from("User name cannot contain white-space!")); }
-        if display_name.contains(char::is_whitespace) { return Ok(String::from("User name cannot contain white-space!")); }
+        if user_name.contains(char::is_whitespace) { return Ok(Response::ok(String::from("User name cannot contain white-space!"))); }
+        if display_name.contains(char::is_whitespace) { return Ok(Response::ok(String::from("User name cannot contain white-space!"))); }
     }
 
     // Gets the user manager
-    let mut user_manager = match user_manager_rw.write() {
+    let mut user_manager = match state.user_manager.write() {
         Ok(user_manager) => user_manager,
         Err(error) => return Err(error.to_string()),
     };
@@ -320,26 +538,34 @@ fn create_account(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTr
     // Adds the new User
     let user_id = match user_manager.new_user(user_name.clone(), display_name.clone(), user_passord) {
         Ok(id) => id,
-        Err(error) => return Ok(error),
+        Err(error) => return Ok(Response::ok(error)),
+    };
+
+    // Records the new account before handing out a session, it is taken back if it can't be
+    let recorded = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => record_to_journal(&state.journal, &JournalEntry::new_user(user)),
+        Err(error) => Err(error),
     };
+    if let Err(error) = recorded {
+        user_manager.remove_user(user_id);
+        return Err(error);
+    }
 
     // Gets the client tracker
-    let mut client_tracker = match client_tracker_rw.write() {
+    let mut client_tracker = match state.client_tracker.write() {
         Ok(client_tracker) => client_tracker,
         Err(error) => return Err(error.to_string()),
     };
 
-    //Now adds the new user to the tracker
-    match client_tracker.add_client(user_id, user_name.clone(), display_name) {
-        Ok(new_id) => Ok(format!("ID={},USERNAME={}", new_id, user_name)),
-        Err(error) => Err(error),
-    }
+    //Now starts a session for the new user
+    let token = client_tracker.add_client(user_id);
+    Ok(Response::ok(String::from("Logged in")).with_header("Set-Cookie", &cookie::session_cookie(&token)))
 }
 
 /// Logins the client to their account
-fn login(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>, user_manager_rw : &Arc<RwLock<UserManager>>) -> Result<String, String> {
+fn login(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
     //Gets the data from the request
-    let request_data = match get_text_from_request(buffer) {
+    let request_data = match request.body_text() {
         Ok(request_data) => request_data,
         Err(error) => return Err(error),
     };
@@ -356,60 +582,76 @@ fn login(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>,
         Err(_error) => return Err(String::from("Could not parse password!")),
     };
 
-    //Generates the password from the text
-    let user_passord = match Password::from_text(&password) {
-        Ok(user_passord) => user_passord,
-        Err(error) => return Err(error.to_string()),
-    };
-
     //Gets the user manager
-    let user_manager = match user_manager_rw.read() {
+    let user_manager = match state.user_manager.read() {
         Ok(user_manager) => user_manager,
         Err(error) => return Err(error.to_string()),
     };
 
-    //Checks that the account exists
-    let user : &User = match user_manager.get_user_by_username(&user_name) {
-        Ok(read_user) => read_user,
-        Err(_error) => return Ok(format!("No user with the name {} exist", user_name)),
+    //Checks that the account exists, taking its password hash out
+    let (user_id, user_password) = match user_manager.get_user_by_username(&user_name) {
+        Ok(read_user) => (read_user.id(), read_user.password().clone()),
+        Err(_error) => return Ok(Response::ok(format!("No user with the name {} exist", user_name))),
     };
 
+    //Hashing is slow on purpose, so it happens after the user manager is let go for trades and ticks
+    drop(user_manager);
+
     //Ensures the password is correct
-    if !user.try_password(user_passord) {
-        return Ok(String::from("Incorrect password"));
+    if !user_password.verify(&password) {
+        return Ok(Response::ok(String::from("Incorrect password")));
     }
 
     //Gets the socket tracker
-    let mut client_tracker = match client_tracker_rw.write() {
+    let mut client_tracker = match state.client_tracker.write() {
         Ok(client_tracker) => client_tracker,
         Err(error) => return Err(error.to_string()),
     };
 
-    //Adds the client to the socket tracker
-    match client_tracker.add_client(user.id(), user_name.clone(), user.display_name().clone()) {
-        Ok(client_id) => Ok(format!("ID={},USERNAME={}", client_id, user_name)),
-        Err(_) => {
-            match client_tracker.get_client_id_by_user_id(user.id()) {
-                Ok(client_id) => Ok(format!("ID={},USERNAME={}", client_id, user_name)),
-                Err(error) => Err(error),
-            }
-        },
+    //Every login gets its own session, so a user can be logged in on many devices
+    let token = client_tracker.add_client(user_id);
+    Ok(Response::ok(String::from("Logged in")).with_header("Set-Cookie", &cookie::session_cookie(&token)))
+}
+
+/// Logs the client out, the session can't be used again
+fn logout(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    //Ends the session if there is one
+    if let Ok(token) = get_token_from_request(request) {
+        match state.client_tracker.write() {
+            Ok(mut client_tracker) => { let _ = client_tracker.remove_client(&token); },
+            Err(error) => return Err(error.to_string()),
+        }
+        match state.event_feed.write() {
+            Ok(mut event_feed) => event_feed.close_session(&token),
+            Err(error) => return Err(error.to_string()),
+        }
+    }
+
+    //The browser forgets the cookie even if the session had already ended
+    Ok(Response::ok(String::from("Logged out")).with_header("Set-Cookie", &cookie::expired_session_cookie()))
+}
+
+/// Writes an entry to the journal
+fn record_to_journal(journal_rw : &Arc<RwLock<Journal>>, entry : &JournalEntry) -> Result<(), String> {
+    match journal_rw.write() {
+        Ok(mut journal) => journal.record(entry),
+        Err(error) => Err(error.to_string()),
     }
 }
 
 /// Loads the new leaderboards
-fn load_new_leaderboards(ranker_rw : &Arc<RwLock<Ranker>>) -> Result<String, String> {
+fn leaderboard_data(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
     //Reads the ranker
-    match ranker_rw.read() {
+    match state.ranker.read() {
         Ok(ranker) => load_leaderboards(&ranker),
         Err(error) => Err(error.to_string()),
     }
 }
 
 /// Loads the old leaderboards
-fn load_old_leaderboards(ranker_history_rw : &Arc<RwLock<RankerHistory>>) -> Result<String, String>  {
+fn old_leaderboard_data(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String>  {
     //Reads the ranker
-    match ranker_history_rw.read() {
+    match state.ranker_history.read() {
         Ok(ranker_history) => {
             match ranker_history.get_recent() {
                 Some(ranker) => load_leaderboards(ranker),
@@ -421,9 +663,9 @@ fn load_old_leaderboards(ranker_history_rw : &Arc<RwLock<RankerHistory>>) -> Res
 }
 
 /// Loads the leaderboards from a ranker
-fn load_leaderboards(ranker : &Ranker) -> Result<String, String>  {
+fn load_leaderboards(ranker : &Ranker) -> Result<Response, String>  {
     //Gets the leaderboard data
-    ranker.get_data_range(0..10)
+    ranker.get_data_range(0..10).map(Response::ok)
 }
 
 /// Parses text for whatever is in 'to_find'
@@ -454,184 +696,201 @@ fn parse_text(to_find : &String, to_parse : &String) -> Result<String, String> {
 }
 
 
-/// Gets the response based off the HTTPS request
-fn get_response(buffer : &[u8; 1024], client_tracker_rw : &Arc<RwLock<ClientTracker>>, company_manager_rw : &Arc<RwLock<CompanyManager>>, user_manager_rw : &Arc<RwLock<UserManager>>, ranker_rw : &Arc<RwLock<Ranker>>, ranker_history_rw : &Arc<RwLock<RankerHistory>>) -> Result<String, String> {
-    //All the possible request headers
-    let load_page = b"GET / ";
-    let load_login_page = b"GET /login.html";
-    let load_create_page = b"GET /create_account.html";
-    let load_stock_data = b"GET /stock_data";
-    let load_stock_amount = b"GET /stock_amount";
-    let load_cash_amount = b"GET /money";
-    let load_leaderboard = b"GET /leaderboard_data";
-    let load_old_leaderboard = b"GET /old_leaderboard_data";
-    let buy_stock_text = b"POST /buy_request";
-    let sell_stock_text = b"POST /sell_request";
-    let login_text = b"POST /login";
-    let create_account_text = b"POST /create_account";
-
-    //Getting the webpage
-    if buffer.starts_with(load_page) {
-        return Ok(read_from_file("html/hello.html").unwrap());
-    } else 
-    //Loads the login page
-    if buffer.starts_with(load_login_page) {
-        return Ok(read_from_file("html/login.html").unwrap());
-    } else
-    //Loads the create account page
-    if buffer.starts_with(load_create_page) {
-        return Ok(read_from_file("html/create_account.html").unwrap());
-    } else
-    //Load the stocks valuations
-    if buffer.starts_with(load_stock_data) {
-        match company_manager_rw.read() {
-            Ok(company_manager) => return Ok(company_manager.get_data()),
-            Err(error) => panic!("Stock data mutex was poisoned: {}", error),
-        }
-    } else 
-    //Load the amount of stocks a user has
-    if buffer.starts_with(load_stock_amount) {
-        //Gets the clients ID from the request
-        let user_id : ID = match get_user_id_from_request(buffer, client_tracker_rw) {
-            Ok(id) => id,
-            Err(error) => return Err(error),
-        };
-
-        //Reads the user manager
-        let user_manager = match user_manager_rw.read() {
-            Ok(user_manager) => user_manager,
-            Err(error) => panic!("User manager lock was poisoned: {}", error),
-        };
-
-        //Gets the user
-        let user : &User = match user_manager.get_user_by_id(user_id) {
-            Ok(user) => user,
-            Err(error) => return Err(error),
-        };
-
-        //Returns the users stock amount
-        return Ok(String::from(user.wallet().get_data()));
-    } else 
-    //Load the cash
-    if buffer.starts_with(load_cash_amount) {
-        //Gets the clients ID from the request
-        let user_id : ID = match get_user_id_from_request(buffer, client_tracker_rw) {
-            Ok(id) => id,
-            Err(error) => return Err(error),
-        };
-
-        // Reads from the user manager
-        let user_manager = match user_manager_rw.read() {
-            Ok(user_manager) => user_manager,
-            Err(error) => panic!("User manager lock was poisoned: {}", error),
-        };
-
-        // Gets the user
-        let user : &User = match user_manager.get_user_by_id(user_id) {
-            Ok(user) => user,
-            Err(error) => return Err(error),
-        };
-
-        // Returns the users stock amount
-        return Ok(user.money().to_string());
-    } else
-    //Loads the leaderboards
-    if buffer.starts_with(load_leaderboard) {
-        return load_new_leaderboards(ranker_rw);
-    } else
-    // Loads the old leaderboards
-    if buffer.starts_with(load_old_leaderboard) {
-        return load_old_leaderboards(ranker_history_rw);
-    }
-    //Sells a stock
-    if buffer.starts_with(sell_stock_text){
-        return sell_stock(buffer, client_tracker_rw, company_manager_rw, user_manager_rw);
-    } else 
-    //Buys a stock
-    if buffer.starts_with(buy_stock_text) {
-        return buy_stock(buffer, client_tracker_rw, company_manager_rw, user_manager_rw);
-    } else
-    if buffer.starts_with(login_text) {
-        return login(buffer, client_tracker_rw, user_manager_rw);
-    } else
-    //Creates an account
-    if buffer.starts_with(create_account_text) {
-        return create_account(buffer, client_tracker_rw, user_manager_rw);
-    }
-
-    //If we are here, we do not have any valid responses
-    Err(String::from("No response programmed"))
+/// Loads the main page
+fn home_page(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    //Clients without a session have to log in first
+    if get_user_id_from_request(request, &state.client_tracker).is_err() {
+        return Ok(Response::redirect("/login.html"));
+    }
+    read_from_file("html/hello.html").map(Response::ok)
 }
 
+/// Loads the login page
+fn login_page(_request : &Request, _params : &Params, _state : &ServerState) -> Result<Response, String> {
+    read_from_file("html/login.html").map(Response::ok)
+}
 
+/// Loads the create account page
+fn create_account_page(_request : &Request, _params : &Params, _state : &ServerState) -> Result<Response, String> {
+    read_from_file("html/create_account.html").map(Response::ok)
+}
 
-/// Handles all possible requests from a client
-/// If a request is not pre-programmed, Error 404 is returned
-pub fn handle_connection(mut stream : TcpStream, client_tracker_rw : &Arc<RwLock<ClientTracker>>, company_manager_rw : &Arc<RwLock<CompanyManager>>, user_manager_rw : &Arc<RwLock<UserManager>>, ranker_rw : &Arc<RwLock<Ranker>>, ranker_history_rw : &Arc<RwLock<RankerHistory>>) -> Result<(), String> {
-    //The Buffer
-    let mut buffer = [0; 1024];
+/// Load the stocks valuations
+fn stock_data(_request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    match state.company_manager.read() {
+        Ok(company_manager) => Ok(Response::ok(company_manager.get_data())),
+        Err(error) => panic!("Stock data mutex was poisoned: {}", error),
+    }
+}
 
-    //Reads the Stream
-    match stream.read(&mut buffer) {
-        Err(error) => return Err(error.to_string()),
-        _ => (),
+/// Loads the valuation of a single company, found by its ticker, name or ID
+fn company_data(_request : &Request, params : &Params, state : &ServerState) -> Result<Response, String> {
+    let name = match params.get("name") {
+        Some(name) => name.to_string(),
+        None => return Err(String::from("No company name in the path")),
     };
 
-    //DEBUG: Prints the request!
-    println!("New Request:\n{}", String::from_utf8_lossy(&buffer[..]));
+    let company_manager = match state.company_manager.read() {
+        Ok(company_manager) => company_manager,
+        Err(error) => panic!("Stock data mutex was poisoned: {}", error),
+    };
 
-    //Gets the response text
-    let response_text_result = get_response(&buffer, client_tracker_rw, company_manager_rw, user_manager_rw, ranker_rw, ranker_history_rw);
+    match company_manager.find_company(&name) {
+        Ok(company) => Ok(Response::ok(company.get_data())),
+        Err(_error) => Ok(Response::not_found()),
+    }
+}
 
-    //Defaults to the invalid response
-    let status_line;
-    let contents;
+/// Load the amount of stocks a user has
+fn stock_amount(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    //Gets the clients ID from the request
+    let user_id : ID = match get_user_id_from_request(request, &state.client_tracker) {
+        Ok(id) => id,
+        Err(error) => return Err(error),
+    };
 
-    match response_text_result {
-        //The response was ok
-        Ok(response) => {
-            contents = response; 
-            status_line = "HTTP/1.1 200 OK"; 
-        },
-        //There was an error processing the request
-        Err(error) => {
-            println!("Error: {}", error);
-            // If the ID is wrong make the log back in!
-            if error.starts_with("No client with ID") {
-                contents = "INVALID ID".to_string();
-                status_line = "HTTP/1.1 200 OK";
-            } else {
-                println!("Server Error: {}", error);
-                status_line = "HTTP/1.1 404 NOT FOUND";
-                
-                if error.starts_with("No client with ID") {
-                    contents = String::from("INVALID ID");
-                } else {
-                    contents = read_from_file("html/404.html").unwrap(); 
-                }
-            }
-        },
+    //Reads the user manager
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => panic!("User manager lock was poisoned: {}", error),
+    };
+
+    //Gets the user
+    let user : &User = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Err(error),
+    };
+
+    //Returns the users stock amount
+    Ok(Response::ok(user.wallet().get_data()))
+}
+
+/// Load the cash
+fn money(request : &Request, _params : &Params, state : &ServerState) -> Result<Response, String> {
+    //Gets the clients ID from the request
+    let user_id : ID = match get_user_id_from_request(request, &state.client_tracker) {
+        Ok(id) => id,
+        Err(error) => return Err(error),
+    };
+
+    // Reads from the user manager
+    let user_manager = match state.user_manager.read() {
+        Ok(user_manager) => user_manager,
+        Err(error) => panic!("User manager lock was poisoned: {}", error),
+    };
+
+    // Gets the user
+    let user : &User = match user_manager.get_user_by_id(user_id) {
+        Ok(user) => user,
+        Err(error) => return Err(error),
+    };
+
+    // Returns the users money
+    Ok(Response::ok(user.money().to_string()))
+}
+
+/// The price stream only works as a WebSocket, plain requests are told to upgrade
+fn price_stream_page(_request : &Request, _params : &Params, _state : &ServerState) -> Result<Response, String> {
+    Ok(websocket::upgrade_required())
+}
+
+/// Makes the router with every page and request of the game
+pub fn make_router() -> Router<ServerState> {
+    let router = Router::new()
+        .add("GET", "/", home_page)
+        .add("GET", "/login.html", login_page)
+        .add("GET", "/create_account.html", create_account_page)
+        .add("GET", "/stock_data", stock_data)
+        .add("GET", "/companies/{name}", company_data)
+        .add("GET", "/stock_amount", stock_amount)
+        .add("GET", "/money", money)
+        .add("GET", "/leaderboard_data", leaderboard_data)
+        .add("GET", "/news", api::get_news)
+        .add("GET", "/old_leaderboard_data", old_leaderboard_data)
+        .add("POST", "/buy_request", buy_stock)
+        .add("POST", "/sell_request", sell_stock)
+        .add("POST", "/login", login)
+        .add("POST", "/create_account", create_account)
+        .add("POST", "/logout", logout)
+        .add("GET", websocket::PRICE_STREAM_PATH, price_stream_page);
+
+    api::add_routes(router)
+}
+
+/// Opens the event stream of the requests session
+fn open_event_stream(mut stream : TcpStream, request : &Request, state : &ServerState) -> Result<(), String> {
+    let token = get_token_from_request(request);
+    let user_id = get_user_id_from_request(request, &state.client_tracker);
+
+    match (token, user_id) {
+        (Ok(token), Ok(user_id)) => events::accept(stream, token, user_id, &state.event_feed),
+        (_, Err(error)) | (Err(error), _) => Response::with_status("HTTP/1.1 401 UNAUTHORIZED", error).write_to(&mut stream),
     }
+}
 
-    //Formats the response
-    let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
-    
-    //Writes the response
-    match stream.write(response.as_bytes()) {
-        Err(error) => return Err(error.to_string()),
-        _ => (),
+/// Handles all possible requests from a client
+/// Unknown pages get Error 404, and requests with a body bigger than max_body_size get Error 413
+pub fn handle_connection(mut stream : TcpStream, router : &Router<ServerState>, state : &ServerState, max_body_size : usize) -> Result<(), String> {
+    //A client that stops sending can't hold the thread forever
+    if let Err(error) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+        return Err(error.to_string());
     }
 
-    //Flushes the response
-    match stream.flush() {
+    //Reads the request, refusing ones that are too big
+    let response = match request::read_request(&mut stream, max_body_size) {
+        Ok(request) => {
+            //DEBUG: Prints the request!
+            println!("New Request: {} {}", request.method(), request.path());
+
+            //The price stream keeps the connection, so it skips the normal response
+            if request.path() == websocket::PRICE_STREAM_PATH && websocket::is_upgrade(&request) {
+                return websocket::accept(stream, &request, &state.price_stream);
+            }
+            //So does the event stream
+            if request.path() == events::EVENTS_PATH && request.method() == "GET" {
+                return open_event_stream(stream, &request, state);
+            }
+
+            match router.route(&request, state) {
+                //The response was ok
+                Ok(response) => refresh_session_cookie(&request, response, &state.client_tracker),
+                //There was an error processing the request
+                Err(error) => {
+                    // If the session is wrong make them log back in!
+                    if error.starts_with("Invalid session") {
+                        Response::ok(String::from("INVALID ID"))
+                    } else {
+                        println!("Server Error: {}", error);
+                        Response::with_status("HTTP/1.1 400 BAD REQUEST", error)
+                    }
+                },
+            }
+        },
+        Err(RequestError::BodyTooLarge(size)) => Response::with_status("HTTP/1.1 413 PAYLOAD TOO LARGE", format!("Request body of {} bytes is larger than the limit of {} bytes", size, max_body_size)),
+        Err(RequestError::HeadersTooLarge) => Response::with_status("HTTP/1.1 431 REQUEST HEADER FIELDS TOO LARGE", RequestError::HeadersTooLarge.to_string()),
+        Err(RequestError::Invalid(error)) => Response::with_status("HTTP/1.1 400 BAD REQUEST", error),
+        //There is nobody to respond to
         Err(error) => return Err(error.to_string()),
-        _ => (),
+    };
+
+    response.write_to(&mut stream)
+}
+
+/// Tells a client the server is too busy to handle its request
+/// Used when every worker is busy and the queue is full, so it never waits on the client
+pub fn reject_busy(mut stream : TcpStream) -> Result<(), String> {
+    //Takes whatever part of the request already arrived, so closing doesn't reset the connection
+    if let Err(error) = stream.set_nonblocking(true) {
+        return Err(error.to_string());
+    }
+    let mut discard = [0u8; 1024];
+    while let Ok(size) = stream.read(&mut discard) {
+        if size == 0 { break; }
     }
 
-    Ok(())
+    //The reply is small enough to fit in the sockets buffer
+    Response::with_status("HTTP/1.1 503 SERVICE UNAVAILABLE", String::from("Server is busy, try again"))
+        .with_header("Retry-After", "1")
+        .with_header("Connection", "close")
+        .write_to(&mut stream)
 }
@@ -0,0 +1,360 @@
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::companies::company_manager::CompanyManager;
+use crate::servers::request::Request;
+use crate::servers::response::Response;
+use crate::users::ranking::RankChange;
+
+/// The path clients connect to for the price stream
+pub const PRICE_STREAM_PATH : &str = "/price_stream";
+
+/// Added to the clients key to prove the server speaks WebSocket (RFC 6455)
+const WEBSOCKET_GUID : &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// A subscriber that can't take a message this quickly is dropped, so it can't hold up the market
+const WRITE_TIMEOUT : Duration = Duration::from_secs(1);
+/// The stream only sends, so anything bigger from a client is a mistake
+const MAX_CLIENT_FRAME : usize = 4096;
+
+const OPCODE_CONTINUATION : u8 = 0x0;
+const OPCODE_TEXT : u8 = 0x1;
+const OPCODE_BINARY : u8 = 0x2;
+const OPCODE_CLOSE : u8 = 0x8;
+const OPCODE_PING : u8 = 0x9;
+const OPCODE_PONG : u8 = 0xA;
+
+/// The close code for a client breaking the protocol
+const CLOSE_PROTOCOL_ERROR : u16 = 1002;
+/// The close code for a client sending a frame too big to read
+const CLOSE_TOO_BIG : u16 = 1009;
+
+/// A frame sent by a client
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub opcode : u8,
+    pub payload : Vec<u8>,
+}
+
+/// Checks if a request is asking to become a WebSocket
+pub fn is_upgrade(request : &Request) -> bool {
+    request.header_values("Upgrade")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim().eq_ignore_ascii_case("websocket"))
+}
+
+/// Works out the Sec-WebSocket-Accept header for a clients Sec-WebSocket-Key
+pub fn accept_key(key : &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Checks the upgrade request, returns the accept key or the response refusing it
+fn check_handshake(request : &Request) -> Result<String, Response> {
+    if request.method() != "GET" {
+        return Err(Response::method_not_allowed(&["GET"]));
+    }
+
+    let upgrades_connection = request.header_values("Connection")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    if !upgrades_connection {
+        return Err(Response::with_status("HTTP/1.1 400 BAD REQUEST", String::from("Connection must include Upgrade")));
+    }
+
+    //Version 13 is the only one browsers use
+    if request.header_values("Sec-WebSocket-Version").iter().all(|version| version.trim() != "13") {
+        return Err(upgrade_required());
+    }
+
+    //The key is 16 random bytes in base64
+    let key = match request.header_values("Sec-WebSocket-Key").first() {
+        Some(key) => key.trim().to_string(),
+        None => return Err(Response::with_status("HTTP/1.1 400 BAD REQUEST", String::from("No Sec-WebSocket-Key was sent"))),
+    };
+    match STANDARD.decode(&key) {
+        Ok(decoded) if decoded.len() == 16 => Ok(accept_key(&key)),
+        _ => Err(Response::with_status("HTTP/1.1 400 BAD REQUEST", String::from("Sec-WebSocket-Key is not valid"))),
+    }
+}
+
+/// Tells a client the path only works as a WebSocket
+pub fn upgrade_required() -> Response {
+    Response::with_status("HTTP/1.1 426 UPGRADE REQUIRED", String::from("Connect with a WebSocket"))
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Version", "13")
+}
+
+/// Makes a frame to send to a client, server frames aren't masked
+pub fn encode_frame(opcode : u8, payload : &[u8]) -> Vec<u8> {
+    let mut frame : Vec<u8> = vec![0x80 | opcode];
+
+    //Short lengths fit in the second byte, longer ones follow it
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Reads a frame from the start of the buffer
+/// Returns the frame and its size, or None if the whole frame hasn't arrived yet
+/// Errors carry the close code to send back
+pub fn decode_frame(buffer : &[u8]) -> Result<Option<(Frame, usize)>, u16> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    let opcode = buffer[0] & 0x0F;
+    let finished = buffer[0] & 0x80 != 0;
+    let masked = buffer[1] & 0x80 != 0;
+
+    //Clients have to mask every frame
+    if !masked {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+
+    //Gets the payload length, which might take more bytes
+    let (length, mut pos) = match buffer[1] & 0x7F {
+        126 => match buffer.get(2..4) {
+            Some(bytes) => (u16::from_be_bytes([bytes[0], bytes[1]]) as usize, 4),
+            None => return Ok(None),
+        },
+        127 => match buffer.get(2..10) {
+            Some(bytes) => {
+                let mut length = [0u8; 8];
+                length.copy_from_slice(bytes);
+                (u64::from_be_bytes(length).min(usize::MAX as u64) as usize, 10)
+            },
+            None => return Ok(None),
+        },
+        length => (length as usize, 2),
+    };
+
+    //Control frames are short and never split
+    if opcode >= OPCODE_CLOSE && (length > 125 || !finished) {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+    if length > MAX_CLIENT_FRAME {
+        return Err(CLOSE_TOO_BIG);
+    }
+
+    let mask = match buffer.get(pos..pos + 4) {
+        Some(mask) => [mask[0], mask[1], mask[2], mask[3]],
+        None => return Ok(None),
+    };
+    pos += 4;
+
+    let payload = match buffer.get(pos..pos + length) {
+        Some(payload) => payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]).collect(),
+        None => return Ok(None),
+    };
+
+    Ok(Some((Frame { opcode, payload }, pos + length)))
+}
+
+/// A client listening to the price stream
+struct Subscriber {
+    stream : TcpStream,
+    /// Part of a frame the client hasn't finished sending
+    buffer : Vec<u8>,
+}
+
+impl Subscriber {
+    /// Sends a frame, false if the client is gone
+    fn send(&mut self, frame : &[u8]) -> bool {
+        self.stream.write_all(frame).and_then(|_| self.stream.flush()).is_ok()
+    }
+
+    /// Answers whatever the client sent since the last check
+    /// False once the client closed or broke the protocol
+    fn read_incoming(&mut self) -> bool {
+        //Only takes what already arrived, the market never waits on a client
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+
+        let mut open = true;
+        let mut chunk = [0u8; 1024];
+        //A client flooding the socket is only read a few frames at a time
+        while self.buffer.len() < MAX_CLIENT_FRAME * 4 {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    open = false;
+                    break;
+                },
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_error) => {
+                    open = false;
+                    break;
+                },
+            }
+        }
+
+        if self.stream.set_nonblocking(false).is_err() || !open {
+            return false;
+        }
+
+        loop {
+            let (frame, size) = match decode_frame(&self.buffer) {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => return true,
+                Err(code) => {
+                    self.send(&encode_frame(OPCODE_CLOSE, &code.to_be_bytes()));
+                    return false;
+                },
+            };
+            self.buffer.drain(..size);
+
+            match frame.opcode {
+                //Sends the close back, which ends the connection
+                OPCODE_CLOSE => {
+                    let code = frame.payload.get(..2).unwrap_or(&[]);
+                    self.send(&encode_frame(OPCODE_CLOSE, code));
+                    return false;
+                },
+                OPCODE_PING => {
+                    if !self.send(&encode_frame(OPCODE_PONG, &frame.payload)) {
+                        return false;
+                    }
+                },
+                //Messages from the client don't mean anything to the stream
+                OPCODE_PONG | OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => (),
+                _ => {
+                    self.send(&encode_frame(OPCODE_CLOSE, &CLOSE_PROTOCOL_ERROR.to_be_bytes()));
+                    return false;
+                },
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TickPrice<'a> {
+    id : usize,
+    name : &'a String,
+    ticker : &'a String,
+    price : f32,
+}
+
+#[derive(Serialize)]
+struct TickRankChange<'a> {
+    name : &'a String,
+    previous_rank : Option<usize>,
+    rank : usize,
+    value : f32,
+}
+
+/// The message sent to every subscriber after the market updates
+#[derive(Serialize)]
+struct TickMessage<'a> {
+    r#type : &'static str,
+    tick : u64,
+    prices : Vec<TickPrice<'a>>,
+    leaderboard : Vec<TickRankChange<'a>>,
+}
+
+/// Pushes every market tick to the connected WebSocket clients
+pub struct PriceStream {
+    subscribers : Vec<Subscriber>,
+    max_subscribers : usize,
+    /// The amount of ticks since the server started
+    tick : u64,
+}
+
+impl PriceStream {
+    /// Makes a stream that takes up to max_subscribers clients
+    pub fn new(max_subscribers : usize) -> PriceStream {
+        PriceStream { subscribers : Vec::new(), max_subscribers, tick : 0 }
+    }
+
+    /// Checks if another client can connect
+    pub fn is_full(&self) -> bool {
+        self.subscribers.len() >= self.max_subscribers
+    }
+
+    /// Counts a new tick and makes its message
+    /// Made while the managers are locked, but sent after they are let go
+    pub fn next_tick(&mut self, company_manager : &CompanyManager, changes : &[RankChange]) -> Result<String, String> {
+        self.tick += 1;
+
+        let message = TickMessage {
+            r#type : "tick",
+            tick : self.tick,
+            prices : company_manager.companies()
+                .iter()
+                .map(|company| TickPrice { id : company.id().value(), name : company.name(), ticker : company.ticker(), price : company.stock_price() })
+                .collect(),
+            leaderboard : changes
+                .iter()
+                .map(|change| TickRankChange { name : &change.name, previous_rank : change.previous_rank, rank : change.rank, value : change.value })
+                .collect(),
+        };
+
+        serde_json::to_string(&message).map_err(|error| error.to_string())
+    }
+
+    /// Sends a text message to every client, dropping the ones that left
+    pub fn broadcast(&mut self, message : &str) {
+        let frame = encode_frame(OPCODE_TEXT, message.as_bytes());
+        self.subscribers.retain_mut(|subscriber| subscriber.read_incoming() && subscriber.send(&frame));
+    }
+
+    /// Adds a client whose handshake finished
+    fn subscribe(&mut self, stream : TcpStream) {
+        self.subscribers.push(Subscriber { stream, buffer : Vec::new() });
+    }
+}
+
+/// Turns the connection into a WebSocket and adds it to the price stream
+/// The worker is free again once this returns, the stream keeps the connection
+pub fn accept(mut stream : TcpStream, request : &Request, price_stream : &std::sync::RwLock<PriceStream>) -> Result<(), String> {
+    let accept = match check_handshake(request) {
+        Ok(accept) => accept,
+        Err(response) => return response.write_to(&mut stream),
+    };
+
+    let mut price_stream = match price_stream.write() {
+        Ok(price_stream) => price_stream,
+        Err(error) => return Err(error.to_string()),
+    };
+
+    if price_stream.is_full() {
+        return Response::with_status("HTTP/1.1 503 SERVICE UNAVAILABLE", String::from("Too many clients are streaming prices"))
+            .with_header("Retry-After", "30")
+            .write_to(&mut stream);
+    }
+
+    //Switching protocols can't have a body, so it's written by hand
+    let handshake = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept);
+
+    let ready = stream.set_read_timeout(None)
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .and_then(|_| stream.write_all(handshake.as_bytes()))
+        .and_then(|_| stream.flush());
+    match ready {
+        Ok(_) => {
+            price_stream.subscribe(stream);
+            Ok(())
+        },
+        Err(error) => Err(error.to_string()),
+    }
+}
@@ -0,0 +1,86 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A fixed amount of threads working through a bounded queue of jobs
+/// When the queue is full new jobs are handed back instead of waiting
+pub struct WorkerPool<T : Send + 'static> {
+    /// Taken when the pool is dropped, which stops the workers
+    sender : Option<SyncSender<T>>,
+    workers : Vec<JoinHandle<()>>,
+}
+
+impl<T : Send + 'static> WorkerPool<T> {
+    /// Starts the workers, each job is passed to the handler on one of them
+    /// Up to queue_size jobs can wait for a free worker
+    pub fn new<F>(workers : usize, queue_size : usize, handler : F) -> Result<WorkerPool<T>, String>
+    where F : Fn(T) + Send + Sync + 'static {
+        if workers == 0 {
+            return Err(String::from("A worker pool needs at least one worker"));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<T>(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let mut handles : Vec<JoinHandle<()>> = Vec::new();
+        for number in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let handler = Arc::clone(&handler);
+
+            let spawned = thread::Builder::new()
+                .name(format!("worker {}", number))
+                .spawn(move || loop {
+                    //Only one idle worker waits on the queue at a time, the lock is let go before the job runs
+                    let job = match receiver.lock() {
+                        Ok(receiver) => match receiver.recv() {
+                            Ok(job) => job,
+                            //The pool was dropped
+                            Err(_error) => return,
+                        },
+                        Err(_error) => return,
+                    };
+
+                    //A job that panics shouldn't take the worker down with it
+                    if panic::catch_unwind(AssertUnwindSafe(|| handler(job))).is_err() {
+                        println!("A job panicked on worker {}", number);
+                    }
+                });
+
+            match spawned {
+                Ok(handle) => handles.push(handle),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+
+        Ok(WorkerPool { sender : Some(sender), workers : handles })
+    }
+
+    /// Queues a job for the workers
+    /// The job is handed back if the queue is full
+    pub fn try_execute(&self, job : T) -> Result<(), T> {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return Err(job),
+        };
+
+        match sender.try_send(job) {
+            Ok(_) => Ok(()),
+            Err(TrySendError::Full(job)) => Err(job),
+            Err(TrySendError::Disconnected(job)) => Err(job),
+        }
+    }
+}
+
+/// Finishes the queued jobs and stops the workers
+impl<T : Send + 'static> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        //Closing the queue makes each worker return once it is empty
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
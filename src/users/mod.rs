@@ -1,4 +1,5 @@
 pub mod user_manager;
 pub mod user;
 pub mod password;
-pub mod ranking;
\ No newline at end of file
+pub mod ranking;
+pub mod trade_history;
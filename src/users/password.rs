@@ -1,53 +1,145 @@
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
 
+use crate::data::data_saving::{SaveData, LoadData};
 
+/// Marks a password hashed with PBKDF2-HMAC-SHA256 in the save files
+const HASH_NAME : &str = "pbkdf2-sha256";
+/// How many times the password is hashed, slows down guessing
+#[cfg(not(test))]
+const HASH_ROUNDS : u32 = 600_000;
+/// Tests run unoptimized, so they use far fewer rounds
+#[cfg(test)]
+const HASH_ROUNDS : u32 = 1_000;
+/// Saved hashes with fewer rounds are refused, they would be far too quick to guess
+const MIN_HASH_ROUNDS : u32 = 1_000;
+const SALT_LENGTH : usize = 16;
+const HASH_LENGTH : usize = 32;
 
+/// Text passwords must be between these lengths
+const MIN_LENGTH : usize = 8;
+const MAX_LENGTH : usize = 128;
+/// The arrow keys that can be entered on the key pad
+const ARROW_KEYS : [&str; 4] = ["left", "right", "up", "down"];
+/// How many arrow keys make an arrow key password
+const ARROW_KEY_LENGTH : usize = 6;
 
-
-/// The Password struct stores a password (Cannot be changed once created)
-/// A Password is a combination of 4 of the arrow keys 
-#[derive(Clone, Copy)]
+/// The Password struct stores a salted hash of a password (Cannot be changed once created)
+/// The password itself is never stored
+#[derive(Clone)]
 pub struct Password {
-    keycodes : [u16; 6],
+    rounds : u32,
+    salt : [u8; SALT_LENGTH],
+    hash : [u8; HASH_LENGTH],
 }
 
 /// Default password functions
 impl Password {
-    /// Creates a new password from a set of keycodes
-    pub fn new(keys : [u16; 6]) -> Password {
-        Password { keycodes: keys }
+    /// Hashes a password with a new random salt
+    pub fn new(password : &str) -> Password {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+
+        Password { rounds : HASH_ROUNDS, salt, hash : hash_password(password, &salt, HASH_ROUNDS) }
     }
 
-    /// Parses a password from text
-    pub fn from_text(password : &String) -> Result<Password, String> {
-        //Defaults all keycodes to 0
-        let mut keycodes : [u16; 6] = [0 ; 6];
-
-        //Splits the password by '-'
-        let split_password : Vec<&str> = password.split("-").collect();
-        
-        //Ensures the length is 6
-        if split_password.len() != 6 { return Err(String::from("Length of password is not 6!"))}
-
-        let mut pos : usize = 0;
-        //Loops through each bit
-        for code in split_password {
-            match code {
-                "left" => keycodes[pos] = 1,
-                "right" => keycodes[pos] = 2,
-                "up" => keycodes[pos] = 3,
-                "down" => keycodes[pos] = 4,
-                _ => return Err(format!("{} is not a valid password entry!", code)),
+    /// Checks a password entered by a user and hashes it
+    /// Accepts any text password, or an arrow key password from the key pad ("left-right-up-down-up-up")
+    pub fn from_text(password : &str) -> Result<Password, String> {
+        //Arrow key passwords are entered one key at a time
+        let split_password : Vec<&str> = password.split('-').collect();
+        if split_password.iter().all(|code| ARROW_KEYS.contains(code)) {
+            if split_password.len() != ARROW_KEY_LENGTH {
+                return Err(format!("Arrow key passwords must be {} keys long!", ARROW_KEY_LENGTH));
             }
-            //adds to the size
-            pos += 1;
+            return Ok(Self::new(password));
+        }
+
+        //Everything else is a text password
+        let length = password.chars().count();
+        if length < MIN_LENGTH { return Err(format!("Password must be at least {} characters long!", MIN_LENGTH)); }
+        if length > MAX_LENGTH { return Err(format!("Password must be at most {} characters long!", MAX_LENGTH)); }
+
+        Ok(Self::new(password))
+    }
+
+    /// Checks if the entered password matches
+    pub fn verify(&self, password : &str) -> bool {
+        let attempt = hash_password(password, &self.salt, self.rounds);
+
+        //Looks at every byte, so the time taken doesn't tell how much of the hash matched
+        let difference = attempt
+            .iter()
+            .zip(self.hash.iter())
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+
+        difference == 0
+    }
+}
+
+/// Saves the password as "pbkdf2-sha256$rounds$salt$hash"
+impl SaveData for Password {
+    fn get_data(&self) -> String {
+        format!("{}${}${}${}", HASH_NAME, self.rounds, to_hex(&self.salt), to_hex(&self.hash))
+    }
+}
+
+/// Loads the password from its saved hash
+impl LoadData for Password {
+    fn load_data(data : &str) -> Result<Password, String> {
+        let parts : Vec<&str> = data.split('$').collect();
+        if parts.len() != 4 || parts[0] != HASH_NAME {
+            return Err(String::from("Password is not a saved hash"));
+        }
+
+        let rounds : u32 = match parts[1].parse() {
+            Ok(rounds) => rounds,
+            Err(_error) => return Err(format!("Could not parse password rounds from '{}'", parts[1])),
         };
+        if rounds < MIN_HASH_ROUNDS {
+            return Err(format!("Password hashes need at least {} rounds, found {}", MIN_HASH_ROUNDS, rounds));
+        }
+
+        let mut salt = [0u8; SALT_LENGTH];
+        if let Err(error) = from_hex(parts[2], &mut salt) {
+            return Err(format!("Password salt: {}", error));
+        }
+
+        let mut hash = [0u8; HASH_LENGTH];
+        if let Err(error) = from_hex(parts[3], &mut hash) {
+            return Err(format!("Password hash: {}", error));
+        }
+
+        Ok(Password { rounds, salt, hash })
+    }
+}
+
+/// Hashes the password with PBKDF2-HMAC-SHA256
+fn hash_password(password : &str, salt : &[u8], rounds : u32) -> [u8; HASH_LENGTH] {
+    let mut hash = [0u8; HASH_LENGTH];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, &mut hash);
+    hash
+}
 
-        //Create the password from the text
-        Ok(Self::new(keycodes))
-    } 
+/// Writes the bytes as lower case hex
+fn to_hex(bytes : &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
 
-    /// Compares two passwords against one another
-    pub fn compare(&self, other : Password) -> bool {
-        self.keycodes == other.keycodes
+/// Reads hex into the bytes, the text must fill them exactly
+fn from_hex(text : &str, bytes : &mut [u8]) -> Result<(), String> {
+    if !text.chars().all(|digit| digit.is_ascii_hexdigit()) || text.len() != bytes.len() * 2 {
+        return Err(format!("Expected {} hex digits, found '{}'", bytes.len() * 2, text));
     }
-}
\ No newline at end of file
+
+    for (pos, byte) in bytes.iter_mut().enumerate() {
+        *byte = match u8::from_str_radix(&text[pos * 2..pos * 2 + 2], 16) {
+            Ok(byte) => byte,
+            Err(_error) => return Err(format!("'{}' is not hex", text)),
+        };
+    }
+
+    Ok(())
+}
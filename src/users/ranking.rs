@@ -2,13 +2,16 @@
 
 use std::cmp::Ordering;
 
-use crate::{companies::company_manager::CompanyManager, data::data_saving::SaveData};
+use crate::{companies::company_manager::CompanyManager, data::data_saving::{SaveData, LoadData, Record}};
+use crate::id::ID;
 use super::{user::User, user_manager::UserManager};
 
 
 /// Holds the ranking of a user
 #[derive(Clone, Debug)]
-struct Rank {
+pub struct Rank {
+    /// None for ranks saved before they held the user
+    user_id : Option<ID>,
     name : String,
     value : f32,
 }
@@ -17,14 +20,24 @@ struct Rank {
 /// Default Rank functions
 impl Rank {
     /// Makes a new rank
-    fn new(name : String, value : f32) -> Rank {
-        Rank { name, value }
+    fn new(user_id : Option<ID>, name : String, value : f32) -> Rank {
+        Rank { user_id, name, value }
+    }
+
+    /// Gets the display name of the ranked user
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Gets the total value of the ranked user
+    pub fn value(&self) -> f32 {
+        self.value
     }
 
     /// Makes a rank from a user and the company manager
     pub fn rank_from_user(user : &User, company_manager : &CompanyManager) -> Result<Rank, String> {
         match user.value(company_manager) {
-            Ok(value) => Ok(Self::new(user.display_name().clone(), value)),
+            Ok(value) => Ok(Self::new(Some(user.id()), user.display_name().clone(), value)),
             Err(error) => Err(error),
         }
     }
@@ -57,6 +70,60 @@ impl SaveData for Rank {
     fn get_data(&self) -> String {
         format!("{}_{}", self.name, self.value)
     }
+
+    /// Saves the rank as a record so names can hold '_'
+    fn save_data(&self) -> String {
+        let record = Record::new("rank")
+            .with("name", &self.name)
+            .with("value", self.value);
+
+        match self.user_id {
+            Some(user_id) => record.with("user_id", user_id).get_data(),
+            None => record.get_data(),
+        }
+    }
+}
+
+/// Loads a rank from its saved data
+impl LoadData for Rank {
+    fn load_data(data : &str) -> Result<Rank, String> {
+        let record = match Record::load_kind(data, "rank") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let value : f32 = match record.get("value") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let name : String = match record.get("name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        //Older saves don't have the user
+        let user_id = match record.has("user_id") {
+            true => match record.get("user_id") {
+                Ok(value) => Some(ID::load(value)),
+                Err(error) => return Err(error),
+            },
+            false => None,
+        };
+
+        Ok(Self::new(user_id, name, value))
+    }
+}
+
+/// How a users place on the leaderboard moved between two rankings
+#[derive(Clone, Debug)]
+pub struct RankChange {
+    /// None if the rank doesn't know its user
+    pub user_id : Option<ID>,
+    pub name : String,
+    /// None if the user wasn't ranked before
+    pub previous_rank : Option<usize>,
+    pub rank : usize,
+    pub value : f32,
 }
 
 /// Ranks all users against one-another to determine
@@ -96,6 +163,35 @@ impl Ranker {
         Ok(())
     }
 
+    /// Gets every rank, best first
+    pub fn ranks(&self) -> &Vec<Rank> {
+        &self.order
+    }
+
+    /// Gets the users whose place or value changed since the previous ranking
+    /// Ranks start at 1, best first
+    pub fn changes_since(&self, previous : &Ranker) -> Vec<RankChange> {
+        let mut changes : Vec<RankChange> = Vec::new();
+
+        for (pos, rank) in self.order.iter().enumerate() {
+            //Display names are unique, so they find the user in the old ranking
+            let old = previous.order.iter().enumerate().find(|(_pos, old_rank)| old_rank.name == rank.name);
+
+            match old {
+                Some((old_pos, old_rank)) if old_pos == pos && old_rank.value == rank.value => continue,
+                _ => changes.push(RankChange {
+                    user_id : rank.user_id,
+                    name : rank.name.clone(),
+                    previous_rank : old.map(|(old_pos, _old_rank)| old_pos + 1),
+                    rank : pos + 1,
+                    value : rank.value,
+                }),
+            }
+        }
+
+        changes
+    }
+
     /// Gets the ranks in string to send over the server
     /// Gets the ranks from the range specified
     pub fn get_data_range(&self, mut range : std::ops::Range<usize>) -> Result<String, String> {
@@ -115,7 +211,7 @@ impl Ranker {
         };
 
         //Removes the extra data
-        if data.len() != 0 { data.pop(); }
+        if !data.is_empty() { data.pop(); }
 
         Ok(data)
     }
@@ -143,4 +239,57 @@ impl RankerHistory {
     pub fn get_recent(&self) -> Option<&Ranker> {
         self.history.last()
     }
-}
\ No newline at end of file
+}
+
+/// Saves the history, every ranker starts with a "ranker" line
+impl SaveData for RankerHistory {
+    /// Gets the most recent ranking
+    fn get_data(&self) -> String {
+        match self.get_recent() {
+            Some(ranker) => ranker.get_data_range(0..ranker.order.len()).unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    /// Saves every ranking in the history
+    fn save_data(&self) -> String {
+        let mut lines : Vec<String> = Vec::new();
+
+        for ranker in &self.history {
+            lines.push(String::from("ranker"));
+            for rank in &ranker.order {
+                lines.push(rank.save_data());
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Loads the history of the rankers
+impl LoadData for RankerHistory {
+    fn load_data(data : &str) -> Result<RankerHistory, String> {
+        let mut ranker_history = RankerHistory::new();
+
+        for line in data.lines().filter(|line| !line.is_empty()) {
+            //Starts a new ranker
+            if line == "ranker" {
+                ranker_history.add(Ranker::new());
+                continue;
+            }
+
+            //Adds the rank to the newest ranker
+            let ranker = match ranker_history.history.last_mut() {
+                Some(ranker) => ranker,
+                None => return Err(format!("Rank found before any ranker: '{}'", line)),
+            };
+
+            match Rank::load_data(line) {
+                Ok(rank) => ranker.order.push(rank),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(ranker_history)
+    }
+}
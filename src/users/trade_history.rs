@@ -0,0 +1,213 @@
+use serde::Serialize;
+
+use crate::companies::order_book::TradeSide;
+use crate::companies::protection::ProtectionKind;
+use crate::data::data_saving::{SaveData, LoadData, Record};
+use crate::id::ID;
+
+/// The most trades a user keeps, older ones are forgotten
+const MAX_TRADES : usize = 100;
+
+/// What made a trade happen
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeKind {
+    /// Bought or sold straight away
+    Market,
+    /// A resting limit order filled
+    Limit,
+    /// A stop-loss on a holding triggered
+    StopLoss,
+    /// A trailing stop on a holding triggered
+    TrailingStop,
+    /// A take-profit on a holding triggered
+    TakeProfit,
+}
+
+impl TradeKind {
+    /// Gets the kind as it is saved
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeKind::Market => "market",
+            TradeKind::Limit => "limit",
+            TradeKind::StopLoss => "stop_loss",
+            TradeKind::TrailingStop => "trailing_stop",
+            TradeKind::TakeProfit => "take_profit",
+        }
+    }
+
+    /// Reads a saved kind
+    pub fn parse(text : &str) -> Result<TradeKind, String> {
+        match text {
+            "market" => Ok(TradeKind::Market),
+            "limit" => Ok(TradeKind::Limit),
+            other => match ProtectionKind::parse(other) {
+                Ok(kind) => Ok(TradeKind::from(kind)),
+                Err(_error) => Err(format!("Unknown trade kind '{}'", other)),
+            },
+        }
+    }
+}
+
+impl From<ProtectionKind> for TradeKind {
+    fn from(kind : ProtectionKind) -> TradeKind {
+        match kind {
+            ProtectionKind::StopLoss => TradeKind::StopLoss,
+            ProtectionKind::TrailingStop => TradeKind::TrailingStop,
+            ProtectionKind::TakeProfit => TradeKind::TakeProfit,
+        }
+    }
+}
+
+/// A trade a user made
+#[derive(Clone, Debug)]
+pub struct TradeRecord {
+    kind : TradeKind,
+    side : TradeSide,
+    company_id : ID,
+    company_name : String,
+    amount : usize,
+    price : f32,
+}
+
+impl TradeRecord {
+    /// Makes the record of a trade
+    pub fn new(kind : TradeKind, side : TradeSide, company_id : ID, company_name : String, amount : usize, price : f32) -> TradeRecord {
+        TradeRecord { kind, side, company_id, company_name, amount, price }
+    }
+
+    /// Gets what made the trade happen
+    pub fn kind(&self) -> TradeKind {
+        self.kind
+    }
+
+    /// Gets if the user bought or sold
+    pub fn side(&self) -> TradeSide {
+        self.side
+    }
+
+    /// Gets the ID of the company traded
+    pub fn company_id(&self) -> ID {
+        self.company_id
+    }
+
+    /// Gets the name of the company traded
+    pub fn company_name(&self) -> &String {
+        &self.company_name
+    }
+
+    /// Gets the amount of shares traded
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Gets the price each share traded at
+    pub fn price(&self) -> f32 {
+        self.price
+    }
+}
+
+/// Saves the trade as a single record
+impl SaveData for TradeRecord {
+    fn get_data(&self) -> String {
+        Record::new("trade")
+            .with("kind", self.kind().as_str())
+            .with("side", self.side().as_str())
+            .with("company_id", self.company_id())
+            .with("company_name", self.company_name())
+            .with("amount", self.amount())
+            .with("price", self.price())
+            .get_data()
+    }
+}
+
+/// Loads a trade from its record
+impl LoadData for TradeRecord {
+    fn load_data(data : &str) -> Result<TradeRecord, String> {
+        let record = match Record::load_kind(data, "trade") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let kind = match record.get::<String>("kind") {
+            Ok(kind_text) => match TradeKind::parse(&kind_text) {
+                Ok(kind) => kind,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let side = match record.get::<String>("side") {
+            Ok(side_text) => match TradeSide::parse(&side_text) {
+                Ok(side) => side,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let company_id : usize = match record.get("company_id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let company_name : String = match record.get("company_name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let amount : usize = match record.get("amount") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let price : f32 = match record.get("price") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        Ok(TradeRecord { kind, side, company_id : ID::load(company_id), company_name, amount, price })
+    }
+}
+
+/// The latest trades of a user, oldest first
+pub struct TradeHistory {
+    trades : Vec<TradeRecord>,
+}
+
+impl TradeHistory {
+    /// Makes an empty history
+    pub fn new() -> TradeHistory {
+        TradeHistory { trades : Vec::new() }
+    }
+
+    /// Gets every remembered trade
+    pub fn trades(&self) -> &Vec<TradeRecord> {
+        &self.trades
+    }
+
+    /// Adds a trade, forgetting the oldest once it is full
+    pub fn add(&mut self, trade : TradeRecord) {
+        if self.trades.len() >= MAX_TRADES {
+            self.trades.remove(0);
+        }
+        self.trades.push(trade);
+    }
+
+    /// Forgets every trade
+    pub fn clear(&mut self) {
+        self.trades.clear();
+    }
+}
+
+/// Saves every trade on its own line
+impl SaveData for TradeHistory {
+    fn get_data(&self) -> String {
+        let lines : Vec<String> = self.trades
+            .iter()
+            .map(|trade| trade.get_data())
+            .collect();
+
+        lines.join("\n")
+    }
+}
+
+/// A trade that just happened, and the user that made it
+pub struct Fill {
+    pub user_id : ID,
+    pub trade : TradeRecord,
+}
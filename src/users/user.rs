@@ -4,8 +4,11 @@
 use crate::companies::stock::{StockWallet, Stock};
 use crate::CompanyManager;
 use crate::id::ID;
-use crate::SaveData;
+use crate::data::data_saving::{SaveData, LoadData, Record};
 use crate::users::password::Password;
+use crate::companies::order_book::{Order, TradeSide};
+use crate::companies::protection::Protection;
+use crate::users::trade_history::{TradeHistory, TradeRecord};
 
 /// A User can use their money to purchase stock in a company
 pub struct User {
@@ -14,7 +17,10 @@ pub struct User {
     display_name : String,
     password : Password,   
     money : f32,
+    /// Money held back for open buy orders, still part of the users money
+    reserved_money : f32,
     stock_wallet : StockWallet,
+    trade_history : TradeHistory,
 }
 
 /// Default User functions
@@ -27,7 +33,9 @@ impl User {
             display_name, 
             password,
             money : 1000.0, 
+            reserved_money : 0.0,
             stock_wallet : StockWallet::new(),
+            trade_history : TradeHistory::new(),
         }
     }
     
@@ -51,6 +59,16 @@ impl User {
         self.money
     }
 
+    /// Gets the money held back for open buy orders
+    pub fn reserved_money(&self) -> f32 {
+        self.reserved_money
+    }
+
+    /// Gets the money that can be spent right now
+    pub fn available_money(&self) -> f32 {
+        self.money - self.reserved_money
+    }
+
     /// Gets the total value of a user
     pub fn value(&self, company_manager : &CompanyManager) -> Result<f32, String> {
         match self.wallet().total_value(company_manager) {
@@ -64,28 +82,76 @@ impl User {
         &self.stock_wallet
     }
 
+    /// Gets the latest trades of the user
+    pub fn trade_history(&self) -> &TradeHistory {
+        &self.trade_history
+    }
+
+    /// Adds a trade to the users history
+    pub fn record_trade(&mut self, trade : TradeRecord) {
+        self.trade_history.add(trade);
+    }
+
     /// Gets the amount of stock the user has
     pub fn stock_amount(&self) -> usize {
         self.wallet().stock_amount()
     }
 
-    /// Attempts to enter the password to the User
-    /// True on success, false if password is wrong
-    pub fn try_password(&self, password : Password) -> bool {
-        return self.password.compare(password);
+    /// Gets the salted hash of the users password
+    pub fn password(&self) -> &Password {
+        &self.password
     }
 
     //Resets a users earnings
     pub fn reset(&mut self) {
         self.money = 1000.0;
+        self.reserved_money = 0.0;
         self.stock_wallet.reset();
+        self.trade_history.clear();
+    }
+
+    /// Holds back what an open order needs, so it can't be spent or sold by anything else
+    pub fn reserve_for_order(&mut self, order : &Order) -> Result<(), String> {
+        match order.side() {
+            TradeSide::Buy => {
+                if self.available_money() < order.reserved_money() {
+                    return Err(format!("{} does not have enough money to place a buy order of {}$", self.display_name(), order.reserved_money()));
+                }
+                self.reserved_money += order.reserved_money();
+                Ok(())
+            },
+            TradeSide::Sell => self.stock_wallet.reserve_stock(order.company_id(), order.amount()),
+        }
+    }
+
+    /// Lets go of what an order held back, once it is cancelled or about to fill
+    pub fn release_order(&mut self, order : &Order) {
+        match order.side() {
+            TradeSide::Buy => self.reserved_money = (self.reserved_money - order.reserved_money()).max(0.0),
+            TradeSide::Sell => self.stock_wallet.release_stock(order.company_id(), order.amount()),
+        }
+    }
+
+    /// Attaches a stop-loss, trailing-stop or take-profit to the users shares
+    pub fn protect(&mut self, protection : Protection) -> Result<(), String> {
+        self.stock_wallet.protect(protection)
+    }
+
+    /// Removes a protection from the users shares
+    pub fn cancel_protection(&mut self, protection_id : ID) -> Result<Protection, String> {
+        self.stock_wallet.cancel_protection(protection_id)
+    }
+
+    /// Moves the trailing stops with the current prices, then takes out every protection they trigger
+    pub fn take_triggered_protections(&mut self, company_manager : &CompanyManager) -> Vec<Protection> {
+        self.stock_wallet.trigger_protections(company_manager)
     }
 
     /// Buys a stock
     pub fn buy_stock(&mut self, stock : Stock, buy_amount : usize) -> Result<(), String> {
-        //Checks that the user has enough money to purchase the stock
+        //Checks that the user has enough money to purchase the stock, without the money held for orders
         let total_cost = stock.purchase_price() * buy_amount as f32;
-        if self.money() < total_cost { return Err(format!("{} does not have enough money to purchase {}", self, stock))}
+        if self.available_money() < total_cost { return Err(format!("{} does not have enough money to purchase {}", self, stock))}
 
         //Purchases the stock
         self.money -= total_cost;
@@ -95,17 +161,15 @@ impl User {
         Ok(())
     }
 
-    /// Sells stock stock from the user
-    pub fn sell_stock(&mut self, company_manager : &CompanyManager, company_id : ID, sell_amount : usize) -> Result<(), String> {
-        //Sells the stock from the stock wallet
-        let sell_result = self.stock_wallet.sell_stock(company_manager, company_id, sell_amount);
-
-        match sell_result {
-            Ok(sell_money) => self.money += sell_money,
-            Err(error) => return Err(error),
+    /// Sells stock from the user at a traded price
+    pub fn sell_stock_at(&mut self, company_id : ID, sell_amount : usize, price : f32) -> Result<(), String> {
+        match self.stock_wallet.sell_stock_at(company_id, sell_amount, price) {
+            Ok(sell_money) => {
+                self.money += sell_money;
+                Ok(())
+            },
+            Err(error) => Err(error),
         }
-
-        Ok(())
     }
 
     /// Gets all the stocks of the user into a string
@@ -132,6 +196,101 @@ impl SaveData for User {
         //Return the data
         data
     }
+
+    /// Saves everything needed to restore the user
+    /// The first line holds the user, the following lines hold the wallet
+    fn save_data(&self) -> String {
+        let mut data = Record::new("user")
+            .with("id", self.id())
+            .with("user_name", self.user_name())
+            .with("display_name", self.display_name())
+            .with("password", self.password.save_data())
+            .with("money", self.money())
+            .with("reserved_money", self.reserved_money())
+            .get_data();
+
+        //Adds the wallet on the next lines, then the trade history
+        for lines in [self.wallet().save_data(), self.trade_history().save_data()] {
+            if !lines.is_empty() {
+                data.push('\n');
+                data.push_str(&lines);
+            }
+        }
+
+        data
+    }
+}
+
+/// Loads a user from its saved data
+impl LoadData for User {
+    fn load_data(data : &str) -> Result<User, String> {
+        //Splits the user line from the wallet
+        let (user_line, wallet_data) = match data.split_once('\n') {
+            Some((user_line, wallet_data)) => (user_line, wallet_data),
+            None => (data, ""),
+        };
+
+        let record = match Record::load_kind(user_line, "user") {
+            Ok(record) => record,
+            Err(error) => return Err(error),
+        };
+
+        let id : usize = match record.get("id") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let user_name : String = match record.get("user_name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let display_name : String = match record.get("display_name") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        let password = match record.get::<String>("password") {
+            Ok(password_text) => match Password::load_data(&password_text) {
+                Ok(password) => password,
+                Err(error) => return Err(error),
+            },
+            Err(error) => return Err(error),
+        };
+        let money : f32 = match record.get("money") {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+        //Saves from before limit orders have nothing reserved
+        let reserved_money : f32 = match record.get_or("reserved_money", 0.0) {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        };
+
+        //Splits the trade history from the wallet
+        let (trade_lines, wallet_lines) : (Vec<&str>, Vec<&str>) = wallet_data.lines().partition(|line| line.starts_with("trade\t"));
+        let mut trade_history = TradeHistory::new();
+        for line in trade_lines {
+            match TradeRecord::load_data(line) {
+                Ok(trade) => trade_history.add(trade),
+                Err(error) => return Err(format!("User {}: {}", user_name, error)),
+            }
+        }
+
+        //Loads the users stocks
+        let stock_wallet = match StockWallet::load_data(&wallet_lines.join("\n")) {
+            Ok(stock_wallet) => stock_wallet,
+            Err(error) => return Err(format!("User {}: {}", user_name, error)),
+        };
+
+        Ok(User {
+            id : ID::load(id),
+            user_name,
+            display_name,
+            password,
+            money,
+            reserved_money,
+            stock_wallet,
+            trade_history,
+        })
+    }
 }
 
 
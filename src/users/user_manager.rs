@@ -1,13 +1,21 @@
 
 
+use std::collections::HashMap;
+
 use crate::User;
 use crate::users::password::Password;
-use crate::SaveData;
+use crate::data::data_saving::{SaveData, LoadData};
 use crate::ID;
 
-/// User Manager stores all the users in a Vectorp
+/// User Manager stores all the users in a Vector, indexed by ID, user name and display name
 pub struct UserManager {
     users : Vec<User>,
+    /// The position of each user, by the value of their ID
+    by_id : HashMap<usize, usize>,
+    /// The position of each user, by their user name
+    by_username : HashMap<String, usize>,
+    /// The position of each user, by their display name
+    by_display_name : HashMap<String, usize>,
 }
 
 /// Default User Manager functions
@@ -16,6 +24,9 @@ impl UserManager {
     pub fn new() -> UserManager {
         UserManager {
             users: Vec::new(),
+            by_id: HashMap::new(),
+            by_username: HashMap::new(),
+            by_display_name: HashMap::new(),
         }
     }
 
@@ -29,11 +40,46 @@ impl UserManager {
         let new_user = User::new(user_name, display_name, password);
         //Copies the ID for return
         let user_id = new_user.id();
-        self.users.push(new_user);
+        self.add_user(new_user);
+
+        Ok(user_id)
+    }
+
+    /// Adds a user that was loaded from a save
+    pub fn restore_user(&mut self, user : User) -> Result<ID, String> {
+        // Verifies that no user with the same user name or display name exist
+        if let Ok(_user) = self.get_user_by_username(user.user_name()) { return Err(format!("User with user name {} already exists!", user.user_name())); }
+        if let Ok(_user) = self.get_user_by_display_name(user.display_name()) { return Err(format!("User with display name {} already exists!", user.display_name())); }
+
+        let user_id = user.id();
+        self.add_user(user);
 
         Ok(user_id)
     }
 
+    /// Takes back a user, when making their account couldn't be finished
+    pub fn remove_user(&mut self, id : ID) {
+        let users = std::mem::take(&mut self.users);
+        self.by_id.clear();
+        self.by_username.clear();
+        self.by_display_name.clear();
+
+        //Every user after it moves down, so the indexes are made again
+        for user in users.into_iter().filter(|user| !user.id().equals(id)) {
+            self.add_user(user);
+        }
+    }
+
+    /// Adds a user to the end of the list and indexes it
+    /// If a name is somehow taken, the first user keeps it
+    fn add_user(&mut self, user : User) {
+        let pos = self.users.len();
+        self.by_id.entry(user.id().value()).or_insert(pos);
+        self.by_username.entry(user.user_name().clone()).or_insert(pos);
+        self.by_display_name.entry(user.display_name().clone()).or_insert(pos);
+        self.users.push(user);
+    }
+
     /// Resets all the users
     pub fn reset_users(&mut self) {
         //Loops through all the users
@@ -42,7 +88,7 @@ impl UserManager {
         }
     }
 
-    /// Getters
+    // Getters
 
     /// Gets the users from the User manager
     pub fn users(&self) -> &Vec<User> {
@@ -50,73 +96,41 @@ impl UserManager {
     }
 
     /// Gets the users mutably from the User manager
-    pub fn users_mut(&mut self) -> &mut Vec<User> {
+    /// Users can't be added or removed through it, so the indexes stay right
+    pub fn users_mut(&mut self) -> &mut [User] {
         &mut self.users
     }
 
     /// Gets a user by their User name
     pub fn get_user_by_username(&self, username : &String) -> Result<&User, String> {
-        let users = self.users();
-
-        //Filters for identical user names
-        let filtered : Vec<&User> = users
-            .iter()
-            .filter(|user| user.user_name().eq(username))
-            .collect();
-
-        //Checks that the lengths are correct
-        if filtered.len() == 0 { return Err(format!("No User with name {} found", username)); }
-        if filtered.len() != 1 { return Err(format!("Multiple Users with Name {} found", username)); }
-
-        //Returns the remaining user
-        Ok(filtered[0])
+        match self.by_username.get(username) {
+            Some(pos) => Ok(&self.users[*pos]),
+            None => Err(format!("No User with name {} found", username)),
+        }
     }
 
     /// Gets a user by their Display name
     pub fn get_user_by_display_name(&self, display_name : &String) -> Result<&User, String> {
-        let users = self.users();
-
-        //Filters for identical user names
-        let filtered : Vec<&User> = users
-            .iter()
-            .filter(|user| user.display_name().eq(display_name))
-            .collect();
-        
-        //Checks that the lengths are correct
-        if filtered.len() == 0 { return Err(format!("No User with name {} found", display_name)); }
-        if filtered.len() != 1 { return Err(format!("Multiple Users with Name {} found", display_name)); }
-
-        //Returns the remaining user
-        Ok(filtered[0])
+        match self.by_display_name.get(display_name) {
+            Some(pos) => Ok(&self.users[*pos]),
+            None => Err(format!("No User with name {} found", display_name)),
+        }
     }
 
     /// Gets a user by their ID
     pub fn get_user_by_id(&self, id : ID) -> Result<&User, String> {
-        let users = self.users();
-
-        //Filters the Users
-        let filtered : Vec<&User> = users
-            .iter()
-            .filter(|user| user.id().value() == id.value())
-            .collect();
-        
-        //Checks that the lengths are correct
-        if filtered.len() == 0 { return Err(format!("No User with id {} found", id.value())); }
-        if filtered.len() != 1 { return Err(format!("Multiple Users with ID {} found", id.value())); }
-
-        Ok(filtered[0])
+        match self.by_id.get(&id.value()) {
+            Some(pos) => Ok(&self.users[*pos]),
+            None => Err(format!("No User with id {} found", id.value())),
+        }
     }
 
     /// Gets the users by it's ID mutably
     pub fn get_user_by_id_mut(&mut self, id : ID) -> Result<&mut User, String> {
-        //Loops through every user until it find a user with a matching id
-        for user in self.users_mut() {
-            if user.id().equals(id) {
-                return Ok(user);
-            }
+        match self.by_id.get(&id.value()) {
+            Some(pos) => Ok(&mut self.users[*pos]),
+            None => Err(format!("No User with id {} found", id.value())),
         }
-
-        Err(format!("No User with id {} found", id.value()))
     }
 }
 
@@ -136,4 +150,43 @@ impl SaveData for UserManager {
         //Return the data
         data
     }
-}
\ No newline at end of file
+
+    /// Saves every user one after another
+    fn save_data(&self) -> String {
+        let users : Vec<String> = self.users()
+            .iter()
+            .map(|user| user.save_data())
+            .collect();
+
+        users.join("\n")
+    }
+}
+
+/// Loads the User Manager from the saved users
+impl LoadData for UserManager {
+    fn load_data(data : &str) -> Result<UserManager, String> {
+        let mut user_manager = UserManager::new();
+
+        //Groups the lines by user, every user starts with a "user" line
+        let mut user_blocks : Vec<String> = Vec::new();
+        for line in data.lines().filter(|line| !line.is_empty()) {
+            match user_blocks.last_mut() {
+                Some(block) if !line.starts_with("user\t") => {
+                    block.push('\n');
+                    block.push_str(line);
+                },
+                _ => user_blocks.push(line.to_string()),
+            }
+        }
+
+        //Loads each user
+        for block in user_blocks {
+            match User::load_data(&block) {
+                Ok(user) => user_manager.add_user(user),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(user_manager)
+    }
+}